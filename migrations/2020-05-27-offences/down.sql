@@ -0,0 +1 @@
+DROP TABLE IF EXISTS offences;
\ No newline at end of file
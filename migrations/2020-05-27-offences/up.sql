@@ -0,0 +1,18 @@
+CREATE TABLE offences
+(
+    user      bigint not null,
+    unix_time bigint not null,
+    foreign key (user)
+        references users (user)
+        on delete cascade
+);
+
+CREATE INDEX offence_freq
+    ON offences (user, unix_time);
+
+CREATE TRIGGER ensure_offence_user
+    BEFORE INSERT
+    ON offences
+BEGIN
+    INSERT OR IGNORE INTO users VALUES (NEW.user);
+END;
\ No newline at end of file
@@ -0,0 +1 @@
+DROP TABLE IF EXISTS permission_overrides;
\ No newline at end of file
@@ -0,0 +1,14 @@
+CREATE TABLE permission_overrides
+(
+    id          integer primary key,
+    command     text    not null,
+    subcommand  text    not null default '',
+    target_kind text    not null check (target_kind IN ('role', 'user')),
+    target      bigint  not null,
+    channel     bigint  not null default 0,
+    allow       boolean not null,
+    unique (command, subcommand, target_kind, target, channel)
+);
+
+CREATE INDEX permission_override_command
+    ON permission_overrides (command);
\ No newline at end of file
@@ -0,0 +1,8 @@
+INSERT OR REPLACE INTO guild_config
+SELECT 'admin_role', CAST(role AS text)
+FROM staff_roles
+WHERE tier = 2
+ORDER BY role
+LIMIT 1;
+
+DROP TABLE IF EXISTS staff_roles;
\ No newline at end of file
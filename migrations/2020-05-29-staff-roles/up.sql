@@ -0,0 +1,17 @@
+CREATE TABLE staff_roles
+(
+    role bigint  not null primary key,
+    tier integer not null check (tier IN (1, 2))
+);
+
+-- The admin role may have been stored as a role mention.
+INSERT INTO staff_roles
+SELECT role, 2
+FROM (SELECT CAST(REPLACE(REPLACE(value, '<@&', ''), '>', '') AS integer) AS role
+      FROM guild_config
+      WHERE key = 'admin_role')
+WHERE role > 0;
+
+DELETE
+FROM guild_config
+WHERE key = 'admin_role';
\ No newline at end of file
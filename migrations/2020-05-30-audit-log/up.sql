@@ -0,0 +1,12 @@
+CREATE TABLE audit_log
+(
+    id        integer primary key,
+    actor     bigint not null,
+    target    text   not null,
+    action    text   not null,
+    reason    text   not null default '',
+    unix_time bigint not null
+);
+
+CREATE INDEX audit_log_time
+    ON audit_log (unix_time);
\ No newline at end of file
@@ -0,0 +1,21 @@
+CREATE TABLE infractions
+(
+    id        integer primary key,
+    user      bigint not null,
+    moderator bigint not null,
+    reason    text   not null default '',
+    unix_time bigint not null,
+    foreign key (user)
+        references users (user)
+        on delete cascade
+);
+
+CREATE INDEX infraction_user
+    ON infractions (user, unix_time);
+
+CREATE TRIGGER ensure_infraction_user
+    BEFORE INSERT
+    ON infractions
+BEGIN
+    INSERT OR IGNORE INTO users VALUES (NEW.user);
+END;
\ No newline at end of file
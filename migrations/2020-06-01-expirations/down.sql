@@ -0,0 +1 @@
+DROP TABLE IF EXISTS expirations;
\ No newline at end of file
@@ -0,0 +1,11 @@
+CREATE TABLE expirations
+(
+    user      bigint not null,
+    kind      text   not null check (kind in ('mute', 'ban')),
+    role      bigint not null default 0,
+    unix_time bigint not null,
+    primary key (user, kind)
+);
+
+CREATE INDEX expiration_time
+    ON expirations (unix_time);
\ No newline at end of file
@@ -0,0 +1,26 @@
+CREATE TABLE expirations
+(
+    user      bigint not null,
+    kind      text   not null check (kind in ('mute', 'ban')),
+    role      bigint not null default 0,
+    unix_time bigint not null,
+    primary key (user, kind)
+);
+
+CREATE INDEX expiration_time
+    ON expirations (unix_time);
+
+INSERT OR REPLACE INTO expirations (user, kind, role, unix_time)
+SELECT CAST(substr(payload, 1, instr(payload, ' ') - 1) AS INTEGER),
+       'mute',
+       CAST(substr(payload, instr(payload, ' ') + 1) AS INTEGER),
+       run_at
+FROM jobs
+WHERE kind = 'unmute';
+
+INSERT OR REPLACE INTO expirations (user, kind, unix_time)
+SELECT CAST(payload AS INTEGER), 'ban', run_at
+FROM jobs
+WHERE kind = 'unban';
+
+DROP TABLE IF EXISTS jobs;
\ No newline at end of file
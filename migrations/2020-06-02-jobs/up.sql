@@ -0,0 +1,20 @@
+CREATE TABLE jobs
+(
+    id            integer primary key,
+    kind          text    not null,
+    payload       text    not null default '',
+    run_at        bigint  not null,
+    interval_secs bigint  not null default 0,
+    attempts      integer not null default 0
+);
+
+CREATE INDEX job_run_at
+    ON jobs (run_at);
+
+INSERT INTO jobs (kind, payload, run_at)
+SELECT CASE kind WHEN 'mute' THEN 'unmute' ELSE 'unban' END,
+       CASE kind WHEN 'mute' THEN user || ' ' || role ELSE CAST(user AS TEXT) END,
+       unix_time
+FROM expirations;
+
+DROP TABLE expirations;
\ No newline at end of file
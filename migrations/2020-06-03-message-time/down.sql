@@ -0,0 +1 @@
+DROP INDEX IF EXISTS message_time;
\ No newline at end of file
@@ -0,0 +1,2 @@
+CREATE INDEX message_time
+    ON messages (unix_time);
\ No newline at end of file
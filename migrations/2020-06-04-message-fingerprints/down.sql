@@ -0,0 +1,33 @@
+CREATE TABLE messages_old
+(
+    user      bigint not null,
+    message   bigint not null,
+    pressure  bigint not null default 0,
+    unix_time bigint not null,
+    primary key (user, message),
+    foreign key (user)
+        references users (user)
+        on delete cascade
+);
+
+INSERT INTO messages_old (user, message, pressure, unix_time)
+SELECT user, message, pressure, unix_time
+FROM messages;
+
+DROP TABLE messages;
+
+ALTER TABLE messages_old
+    RENAME TO messages;
+
+CREATE INDEX user_freq
+    ON messages (user, unix_time);
+
+CREATE INDEX message_time
+    ON messages (unix_time);
+
+CREATE TRIGGER ensure_message_user
+    BEFORE INSERT
+    ON messages
+BEGIN
+    INSERT OR IGNORE INTO users VALUES (NEW.user);
+END;
\ No newline at end of file
@@ -0,0 +1,8 @@
+ALTER TABLE messages
+    ADD COLUMN channel bigint not null default 0;
+
+ALTER TABLE messages
+    ADD COLUMN fingerprint bigint not null default 0;
+
+CREATE INDEX message_fingerprint
+    ON messages (fingerprint, unix_time);
\ No newline at end of file
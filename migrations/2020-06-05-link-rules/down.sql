@@ -0,0 +1 @@
+DROP TABLE IF EXISTS link_rules;
\ No newline at end of file
@@ -0,0 +1,5 @@
+CREATE TABLE link_rules
+(
+    domain text    primary key,
+    allow  boolean not null
+);
\ No newline at end of file
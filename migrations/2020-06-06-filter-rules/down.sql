@@ -0,0 +1,2 @@
+DROP TABLE IF EXISTS filter_exemptions;
+DROP TABLE IF EXISTS filter_rules;
\ No newline at end of file
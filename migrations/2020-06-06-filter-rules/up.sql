@@ -0,0 +1,18 @@
+CREATE TABLE filter_rules
+(
+    id      integer primary key,
+    pattern text    not null,
+    literal boolean not null,
+    action  text    not null check (action IN ('delete', 'warn', 'pressure', 'log'))
+);
+
+CREATE TABLE filter_exemptions
+(
+    rule   integer not null,
+    kind   text    not null check (kind IN ('channel', 'role')),
+    target bigint  not null,
+    primary key (rule, kind, target),
+    foreign key (rule)
+        references filter_rules (id)
+        on delete cascade
+);
\ No newline at end of file
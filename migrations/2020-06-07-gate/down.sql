@@ -0,0 +1 @@
+DROP TABLE IF EXISTS gate_pending;
\ No newline at end of file
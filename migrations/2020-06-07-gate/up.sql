@@ -0,0 +1,5 @@
+CREATE TABLE gate_pending
+(
+    user      bigint primary key,
+    unix_time bigint not null
+);
\ No newline at end of file
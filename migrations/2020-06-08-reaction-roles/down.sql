@@ -0,0 +1,2 @@
+DROP TABLE IF EXISTS reaction_roles;
+DROP TABLE IF EXISTS reaction_role_messages;
\ No newline at end of file
@@ -0,0 +1,18 @@
+CREATE TABLE reaction_role_messages
+(
+    message   bigint  primary key,
+    channel   bigint  not null,
+    exclusive boolean not null default false
+);
+
+CREATE TABLE reaction_roles
+(
+    message    bigint not null,
+    emoji      text   not null,
+    emoji_text text   not null,
+    role       bigint not null,
+    primary key (message, emoji),
+    foreign key (message)
+        references reaction_role_messages (message)
+        on delete cascade
+);
\ No newline at end of file
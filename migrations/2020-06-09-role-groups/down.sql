@@ -0,0 +1,10 @@
+CREATE TABLE joinable_roles_old
+(
+    role bigint primary key
+);
+
+INSERT INTO joinable_roles_old SELECT role FROM joinable_roles;
+DROP TABLE joinable_roles;
+ALTER TABLE joinable_roles_old RENAME TO joinable_roles;
+
+DROP TABLE IF EXISTS role_groups;
\ No newline at end of file
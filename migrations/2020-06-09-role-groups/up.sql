@@ -0,0 +1,10 @@
+CREATE TABLE role_groups
+(
+    name      text    primary key,
+    exclusive boolean not null default false,
+    max_roles integer check (max_roles is null or max_roles > 0)
+);
+
+ALTER TABLE joinable_roles ADD COLUMN role_group text
+    references role_groups (name)
+    on delete set null;
\ No newline at end of file
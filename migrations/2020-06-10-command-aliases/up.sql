@@ -0,0 +1,5 @@
+CREATE TABLE command_aliases
+(
+    alias     text primary key,
+    expansion text not null
+);
\ No newline at end of file
@@ -0,0 +1,23 @@
+DROP TRIGGER IF EXISTS ensure_command_prefix_not_empty;
+DROP TRIGGER IF EXISTS ensure_command_prefix_not_empty_upd;
+
+UPDATE guild_config
+SET value = '!'
+WHERE key = 'command_prefix'
+  AND length(value) <> 1;
+
+CREATE TRIGGER ensure_command_prefix_one_char
+    BEFORE INSERT
+    ON guild_config
+    WHEN NEW.key = 'command_prefix' AND length(NEW.value) <> 1
+BEGIN
+    SELECT RAISE(ABORT, 'New command prefix must be exactly 1 in length.');
+END;
+
+CREATE TRIGGER ensure_command_prefix_one_char_upd
+    BEFORE UPDATE
+    ON guild_config
+    WHEN NEW.key = 'command_prefix' AND length(NEW.value) <> 1
+BEGIN
+    SELECT RAISE(ABORT, 'New command prefix must be exactly 1 in length.');
+END;
\ No newline at end of file
@@ -0,0 +1,18 @@
+DROP TRIGGER IF EXISTS ensure_command_prefix_one_char;
+DROP TRIGGER IF EXISTS ensure_command_prefix_one_char_upd;
+
+CREATE TRIGGER ensure_command_prefix_not_empty
+    BEFORE INSERT
+    ON guild_config
+    WHEN NEW.key = 'command_prefix' AND length(trim(NEW.value)) = 0
+BEGIN
+    SELECT RAISE(ABORT, 'There must be at least one command prefix.');
+END;
+
+CREATE TRIGGER ensure_command_prefix_not_empty_upd
+    BEFORE UPDATE
+    ON guild_config
+    WHEN NEW.key = 'command_prefix' AND length(trim(NEW.value)) = 0
+BEGIN
+    SELECT RAISE(ABORT, 'There must be at least one command prefix.');
+END;
\ No newline at end of file
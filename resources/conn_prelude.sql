@@ -1,3 +1,4 @@
+PRAGMA auto_vacuum = INCREMENTAL;
 PRAGMA busy_timeout = 120000;
 PRAGMA locking_mode = NORMAL;
 PRAGMA foreign_keys = ON;
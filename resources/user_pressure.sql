@@ -1,4 +1,4 @@
-SELECT SUM(pressure)
+SELECT COALESCE(SUM(pressure), 0)
 FROM messages
 WHERE user = :uid
 AND unix_time >= :since;
\ No newline at end of file
@@ -82,4 +82,19 @@ pub fn parse_app_matches<'a, 'b>(name: impl AsRef<str>, s: impl AsRef<str>, a: &
         [name.as_ref()].iter().cloned()
             .chain(parts.iter().map(|s| s.as_str())))?;
     Ok(matches)
+}
+
+/// Returns the name of the subcommand the arguments string would invoke, if it parses.
+pub fn subcommand_name<'a, 'b>(name: impl AsRef<str>, s: impl AsRef<str>, a: &App<'a, 'b>) -> Option<String> {
+    parse_app_matches(name, s, a).ok()?
+        .subcommand_name()
+        .map(String::from)
+}
+
+/// Lists the names of the app's direct subcommands.
+pub fn subcommand_names(a: &App) -> Vec<String> {
+    // clap 2 has no public accessor for subcommands, so this goes through the parser it exposes.
+    a.p.subcommands.iter()
+        .map(|s| s.get_name().to_string())
+        .collect()
 }
\ No newline at end of file
@@ -17,7 +17,12 @@
 //! Module for processing command-line invocations related to database operations.
 
 use clap::{App, SubCommand, Arg, AppSettings, ArgMatches};
-use crate::db::{DatabaseVersion, DB_VERSION_STRING, new_conn, get_db_version, upgrade, downgrade};
+use crate::db::{DatabaseVersion, DB_VERSION_STRING, GuildConn, new_conn, get_db_version, upgrade, downgrade, prune_messages};
+use crate::modules::maintenance::prune_cutoff;
+use crate::modules::punishment::parse_duration;
+use serenity::model::id::GuildId;
+use std::path::Path;
+use chrono::Utc;
 
 #[doc(hidden)]
 pub fn command_parser() -> App<'static, 'static> {
@@ -54,6 +59,21 @@ pub fn command_parser() -> App<'static, 'static> {
             )
             .about("Queries the version of a Glimbot database file.")
         )
+        .subcommand(SubCommand::with_name("prune")
+            .arg(Arg::with_name("dbs")
+                .multiple(true)
+                .required(true)
+                .value_name("DATABASE_FILES")
+                .help("The database files to prune.")
+            )
+            .arg(Arg::with_name("older-than")
+                .long("older-than")
+                .value_name("DURATION")
+                .takes_value(true)
+                .help("Prunes messages older than this, e.g. 12h or 7d, instead of using each guild's message_retention.")
+            )
+            .about("Deletes old message records from the specified database files and reclaims the space.")
+        )
         .setting(AppSettings::SubcommandRequiredElseHelp)
 }
 
@@ -97,6 +117,26 @@ pub fn handle_matches(m: &ArgMatches) -> anyhow::Result<()> {
                 let ver = get_db_version(&conn)?;
                 info!("Database is at version {}", ver);
             }
+            ("prune", Some(m)) => {
+                let older_than = m.value_of("older-than")
+                    .map(|s| parse_duration(s).ok_or_else(|| anyhow::anyhow!("Invalid duration: {}", s)))
+                    .transpose()?;
+                let now = Utc::now();
+                for db in m.values_of("dbs").unwrap() {
+                    // Guild databases are named after their guild.
+                    let guild = Path::new(db).file_stem()
+                        .and_then(|s| s.to_str())
+                        .and_then(|s| s.parse::<u64>().ok())
+                        .unwrap_or_default();
+                    let conn = GuildConn::new(GuildId(guild), new_conn(db)?);
+                    let cutoff = match older_than {
+                        Some(d) => now - d,
+                        None => prune_cutoff(&conn, &now)?,
+                    };
+                    let n = prune_messages(conn.as_ref(), &cutoff)?;
+                    info!("Pruned {} messages from {}.", n, db);
+                }
+            }
             _ => unreachable!()
         }
     }
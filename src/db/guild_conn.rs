@@ -1,6 +1,281 @@
-use rusqlite::{Connection};
+use rusqlite::{Connection, OptionalExtension, NO_PARAMS};
 use serenity::model::id::GuildId;
-use serenity::model::prelude::RoleId;
+use serenity::model::prelude::{RoleId, UserId, MessageId, ChannelId};
+use chrono::{DateTime, Duration, Utc, TimeZone};
+use std::collections::{BTreeMap, HashMap};
+use std::fmt;
+use std::str::FromStr;
+
+/// The permission tiers a user can hold in a guild, in ascending order of privilege.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub enum Tier {
+    /// Any member of the guild.
+    Everyone,
+    /// Members with a moderator staff role.
+    Moderator,
+    /// Members with an admin staff role.
+    Admin,
+    /// The owner of the guild.
+    Owner,
+}
+
+impl Tier {
+    /// The value of the tier as stored in the `staff_roles` table.
+    pub fn as_i64(self) -> i64 {
+        self as i64
+    }
+
+    /// Converts a value stored in the `staff_roles` table back into a tier.
+    pub fn from_i64(i: i64) -> Option<Self> {
+        match i {
+            0 => Some(Tier::Everyone),
+            1 => Some(Tier::Moderator),
+            2 => Some(Tier::Admin),
+            3 => Some(Tier::Owner),
+            _ => None
+        }
+    }
+}
+
+impl fmt::Display for Tier {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", match self {
+            Tier::Everyone => "everyone",
+            Tier::Moderator => "moderator",
+            Tier::Admin => "admin",
+            Tier::Owner => "owner",
+        })
+    }
+}
+
+/// What the [filter module][crate::modules::filter] does with a message matching a rule, in ascending
+/// order of severity.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub enum FilterAction {
+    /// Records the match in the audit log.
+    Log,
+    /// Adds the guild's `filter_pressure` to the message, if the pressure engine is enabled.
+    Pressure,
+    /// Deletes the message.
+    Delete,
+    /// Deletes the message and warns its author.
+    Warn,
+}
+
+impl FilterAction {
+    /// The name of the action as stored in the `filter_rules` table.
+    pub fn as_str(self) -> &'static str {
+        match self {
+            FilterAction::Log => "log",
+            FilterAction::Pressure => "pressure",
+            FilterAction::Delete => "delete",
+            FilterAction::Warn => "warn",
+        }
+    }
+}
+
+impl FromStr for FilterAction {
+    type Err = ();
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "log" => Ok(FilterAction::Log),
+            "pressure" => Ok(FilterAction::Pressure),
+            "delete" => Ok(FilterAction::Delete),
+            "warn" => Ok(FilterAction::Warn),
+            _ => Err(())
+        }
+    }
+}
+
+/// Whom a [PermissionOverride] applies to.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum OverrideTarget {
+    /// Every member with the role.
+    Role(RoleId),
+    /// A single user.
+    User(UserId),
+}
+
+/// A rule allowing or denying a command to a role or user, optionally limited to a subcommand or channel.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct PermissionOverride {
+    /// The row id of the override.
+    pub id: i64,
+    /// The command the override applies to.
+    pub command: String,
+    /// The subcommand the override applies to, or None for the whole command.
+    pub subcommand: Option<String>,
+    /// The role or user the override applies to.
+    pub target: OverrideTarget,
+    /// The channel the override applies in, or None for every channel.
+    pub channel: Option<ChannelId>,
+    /// Whether the override allows or denies the command.
+    pub allow: bool,
+}
+
+impl PermissionOverride {
+    fn from_row(r: &rusqlite::Row) -> rusqlite::Result<Self> {
+        let subcommand: String = r.get(2)?;
+        let kind: String = r.get(3)?;
+        let target: i64 = r.get(4)?;
+        let channel: i64 = r.get(5)?;
+        Ok(PermissionOverride {
+            id: r.get(0)?,
+            command: r.get(1)?,
+            subcommand: Some(subcommand).filter(|s| !s.is_empty()),
+            target: if kind == "role" {
+                OverrideTarget::Role(RoleId(target as u64))
+            } else {
+                OverrideTarget::User(UserId(target as u64))
+            },
+            channel: Some(ChannelId(channel as u64)).filter(|c| c.0 != 0),
+            allow: r.get(6)?,
+        })
+    }
+}
+
+/// A warning given to a user by a moderator.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Infraction {
+    /// The row id of the infraction.
+    pub id: i64,
+    /// The user who was warned.
+    pub user: UserId,
+    /// The moderator who gave the warning.
+    pub moderator: UserId,
+    /// Why the user was warned.
+    pub reason: String,
+    /// When the user was warned.
+    pub time: DateTime<Utc>,
+}
+
+impl Infraction {
+    fn from_row(r: &rusqlite::Row) -> rusqlite::Result<Self> {
+        let user: i64 = r.get(1)?;
+        let moderator: i64 = r.get(2)?;
+        Ok(Infraction {
+            id: r.get(0)?,
+            user: UserId(user as u64),
+            moderator: UserId(moderator as u64),
+            reason: r.get(3)?,
+            time: Utc.timestamp(r.get(4)?, 0),
+        })
+    }
+}
+
+/// A persistent unit of delayed or recurring work, run by the [Scheduler][crate::dispatch::scheduler::Scheduler].
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Job {
+    /// The row id of the job.
+    pub id: i64,
+    /// The name of the job handler which runs the job.
+    pub kind: String,
+    /// Handler-specific data describing the work to do.
+    pub payload: String,
+    /// When the job should next run.
+    pub run_at: DateTime<Utc>,
+    /// How often the job repeats, or None for a one-shot job.
+    pub interval: Option<Duration>,
+    /// How many times the job has failed since it last succeeded.
+    pub attempts: u32,
+}
+
+impl Job {
+    fn from_row(r: &rusqlite::Row) -> rusqlite::Result<Self> {
+        let interval: i64 = r.get(4)?;
+        Ok(Job {
+            id: r.get(0)?,
+            kind: r.get(1)?,
+            payload: r.get(2)?,
+            run_at: Utc.timestamp(r.get(3)?, 0),
+            interval: Some(Duration::seconds(interval)).filter(|_| interval > 0),
+            attempts: r.get(5)?,
+        })
+    }
+
+    /// The first time after `now` a recurring job should run again, skipping any runs missed while
+    /// Glimbot was offline. Returns None for one-shot jobs, or if the next run is out of range.
+    pub fn next_run(&self, now: &DateTime<Utc>) -> Option<DateTime<Utc>> {
+        let interval = self.interval?.num_seconds();
+        let elapsed = (*now - self.run_at).num_seconds().max(0);
+        let next = self.run_at.timestamp()
+            .checked_add(elapsed - elapsed % interval)?
+            .checked_add(interval)?;
+        Utc.timestamp_opt(next, 0).single()
+    }
+}
+
+/// A rule allowing or denying links to a domain, used by the [links module][crate::modules::links].
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct LinkRule {
+    /// The domain the rule applies to. A leading `*.` also matches every subdomain.
+    pub domain: String,
+    /// Whether links to the domain are allowed or denied.
+    pub allow: bool,
+}
+
+/// A pattern checked against every message by the [filter module][crate::modules::filter].
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct FilterRule {
+    /// The row id of the rule.
+    pub id: i64,
+    /// The pattern to match.
+    pub pattern: String,
+    /// Whether the pattern is matched literally rather than as a regex.
+    pub literal: bool,
+    /// What to do with matching messages.
+    pub action: FilterAction,
+    /// Channels the rule doesn't apply in.
+    pub exempt_channels: Vec<ChannelId>,
+    /// Roles whose members the rule doesn't apply to.
+    pub exempt_roles: Vec<RoleId>,
+}
+
+/// An emoji on a message which toggles a role, used by the [reaction roles module][crate::modules::reaction_roles].
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ReactionRole {
+    /// The channel the message is in.
+    pub channel: ChannelId,
+    /// The message users react to.
+    pub message: MessageId,
+    /// The emoji's key: the id of a custom emoji, or the unicode emoji itself.
+    pub emoji: String,
+    /// The emoji as it would be written in a message.
+    pub emoji_text: String,
+    /// The role the emoji toggles.
+    pub role: RoleId,
+    /// Whether users may only hold one of the message's roles at a time.
+    pub exclusive: bool,
+}
+
+/// A named group of joinable roles, like "pronouns" or "region".
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct RoleGroup {
+    /// The group's name.
+    pub name: String,
+    /// Whether joining a role in the group leaves the group's other roles.
+    pub exclusive: bool,
+    /// The most roles from the group a user may hold at once, if capped.
+    pub max: Option<u32>,
+    /// The joinable roles in the group.
+    pub roles: Vec<RoleId>,
+}
+
+/// A guild's portable settings, exported and imported as a whole by the [config module][crate::modules::config].
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct Snapshot {
+    /// Config values by key.
+    pub config: BTreeMap<String, String>,
+    /// Commands restricted to admins.
+    pub restricted_commands: Vec<String>,
+    /// Joinable roles which aren't in a group.
+    pub joinable_roles: Vec<RoleId>,
+    /// Role groups, along with their roles.
+    pub role_groups: Vec<RoleGroup>,
+    /// Filter rules. Their ids are ignored when the snapshot is applied.
+    pub filters: Vec<FilterRule>,
+}
 
 /// Wrapper around [Connection] to perform typical guild operations.
 pub struct GuildConn { conn: Connection, id: GuildId }
@@ -11,21 +286,21 @@ impl GuildConn {
         GuildConn { conn: c, id }
     }
 
-    /// Retrieves the command prefix of the guild from the database.
-    pub fn command_prefix(&self) -> super::Result<char> {
+    /// Retrieves the command prefixes of the guild from the database.
+    pub fn command_prefixes(&self) -> super::Result<Vec<String>> {
         let s: String = self.get_value("command_prefix")?;
-        Ok(s.chars().next().unwrap())
+        Ok(s.split_whitespace().map(String::from).collect())
     }
 
-    /// Sets the command prefix to the given character.
-    pub fn set_command_prefix(&self, cmd: char) -> super::Result<()> {
-        self.set_value("command_prefix", cmd.to_string())
+    /// Sets the command prefixes to the given strings, which must not contain whitespace.
+    pub fn set_command_prefixes(&self, prefixes: &[&str]) -> super::Result<()> {
+        self.set_value("command_prefix", prefixes.join(" "))
     }
 
     /// Retrieves whether or not a role is joinable from the connection.
     pub fn role_is_joinable(&self, id: RoleId) -> super::Result<bool> {
         let v: bool = self.conn.query_row(
-            "SELECT ? IN joinable_roles;",
+            "SELECT EXISTS (SELECT 1 FROM joinable_roles WHERE role = ?);",
                 params![id.0 as i64],
             |r| r.get(0)
         )?;
@@ -33,6 +308,596 @@ impl GuildConn {
         Ok(v)
     }
 
+    /// Makes a role joinable, placing it in the given group (created if needed) or in no group.
+    pub fn set_role_joinable(&self, id: RoleId, group: Option<&str>) -> super::Result<()> {
+        let tx = self.conn.unchecked_transaction()?;
+        if let Some(g) = group {
+            tx.execute("INSERT OR IGNORE INTO role_groups (name) VALUES (?);", params![g])?;
+        }
+        tx.execute(
+            "INSERT OR REPLACE INTO joinable_roles (role, role_group) VALUES (?, ?);",
+            params![id.0 as i64, group]
+        )?;
+        tx.execute(
+            "DELETE FROM role_groups WHERE name NOT IN (SELECT role_group FROM joinable_roles WHERE role_group IS NOT NULL);",
+            NO_PARAMS
+        )?;
+        tx.commit()?;
+
+        Ok(())
+    }
+
+    /// Makes a role unjoinable, dropping its group if the group is now empty. Returns false if it wasn't joinable.
+    pub fn remove_joinable_role(&self, id: RoleId) -> super::Result<bool> {
+        let tx = self.conn.unchecked_transaction()?;
+        let n = tx.execute("DELETE FROM joinable_roles WHERE role = ?;", params![id.0 as i64])?;
+        tx.execute(
+            "DELETE FROM role_groups WHERE name NOT IN (SELECT role_group FROM joinable_roles WHERE role_group IS NOT NULL);",
+            NO_PARAMS
+        )?;
+        tx.commit()?;
+
+        Ok(n > 0)
+    }
+
+    /// Sets a role group's options. Returns false if there is no such group.
+    pub fn set_role_group_options(&self, name: &str, exclusive: bool, max: Option<u32>) -> super::Result<bool> {
+        let n = self.conn.execute(
+            "UPDATE role_groups SET exclusive = ?, max_roles = ? WHERE name = ?;",
+            params![exclusive, max.map(i64::from), name]
+        )?;
+
+        Ok(n > 0)
+    }
+
+    /// Lists the joinable roles along with their groups, ordered by group.
+    pub fn joinable_roles(&self) -> super::Result<Vec<(RoleId, Option<String>)>> {
+        let mut stmt = self.conn.prepare("SELECT role, role_group FROM joinable_roles ORDER BY role_group, role;")?;
+        let rows = stmt.query_map(NO_PARAMS, |r| {
+            let role: i64 = r.get(0)?;
+            Ok((RoleId(role as u64), r.get(1)?))
+        })?.collect::<rusqlite::Result<Vec<_>>>()?;
+        Ok(rows)
+    }
+
+    /// Lists every role group, ordered by name.
+    pub fn role_groups(&self) -> super::Result<Vec<RoleGroup>> {
+        let mut stmt = self.conn.prepare("SELECT name, exclusive, max_roles FROM role_groups ORDER BY name;")?;
+        let mut groups = stmt.query_map(NO_PARAMS, |r| {
+            let max: Option<i64> = r.get(2)?;
+            Ok(RoleGroup {
+                name: r.get(0)?,
+                exclusive: r.get(1)?,
+                max: max.map(|m| m as u32),
+                roles: Vec::new(),
+            })
+        })?.collect::<rusqlite::Result<Vec<_>>>()?;
+
+        for (role, group) in self.joinable_roles()? {
+            if let Some(g) = group.and_then(|name| groups.iter_mut().find(|g| g.name == name)) {
+                g.roles.push(role);
+            }
+        }
+        Ok(groups)
+    }
+
+    /// Sets a guild command alias, replacing any existing alias with the same name.
+    pub fn set_command_alias(&self, alias: &str, expansion: &str) -> super::Result<()> {
+        self.conn.execute(
+            "INSERT OR REPLACE INTO command_aliases (alias, expansion) VALUES (?, ?);",
+            params![alias, expansion]
+        )?;
+
+        Ok(())
+    }
+
+    /// Removes a guild command alias. Returns false if there was no such alias.
+    pub fn remove_command_alias(&self, alias: &str) -> super::Result<bool> {
+        let n = self.conn.execute("DELETE FROM command_aliases WHERE alias = ?;", params![alias])?;
+        Ok(n > 0)
+    }
+
+    /// Retrieves what a guild command alias expands to, if it exists.
+    pub fn command_alias(&self, alias: &str) -> super::Result<Option<String>> {
+        let v = self.conn.query_row(
+            "SELECT expansion FROM command_aliases WHERE alias = ?;",
+            params![alias],
+            |r| r.get(0)
+        ).optional()?;
+
+        Ok(v)
+    }
+
+    /// Lists the guild's command aliases and their expansions, ordered by alias.
+    pub fn command_aliases(&self) -> super::Result<Vec<(String, String)>> {
+        let mut stmt = self.conn.prepare("SELECT alias, expansion FROM command_aliases ORDER BY alias;")?;
+        let rows = stmt.query_map(NO_PARAMS, |r| Ok((r.get(0)?, r.get(1)?)))?
+            .collect::<rusqlite::Result<Vec<_>>>()?;
+        Ok(rows)
+    }
+
+    /// Retrieves whether or not a command has been restricted to admins.
+    pub fn command_is_restricted(&self, name: impl AsRef<str>) -> super::Result<bool> {
+        let v: bool = self.conn.query_row(
+            "SELECT ? IN restricted_commands;",
+            params![name.as_ref()],
+            |r| r.get(0)
+        )?;
+
+        Ok(v)
+    }
+
+    /// Restricts or unrestricts a command.
+    pub fn set_command_restricted(&self, name: impl AsRef<str>, restricted: bool) -> super::Result<()> {
+        let sql = if restricted {
+            "INSERT OR IGNORE INTO restricted_commands VALUES (?);"
+        } else {
+            "DELETE FROM restricted_commands WHERE name = ?;"
+        };
+
+        self.conn.execute(sql, params![name.as_ref()])?;
+        Ok(())
+    }
+
+    /// Lists every restricted command, sorted by name.
+    pub fn restricted_commands(&self) -> super::Result<Vec<String>> {
+        let mut stmt = self.conn.prepare("SELECT name FROM restricted_commands ORDER BY name;")?;
+        let names = stmt.query_map(NO_PARAMS, |r| r.get(0))?
+            .collect::<rusqlite::Result<Vec<String>>>()?;
+        Ok(names)
+    }
+
+    /// Sets the permission tier granted by a staff role. Setting [Tier::Everyone] removes the role from staff.
+    pub fn set_role_tier(&self, role: RoleId, tier: Tier) -> super::Result<()> {
+        if tier == Tier::Everyone {
+            self.conn.execute(
+                "DELETE FROM staff_roles WHERE role = ?;",
+                params![role.0 as i64]
+            )?;
+        } else {
+            self.conn.execute(
+                "INSERT OR REPLACE INTO staff_roles (role, tier) VALUES (?, ?);",
+                params![role.0 as i64, tier.as_i64()]
+            )?;
+        }
+
+        Ok(())
+    }
+
+    /// Lists every staff role and its tier, highest tier first.
+    pub fn staff_roles(&self) -> super::Result<Vec<(RoleId, Tier)>> {
+        let mut stmt = self.conn.prepare("SELECT role, tier FROM staff_roles ORDER BY tier DESC, role;")?;
+        let roles = stmt.query_map(NO_PARAMS, |r| {
+            let role: i64 = r.get(0)?;
+            let tier: i64 = r.get(1)?;
+            Ok((RoleId(role as u64), Tier::from_i64(tier).unwrap_or(Tier::Everyone)))
+        })?.collect::<rusqlite::Result<Vec<_>>>()?;
+        Ok(roles)
+    }
+
+    /// Adds a permission override, replacing any existing override for the same command, subcommand,
+    /// target and channel.
+    pub fn set_override(&self, command: &str, subcommand: Option<&str>, target: OverrideTarget, channel: Option<ChannelId>, allow: bool) -> super::Result<()> {
+        let (kind, id) = match target {
+            OverrideTarget::Role(r) => ("role", r.0),
+            OverrideTarget::User(u) => ("user", u.0),
+        };
+
+        self.conn.execute(
+            "INSERT OR REPLACE INTO permission_overrides (command, subcommand, target_kind, target, channel, allow) VALUES (?, ?, ?, ?, ?, ?);",
+            params![command, subcommand.unwrap_or(""), kind, id as i64, channel.map_or(0, |c| c.0) as i64, allow]
+        )?;
+
+        Ok(())
+    }
+
+    /// Removes a permission override by id. Returns false if there was no such override.
+    pub fn remove_override(&self, id: i64) -> super::Result<bool> {
+        let n = self.conn.execute(
+            "DELETE FROM permission_overrides WHERE id = ?;",
+            params![id]
+        )?;
+
+        Ok(n > 0)
+    }
+
+    /// Lists the permission overrides for a command, or for every command if none is given.
+    pub fn overrides(&self, command: Option<&str>) -> super::Result<Vec<PermissionOverride>> {
+        let mut stmt = self.conn.prepare(
+            "SELECT id, command, subcommand, target_kind, target, channel, allow FROM permission_overrides
+             WHERE ?1 IS NULL OR command = ?1
+             ORDER BY command, id;"
+        )?;
+        let rows = stmt.query_map(params![command], PermissionOverride::from_row)?
+            .collect::<rusqlite::Result<Vec<_>>>()?;
+        Ok(rows)
+    }
+
+    /// Records the pressure generated by a message so it can be summed by [user_pressure][super::user_pressure],
+    /// along with the fingerprint of its content, if it has one.
+    pub fn add_message(&self, user: UserId, channel: ChannelId, message: MessageId, pressure: i64, fingerprint: Option<u64>, time: &DateTime<Utc>) -> super::Result<()> {
+        self.conn.execute(
+            "INSERT OR IGNORE INTO messages (user, channel, message, pressure, fingerprint, unix_time) VALUES (?, ?, ?, ?, ?, ?);",
+            params![user.0 as i64, channel.0 as i64, message.0 as i64, pressure, fingerprint.unwrap_or(0) as i64, time.timestamp()]
+        )?;
+
+        Ok(())
+    }
+
+    /// Counts the distinct users who have posted messages with the given fingerprint since the given time,
+    /// optionally only in a single channel.
+    pub fn count_duplicate_senders(&self, fingerprint: u64, since: &DateTime<Utc>, channel: Option<ChannelId>) -> super::Result<u32> {
+        let v: u32 = self.conn.query_row(
+            "SELECT COUNT(DISTINCT user) FROM messages
+             WHERE fingerprint = ?1 AND unix_time >= ?2 AND (?3 IS NULL OR channel = ?3);",
+            params![fingerprint as i64, since.timestamp(), channel.map(|c| c.0 as i64)],
+            |r| r.get(0)
+        )?;
+
+        Ok(v)
+    }
+
+    /// Records an anti-spam offence for the user.
+    pub fn add_offence(&self, user: UserId, time: &DateTime<Utc>) -> super::Result<()> {
+        self.conn.execute(
+            "INSERT INTO offences (user, unix_time) VALUES (?, ?);",
+            params![user.0 as i64, time.timestamp()]
+        )?;
+
+        Ok(())
+    }
+
+    /// Counts the anti-spam offences the user has committed since the given time.
+    pub fn count_offences(&self, user: UserId, since: &DateTime<Utc>) -> super::Result<u32> {
+        let v: u32 = self.conn.query_row(
+            "SELECT COUNT(*) FROM offences WHERE user = ? AND unix_time >= ?;",
+            params![user.0 as i64, since.timestamp()],
+            |r| r.get(0)
+        )?;
+
+        Ok(v)
+    }
+
+    /// Records a warning against the user, returning the id of the new infraction.
+    pub fn add_infraction(&self, user: UserId, moderator: UserId, reason: &str, time: &DateTime<Utc>) -> super::Result<i64> {
+        self.conn.execute(
+            "INSERT INTO infractions (user, moderator, reason, unix_time) VALUES (?, ?, ?, ?);",
+            params![user.0 as i64, moderator.0 as i64, reason, time.timestamp()]
+        )?;
+
+        Ok(self.conn.last_insert_rowid())
+    }
+
+    /// Lists the user's infractions, oldest first.
+    pub fn infractions(&self, user: UserId) -> super::Result<Vec<Infraction>> {
+        let mut stmt = self.conn.prepare(
+            "SELECT id, user, moderator, reason, unix_time FROM infractions WHERE user = ? ORDER BY unix_time, id;"
+        )?;
+        let rows = stmt.query_map(params![user.0 as i64], Infraction::from_row)?
+            .collect::<rusqlite::Result<Vec<_>>>()?;
+        Ok(rows)
+    }
+
+    /// Retrieves an infraction by id.
+    pub fn infraction(&self, id: i64) -> super::Result<Option<Infraction>> {
+        let infraction = self.conn.query_row(
+            "SELECT id, user, moderator, reason, unix_time FROM infractions WHERE id = ?;",
+            params![id],
+            Infraction::from_row
+        ).optional()?;
+        Ok(infraction)
+    }
+
+    /// Removes an infraction by id, returning it if it existed.
+    pub fn remove_infraction(&self, id: i64) -> super::Result<Option<Infraction>> {
+        let infraction = self.infraction(id)?;
+        if infraction.is_some() {
+            self.conn.execute("DELETE FROM infractions WHERE id = ?;", params![id])?;
+        }
+
+        Ok(infraction)
+    }
+
+    /// Removes every infraction for the user, returning how many there were.
+    pub fn clear_infractions(&self, user: UserId) -> super::Result<usize> {
+        let n = self.conn.execute(
+            "DELETE FROM infractions WHERE user = ?;",
+            params![user.0 as i64]
+        )?;
+
+        Ok(n)
+    }
+
+    /// Schedules a job to run at the given time, repeating every `interval` if one is given.
+    /// Returns the id of the new job.
+    pub fn schedule_job(&self, kind: &str, payload: &str, run_at: &DateTime<Utc>, interval: Option<Duration>) -> super::Result<i64> {
+        self.conn.execute(
+            "INSERT INTO jobs (kind, payload, run_at, interval_secs) VALUES (?, ?, ?, ?);",
+            params![kind, payload, run_at.timestamp(), interval.map_or(0, |i| i.num_seconds())]
+        )?;
+
+        Ok(self.conn.last_insert_rowid())
+    }
+
+    /// Cancels every pending job of the given kind with the given payload, returning how many there were.
+    pub fn cancel_jobs(&self, kind: &str, payload: &str) -> super::Result<usize> {
+        let n = self.conn.execute(
+            "DELETE FROM jobs WHERE kind = ? AND payload = ?;",
+            params![kind, payload]
+        )?;
+
+        Ok(n)
+    }
+
+    /// Lists the jobs of the given kind, or every job if none is given, soonest first.
+    pub fn jobs(&self, kind: Option<&str>) -> super::Result<Vec<Job>> {
+        let mut stmt = self.conn.prepare(
+            "SELECT id, kind, payload, run_at, interval_secs, attempts FROM jobs
+             WHERE ?1 IS NULL OR kind = ?1
+             ORDER BY run_at, id;"
+        )?;
+        let rows = stmt.query_map(params![kind], Job::from_row)?
+            .collect::<rusqlite::Result<Vec<_>>>()?;
+        Ok(rows)
+    }
+
+    /// Lists the jobs which are due at the given time, oldest first.
+    pub fn due_jobs(&self, now: &DateTime<Utc>) -> super::Result<Vec<Job>> {
+        let mut stmt = self.conn.prepare(
+            "SELECT id, kind, payload, run_at, interval_secs, attempts FROM jobs
+             WHERE run_at <= ?
+             ORDER BY run_at, id;"
+        )?;
+        let rows = stmt.query_map(params![now.timestamp()], Job::from_row)?
+            .collect::<rusqlite::Result<Vec<_>>>()?;
+        Ok(rows)
+    }
+
+    /// Marks a job as having run successfully. One-shot jobs are removed, while recurring jobs
+    /// are moved to their next run.
+    pub fn finish_job(&self, job: &Job, now: &DateTime<Utc>) -> super::Result<()> {
+        match job.next_run(now) {
+            Some(next) => self.conn.execute(
+                "UPDATE jobs SET run_at = ?, attempts = 0 WHERE id = ?;",
+                params![next.timestamp(), job.id]
+            )?,
+            None => self.conn.execute(
+                "DELETE FROM jobs WHERE id = ?;",
+                params![job.id]
+            )?,
+        };
+
+        Ok(())
+    }
+
+    /// Marks a job as having failed, retrying it at the given time.
+    pub fn retry_job(&self, job: &Job, at: &DateTime<Utc>) -> super::Result<()> {
+        self.conn.execute(
+            "UPDATE jobs SET run_at = ?, attempts = attempts + 1 WHERE id = ?;",
+            params![at.timestamp(), job.id]
+        )?;
+
+        Ok(())
+    }
+
+    /// Removes a job by id, e.g. once it has failed too many times. Returns false if there was no such job.
+    pub fn remove_job(&self, id: i64) -> super::Result<bool> {
+        let n = self.conn.execute(
+            "DELETE FROM jobs WHERE id = ?;",
+            params![id]
+        )?;
+
+        Ok(n > 0)
+    }
+
+    /// Allows or denies links to a domain, replacing any existing rule for it.
+    pub fn set_link_rule(&self, domain: &str, allow: bool) -> super::Result<()> {
+        self.conn.execute(
+            "INSERT OR REPLACE INTO link_rules (domain, allow) VALUES (?, ?);",
+            params![domain, allow]
+        )?;
+
+        Ok(())
+    }
+
+    /// Removes the rule for a domain. Returns false if there was no such rule.
+    pub fn remove_link_rule(&self, domain: &str) -> super::Result<bool> {
+        let n = self.conn.execute(
+            "DELETE FROM link_rules WHERE domain = ?;",
+            params![domain]
+        )?;
+
+        Ok(n > 0)
+    }
+
+    /// Lists every link rule in the guild, ordered by domain.
+    pub fn link_rules(&self) -> super::Result<Vec<LinkRule>> {
+        let mut stmt = self.conn.prepare("SELECT domain, allow FROM link_rules ORDER BY domain;")?;
+        let rows = stmt.query_map(NO_PARAMS, |r| Ok(LinkRule { domain: r.get(0)?, allow: r.get(1)? }))?
+            .collect::<rusqlite::Result<Vec<_>>>()?;
+        Ok(rows)
+    }
+
+    /// Adds to the pressure of a message recorded by the spam module. Returns false if the message
+    /// wasn't recorded, e.g. because the pressure engine is disabled.
+    pub fn add_pressure(&self, user: UserId, message: MessageId, pressure: i64) -> super::Result<bool> {
+        let n = self.conn.execute(
+            "UPDATE messages SET pressure = pressure + ? WHERE user = ? AND message = ?;",
+            params![pressure, user.0 as i64, message.0 as i64]
+        )?;
+
+        Ok(n > 0)
+    }
+
+    /// Adds a filter rule along with its exemptions, returning its id.
+    pub fn add_filter_rule(&self, pattern: &str, literal: bool, action: FilterAction, exempt_channels: &[ChannelId], exempt_roles: &[RoleId]) -> super::Result<i64> {
+        let tx = self.conn.unchecked_transaction()?;
+        tx.execute(
+            "INSERT INTO filter_rules (pattern, literal, action) VALUES (?, ?, ?);",
+            params![pattern, literal, action.as_str()]
+        )?;
+        let id = tx.last_insert_rowid();
+
+        let exemptions = exempt_channels.iter().map(|c| ("channel", c.0))
+            .chain(exempt_roles.iter().map(|r| ("role", r.0)));
+        for (kind, target) in exemptions {
+            tx.execute(
+                "INSERT OR IGNORE INTO filter_exemptions (rule, kind, target) VALUES (?, ?, ?);",
+                params![id, kind, target as i64]
+            )?;
+        }
+
+        tx.commit()?;
+        Ok(id)
+    }
+
+    /// Removes a filter rule and its exemptions by id. Returns false if there was no such rule.
+    pub fn remove_filter_rule(&self, id: i64) -> super::Result<bool> {
+        let n = self.conn.execute(
+            "DELETE FROM filter_rules WHERE id = ?;",
+            params![id]
+        )?;
+
+        Ok(n > 0)
+    }
+
+    /// Lists every filter rule in the guild, ordered by id.
+    pub fn filter_rules(&self) -> super::Result<Vec<FilterRule>> {
+        let mut stmt = self.conn.prepare("SELECT id, pattern, literal, action FROM filter_rules ORDER BY id;")?;
+        let mut rules = stmt.query_map(NO_PARAMS, |r| {
+            let action: String = r.get(3)?;
+            Ok(FilterRule {
+                id: r.get(0)?,
+                pattern: r.get(1)?,
+                literal: r.get(2)?,
+                action: action.parse().unwrap_or(FilterAction::Log),
+                exempt_channels: Vec::new(),
+                exempt_roles: Vec::new(),
+            })
+        })?.collect::<rusqlite::Result<Vec<_>>>()?;
+
+        let mut stmt = self.conn.prepare("SELECT rule, kind, target FROM filter_exemptions ORDER BY target;")?;
+        let exemptions = stmt.query_map(NO_PARAMS, |r| Ok((r.get::<_, i64>(0)?, r.get::<_, String>(1)?, r.get::<_, i64>(2)? as u64)))?
+            .collect::<rusqlite::Result<Vec<_>>>()?;
+        for (id, kind, target) in exemptions {
+            if let Some(rule) = rules.iter_mut().find(|r| r.id == id) {
+                if kind == "channel" {
+                    rule.exempt_channels.push(ChannelId(target));
+                } else {
+                    rule.exempt_roles.push(RoleId(target));
+                }
+            }
+        }
+
+        Ok(rules)
+    }
+
+    /// Holds a user at the gate until they verify. Returns false if they were already held.
+    pub fn add_gate_pending(&self, user: UserId, time: &DateTime<Utc>) -> super::Result<bool> {
+        let n = self.conn.execute(
+            "INSERT OR IGNORE INTO gate_pending (user, unix_time) VALUES (?, ?);",
+            params![user.0 as i64, time.timestamp()]
+        )?;
+
+        Ok(n > 0)
+    }
+
+    /// Releases a user from the gate. Returns false if they weren't held.
+    pub fn remove_gate_pending(&self, user: UserId) -> super::Result<bool> {
+        let n = self.conn.execute(
+            "DELETE FROM gate_pending WHERE user = ?;",
+            params![user.0 as i64]
+        )?;
+
+        Ok(n > 0)
+    }
+
+    /// Retrieves whether a user is held at the gate.
+    pub fn is_gate_pending(&self, user: UserId) -> super::Result<bool> {
+        let b = self.conn.query_row(
+            "SELECT EXISTS (SELECT 1 FROM gate_pending WHERE user = ?);",
+            params![user.0 as i64],
+            |r| r.get(0)
+        )?;
+
+        Ok(b)
+    }
+
+    /// Lists the users held at the gate along with when they joined, oldest first.
+    pub fn gate_pending(&self) -> super::Result<Vec<(UserId, DateTime<Utc>)>> {
+        let mut stmt = self.conn.prepare("SELECT user, unix_time FROM gate_pending ORDER BY unix_time, user;")?;
+        let rows = stmt.query_map(NO_PARAMS, |r| Ok((UserId(r.get::<_, i64>(0)? as u64), Utc.timestamp(r.get(1)?, 0))))?
+            .collect::<rusqlite::Result<Vec<_>>>()?;
+        Ok(rows)
+    }
+
+    /// Binds an emoji on a message to a role, replacing any existing binding for the emoji.
+    pub fn bind_reaction_role(&self, channel: ChannelId, message: MessageId, emoji: &str, emoji_text: &str, role: RoleId) -> super::Result<()> {
+        let tx = self.conn.unchecked_transaction()?;
+        tx.execute(
+            "INSERT OR IGNORE INTO reaction_role_messages (message, channel) VALUES (?, ?);",
+            params![message.0 as i64, channel.0 as i64]
+        )?;
+        tx.execute(
+            "INSERT OR REPLACE INTO reaction_roles (message, emoji, emoji_text, role) VALUES (?, ?, ?, ?);",
+            params![message.0 as i64, emoji, emoji_text, role.0 as i64]
+        )?;
+        tx.commit()?;
+
+        Ok(())
+    }
+
+    /// Unbinds an emoji on a message. Returns false if it wasn't bound.
+    pub fn unbind_reaction_role(&self, message: MessageId, emoji: &str) -> super::Result<bool> {
+        let tx = self.conn.unchecked_transaction()?;
+        let n = tx.execute(
+            "DELETE FROM reaction_roles WHERE message = ? AND emoji = ?;",
+            params![message.0 as i64, emoji]
+        )?;
+        tx.execute(
+            "DELETE FROM reaction_role_messages WHERE message = ?1
+             AND NOT EXISTS (SELECT 1 FROM reaction_roles WHERE message = ?1);",
+            params![message.0 as i64]
+        )?;
+        tx.commit()?;
+
+        Ok(n > 0)
+    }
+
+    /// Sets whether a message's reaction roles are exclusive. Returns false if the message has none.
+    pub fn set_reaction_roles_exclusive(&self, message: MessageId, exclusive: bool) -> super::Result<bool> {
+        let n = self.conn.execute(
+            "UPDATE reaction_role_messages SET exclusive = ? WHERE message = ?;",
+            params![exclusive, message.0 as i64]
+        )?;
+
+        Ok(n > 0)
+    }
+
+    /// Lists the reaction roles on a message, or on every message if none is given.
+    pub fn reaction_roles(&self, message: Option<MessageId>) -> super::Result<Vec<ReactionRole>> {
+        let mut stmt = self.conn.prepare(
+            "SELECT m.channel, m.message, r.emoji, r.emoji_text, r.role, m.exclusive
+             FROM reaction_roles r JOIN reaction_role_messages m ON r.message = m.message
+             WHERE ?1 IS NULL OR m.message = ?1
+             ORDER BY m.message, r.role;"
+        )?;
+        let rows = stmt.query_map(params![message.map(|m| m.0 as i64)], |r| {
+            let channel: i64 = r.get(0)?;
+            let message: i64 = r.get(1)?;
+            let role: i64 = r.get(4)?;
+            Ok(ReactionRole {
+                channel: ChannelId(channel as u64),
+                message: MessageId(message as u64),
+                emoji: r.get(2)?,
+                emoji_text: r.get(3)?,
+                role: RoleId(role as u64),
+                exclusive: r.get(5)?,
+            })
+        })?.collect::<rusqlite::Result<Vec<_>>>()?;
+        Ok(rows)
+    }
+
     /// Retrieves the config element with the given key.
     pub fn get_value(&self, key: impl AsRef<str>) -> super::Result<String> {
         let s: String = self.as_ref()
@@ -70,6 +935,88 @@ impl GuildConn {
         Ok(())
     }
 
+    /// Deletes the config element with the given key, returning whether it was set.
+    pub fn remove_value(&self, key: impl AsRef<str>) -> super::Result<bool> {
+        let n = self.as_ref()
+            .execute("DELETE FROM guild_config WHERE key = ?;", params![key.as_ref()])?;
+        Ok(n > 0)
+    }
+
+    /// Retrieves every config element which has been set, by key.
+    pub fn config_values(&self) -> super::Result<HashMap<String, String>> {
+        let mut stmt = self.conn.prepare("SELECT key, value FROM guild_config;")?;
+        let rows = stmt.query_map(NO_PARAMS, |r| Ok((r.get(0)?, r.get(1)?)))?
+            .collect::<rusqlite::Result<HashMap<_, _>>>()?;
+        Ok(rows)
+    }
+
+    /// Captures the guild's portable settings.
+    pub fn snapshot(&self) -> super::Result<Snapshot> {
+        Ok(Snapshot {
+            config: self.config_values()?.into_iter().collect(),
+            restricted_commands: self.restricted_commands()?,
+            joinable_roles: self.joinable_roles()?
+                .into_iter()
+                .filter(|(_, g)| g.is_none())
+                .map(|(r, _)| r)
+                .collect(),
+            role_groups: self.role_groups()?,
+            filters: self.filter_rules()?,
+        })
+    }
+
+    /// Replaces the guild's portable settings with the snapshot, all at once. Only the given config
+    /// keys are replaced; others, such as those of modules which aren't loaded, are left alone.
+    pub fn apply_snapshot(&self, snapshot: &Snapshot, keys: &[&str]) -> super::Result<()> {
+        let tx = self.conn.unchecked_transaction()?;
+        for key in keys {
+            tx.execute("DELETE FROM guild_config WHERE key = ?;", params![key])?;
+        }
+        for table in &["restricted_commands", "joinable_roles", "role_groups", "filter_exemptions", "filter_rules"] {
+            tx.execute(&format!("DELETE FROM {};", table), NO_PARAMS)?;
+        }
+
+        for (key, value) in &snapshot.config {
+            tx.execute("INSERT OR REPLACE INTO guild_config VALUES (?, ?);", params![key, value])?;
+        }
+        for name in &snapshot.restricted_commands {
+            tx.execute("INSERT OR IGNORE INTO restricted_commands VALUES (?);", params![name])?;
+        }
+        for role in &snapshot.joinable_roles {
+            tx.execute("INSERT OR REPLACE INTO joinable_roles (role) VALUES (?);", params![role.0 as i64])?;
+        }
+        for g in &snapshot.role_groups {
+            tx.execute(
+                "INSERT INTO role_groups (name, exclusive, max_roles) VALUES (?, ?, ?);",
+                params![g.name, g.exclusive, g.max.map(i64::from)]
+            )?;
+            for role in &g.roles {
+                tx.execute(
+                    "INSERT OR REPLACE INTO joinable_roles (role, role_group) VALUES (?, ?);",
+                    params![role.0 as i64, g.name]
+                )?;
+            }
+        }
+        for rule in &snapshot.filters {
+            tx.execute(
+                "INSERT INTO filter_rules (pattern, literal, action) VALUES (?, ?, ?);",
+                params![rule.pattern, rule.literal, rule.action.as_str()]
+            )?;
+            let id = tx.last_insert_rowid();
+            let exemptions = rule.exempt_channels.iter().map(|c| ("channel", c.0))
+                .chain(rule.exempt_roles.iter().map(|r| ("role", r.0)));
+            for (kind, target) in exemptions {
+                tx.execute(
+                    "INSERT OR IGNORE INTO filter_exemptions (rule, kind, target) VALUES (?, ?, ?);",
+                    params![id, kind, target as i64]
+                )?;
+            }
+        }
+
+        tx.commit()?;
+        Ok(())
+    }
+
     /// Retrieves the [GuildId] from this connection
     pub fn as_id(&self) -> &GuildId {
         &self.id
@@ -90,21 +1037,365 @@ impl AsMut<Connection> for GuildConn {
 
 #[cfg(test)]
 mod tests {
-    use tempdir::TempDir;
-    use crate::db::{ensure_guild_db, init_guild_db, GuildConn};
-    use serenity::model::id::GuildId;
+    use crate::db::Job;
+    use crate::util::test_guild_conn;
+    use serenity::model::id::{UserId, MessageId, RoleId, ChannelId};
+    use crate::db::{user_pressure, OverrideTarget, LinkRule, RoleGroup, Tier, FilterAction};
+    use rusqlite::NO_PARAMS;
+    use chrono::{Utc, Duration, TimeZone};
 
     #[test]
     fn test_command_prefix() {
-        let dummy_dir = TempDir::new("migrations").unwrap();
-        let id = GuildId::from(std::u64::MAX);
-        let mut dummy_conn = ensure_guild_db(dummy_dir.as_ref(), id).unwrap();
-        init_guild_db(&mut dummy_conn).unwrap();
-        let gconn = GuildConn::new(id, dummy_conn);
-        let c = gconn.command_prefix().unwrap();
-        assert_eq!(c, '!');
-        gconn.set_command_prefix('~').unwrap();
-        let c = gconn.command_prefix().unwrap();
-        assert_eq!(c, '~');
+        let (_dir, gconn) = test_guild_conn();
+        let c = gconn.command_prefixes().unwrap();
+        assert_eq!(c, vec!["!"]);
+        gconn.set_command_prefixes(&["~", "gl!"]).unwrap();
+        let c = gconn.command_prefixes().unwrap();
+        assert_eq!(c, vec!["~", "gl!"]);
+        assert!(gconn.set_command_prefixes(&[]).is_err());
+    }
+
+    #[test]
+    fn test_message_pressure() {
+        let (_dir, gconn) = test_guild_conn();
+        let user = UserId::from(1);
+        let now = Utc::now();
+
+        assert_eq!(user_pressure(&now, user, gconn.as_ref()).unwrap(), 0);
+
+        let channel = ChannelId::from(1);
+        gconn.add_message(user, channel, MessageId::from(1), 10, None, &(now - Duration::seconds(30))).unwrap();
+        gconn.add_message(user, channel, MessageId::from(2), 15, None, &now).unwrap();
+        gconn.add_message(UserId::from(2), channel, MessageId::from(3), 20, None, &now).unwrap();
+
+        assert_eq!(user_pressure(&(now - Duration::seconds(10)), user, gconn.as_ref()).unwrap(), 15);
+        assert_eq!(user_pressure(&(now - Duration::seconds(60)), user, gconn.as_ref()).unwrap(), 25);
+    }
+
+    #[test]
+    fn test_restricted_commands() {
+        let (_dir, gconn) = test_guild_conn();
+
+        assert!(!gconn.command_is_restricted("ping").unwrap());
+        gconn.set_command_restricted("ping", true).unwrap();
+        gconn.set_command_restricted("me", true).unwrap();
+        gconn.set_command_restricted("ping", true).unwrap();
+        assert!(gconn.command_is_restricted("ping").unwrap());
+        assert_eq!(gconn.restricted_commands().unwrap(), vec!["me".to_string(), "ping".to_string()]);
+        gconn.set_command_restricted("ping", false).unwrap();
+        assert!(!gconn.command_is_restricted("ping").unwrap());
+    }
+
+    #[test]
+    fn test_permission_overrides() {
+        let (_dir, gconn) = test_guild_conn();
+
+        let role = OverrideTarget::Role(RoleId(1));
+        gconn.set_override("roles", Some("add-user"), role, None, true).unwrap();
+        gconn.set_override("roles", Some("add-user"), role, None, false).unwrap();
+        gconn.set_override("ping", None, OverrideTarget::User(UserId(2)), Some(ChannelId(3)), false).unwrap();
+
+        let roles = gconn.overrides(Some("roles")).unwrap();
+        assert_eq!(roles.len(), 1);
+        assert_eq!(roles[0].subcommand.as_deref(), Some("add-user"));
+        assert_eq!(roles[0].channel, None);
+        assert!(!roles[0].allow);
+
+        let all = gconn.overrides(None).unwrap();
+        assert_eq!(all.len(), 2);
+        assert_eq!(all[0].channel, Some(ChannelId(3)));
+        assert_eq!(all[0].subcommand, None);
+
+        assert!(gconn.remove_override(all[0].id).unwrap());
+        assert!(!gconn.remove_override(all[0].id).unwrap());
+    }
+
+    #[test]
+    fn test_staff_roles() {
+        let (_dir, gconn) = test_guild_conn();
+
+        gconn.set_role_tier(RoleId(1), Tier::Moderator).unwrap();
+        gconn.set_role_tier(RoleId(2), Tier::Admin).unwrap();
+        gconn.set_role_tier(RoleId(3), Tier::Moderator).unwrap();
+        assert_eq!(gconn.staff_roles().unwrap(),
+                   vec![(RoleId(2), Tier::Admin), (RoleId(1), Tier::Moderator), (RoleId(3), Tier::Moderator)]);
+
+        gconn.set_role_tier(RoleId(1), Tier::Everyone).unwrap();
+        gconn.set_role_tier(RoleId(3), Tier::Admin).unwrap();
+        assert_eq!(gconn.staff_roles().unwrap(), vec![(RoleId(2), Tier::Admin), (RoleId(3), Tier::Admin)]);
+    }
+
+    #[test]
+    fn test_infractions() {
+        let (_dir, gconn) = test_guild_conn();
+        let (user, other, moderator) = (UserId(1), UserId(2), UserId(3));
+        let now = Utc::now();
+
+        let first = gconn.add_infraction(user, moderator, "rude", &(now - Duration::seconds(10))).unwrap();
+        gconn.add_infraction(user, moderator, "spam", &now).unwrap();
+        gconn.add_infraction(other, moderator, "", &now).unwrap();
+
+        let warnings = gconn.infractions(user).unwrap();
+        assert_eq!(warnings.len(), 2);
+        assert_eq!(warnings[0].id, first);
+        assert_eq!(warnings[0].reason, "rude");
+        assert_eq!(warnings[1].moderator, moderator);
+
+        assert_eq!(gconn.infraction(first).unwrap().map(|i| i.user), Some(user));
+        assert_eq!(gconn.remove_infraction(first).unwrap().map(|i| i.user), Some(user));
+        assert_eq!(gconn.infraction(first).unwrap(), None);
+        assert_eq!(gconn.remove_infraction(first).unwrap(), None);
+        assert_eq!(gconn.clear_infractions(user).unwrap(), 1);
+        assert!(gconn.infractions(user).unwrap().is_empty());
+        assert_eq!(gconn.infractions(other).unwrap().len(), 1);
+    }
+
+    #[test]
+    fn test_jobs() {
+        let (_dir, gconn) = test_guild_conn();
+        let now = Utc.timestamp(1_000_000, 0);
+        let minute = Duration::seconds(60);
+
+        let once = gconn.schedule_job("unban", "1", &(now - minute), None).unwrap();
+        let every = gconn.schedule_job("digest", "", &now, Some(minute)).unwrap();
+        gconn.schedule_job("unban", "2", &(now + minute), None).unwrap();
+
+        let due = gconn.due_jobs(&now).unwrap();
+        assert_eq!(due.iter().map(|j| j.id).collect::<Vec<_>>(), vec![once, every]);
+
+        // Recurring jobs skip the runs they missed.
+        gconn.finish_job(&due[0], &now).unwrap();
+        gconn.finish_job(&due[1], &(now + minute * 2 + Duration::seconds(5))).unwrap();
+        let jobs = gconn.jobs(None).unwrap();
+        assert_eq!(jobs.len(), 2);
+        assert_eq!(jobs[1].id, every);
+        assert_eq!(jobs[1].run_at, now + minute * 3);
+
+        gconn.retry_job(&jobs[0], &(now + minute * 5)).unwrap();
+        assert_eq!(gconn.jobs(Some("unban")).unwrap()[0].attempts, 1);
+        assert_eq!(gconn.cancel_jobs("unban", "2").unwrap(), 1);
+        assert!(gconn.remove_job(every).unwrap());
+        assert!(gconn.jobs(None).unwrap().is_empty());
+    }
+
+    #[test]
+    fn test_next_run() {
+        let start = Utc.timestamp(1_000_000, 0);
+        let mut job = Job {
+            id: 1,
+            kind: "digest".to_string(),
+            payload: String::new(),
+            run_at: start,
+            interval: Some(Duration::seconds(1)),
+            attempts: 0,
+        };
+
+        assert_eq!(job.next_run(&(start - Duration::seconds(30))), Some(start + Duration::seconds(1)));
+        assert_eq!(job.next_run(&start), Some(start + Duration::seconds(1)));
+        // Far more missed runs than fit in an i32.
+        let later = start + Duration::seconds(i64::from(i32::MAX) * 4 + 10);
+        assert_eq!(job.next_run(&later), Some(later + Duration::seconds(1)));
+
+        job.interval = Some(Duration::seconds(60));
+        assert_eq!(job.next_run(&(start + Duration::seconds(150))), Some(start + Duration::seconds(180)));
+
+        job.interval = None;
+        assert_eq!(job.next_run(&start), None);
+    }
+
+    #[test]
+    fn test_duplicate_senders() {
+        let (_dir, gconn) = test_guild_conn();
+        let now = Utc::now();
+        let (a, b) = (ChannelId(1), ChannelId(2));
+        let fp = Some(u64::MAX - 1);
+
+        gconn.add_message(UserId(1), a, MessageId(1), 10, fp, &now).unwrap();
+        gconn.add_message(UserId(1), a, MessageId(2), 10, fp, &now).unwrap();
+        gconn.add_message(UserId(2), b, MessageId(3), 10, fp, &now).unwrap();
+        gconn.add_message(UserId(3), a, MessageId(4), 10, fp, &(now - Duration::seconds(60))).unwrap();
+        gconn.add_message(UserId(4), a, MessageId(5), 10, Some(7), &now).unwrap();
+
+        let since = now - Duration::seconds(30);
+        assert_eq!(gconn.count_duplicate_senders(u64::MAX - 1, &since, None).unwrap(), 2);
+        assert_eq!(gconn.count_duplicate_senders(u64::MAX - 1, &since, Some(a)).unwrap(), 1);
+        assert_eq!(gconn.count_duplicate_senders(u64::MAX - 1, &(now - Duration::seconds(90)), Some(a)).unwrap(), 2);
+    }
+
+    #[test]
+    fn test_link_rules() {
+        let (_dir, gconn) = test_guild_conn();
+
+        gconn.set_link_rule("*.example.com", false).unwrap();
+        gconn.set_link_rule("docs.rs", true).unwrap();
+        gconn.set_link_rule("*.example.com", true).unwrap();
+        let rules = gconn.link_rules().unwrap();
+        assert_eq!(rules, vec![
+            LinkRule { domain: "*.example.com".to_string(), allow: true },
+            LinkRule { domain: "docs.rs".to_string(), allow: true },
+        ]);
+
+        assert!(gconn.remove_link_rule("docs.rs").unwrap());
+        assert!(!gconn.remove_link_rule("docs.rs").unwrap());
+        assert_eq!(gconn.link_rules().unwrap().len(), 1);
+    }
+
+    #[test]
+    fn test_filter_rules() {
+        let (_dir, gconn) = test_guild_conn();
+
+        let a = gconn.add_filter_rule("bad word", true, FilterAction::Delete, &[ChannelId(1)], &[RoleId(2), RoleId(3)]).unwrap();
+        let b = gconn.add_filter_rule("^spam+$", false, FilterAction::Log, &[], &[]).unwrap();
+        let rules = gconn.filter_rules().unwrap();
+        assert_eq!(rules.len(), 2);
+        assert_eq!(rules[0].id, a);
+        assert_eq!(rules[0].exempt_channels, vec![ChannelId(1)]);
+        assert_eq!(rules[0].exempt_roles, vec![RoleId(2), RoleId(3)]);
+        assert_eq!(rules[1].action, FilterAction::Log);
+
+        assert!(gconn.remove_filter_rule(a).unwrap());
+        assert!(!gconn.remove_filter_rule(a).unwrap());
+        assert_eq!(gconn.filter_rules().unwrap().iter().map(|r| r.id).collect::<Vec<_>>(), vec![b]);
+        let exemptions: i64 = gconn.as_ref().query_row("SELECT COUNT(*) FROM filter_exemptions;", NO_PARAMS, |r| r.get(0)).unwrap();
+        assert_eq!(exemptions, 0);
+
+        let now = Utc::now();
+        gconn.add_message(UserId(1), ChannelId(1), MessageId(1), 10, None, &now).unwrap();
+        assert!(gconn.add_pressure(UserId(1), MessageId(1), 25).unwrap());
+        assert!(!gconn.add_pressure(UserId(1), MessageId(2), 25).unwrap());
+        assert_eq!(user_pressure(&(now - Duration::seconds(1)), UserId(1), gconn.as_ref()).unwrap(), 35);
+    }
+
+    #[test]
+    fn test_gate_pending() {
+        let (_dir, gconn) = test_guild_conn();
+        let now = Utc.timestamp(1_000_000, 0);
+
+        assert!(gconn.add_gate_pending(UserId(2), &now).unwrap());
+        assert!(gconn.add_gate_pending(UserId(1), &(now - Duration::seconds(5))).unwrap());
+        assert!(!gconn.add_gate_pending(UserId(2), &now).unwrap());
+        assert!(gconn.is_gate_pending(UserId(2)).unwrap());
+        assert_eq!(gconn.gate_pending().unwrap(), vec![(UserId(1), now - Duration::seconds(5)), (UserId(2), now)]);
+
+        assert!(gconn.remove_gate_pending(UserId(2)).unwrap());
+        assert!(!gconn.remove_gate_pending(UserId(2)).unwrap());
+        assert!(!gconn.is_gate_pending(UserId(2)).unwrap());
+    }
+
+    #[test]
+    fn test_reaction_roles() {
+        let (_dir, gconn) = test_guild_conn();
+        let (channel, message, other) = (ChannelId(1), MessageId(2), MessageId(3));
+
+        gconn.bind_reaction_role(channel, message, "\u{1f34e}", "\u{1f34e}", RoleId(10)).unwrap();
+        gconn.bind_reaction_role(channel, message, "55", "<:pear:55>", RoleId(11)).unwrap();
+        gconn.bind_reaction_role(channel, other, "55", "<:pear:55>", RoleId(12)).unwrap();
+        assert!(gconn.set_reaction_roles_exclusive(message, true).unwrap());
+        assert!(!gconn.set_reaction_roles_exclusive(MessageId(4), true).unwrap());
+
+        let bound = gconn.reaction_roles(Some(message)).unwrap();
+        assert_eq!(bound.iter().map(|r| r.role).collect::<Vec<_>>(), vec![RoleId(10), RoleId(11)]);
+        assert!(bound.iter().all(|r| r.exclusive && r.channel == channel));
+        assert_eq!(gconn.reaction_roles(None).unwrap().len(), 3);
+
+        assert!(gconn.unbind_reaction_role(other, "55").unwrap());
+        assert!(!gconn.unbind_reaction_role(other, "55").unwrap());
+        let messages: i64 = gconn.as_ref().query_row("SELECT COUNT(*) FROM reaction_role_messages;", NO_PARAMS, |r| r.get(0)).unwrap();
+        assert_eq!(messages, 1);
+    }
+
+    #[test]
+    fn test_role_groups() {
+        let (_dir, gconn) = test_guild_conn();
+
+        gconn.set_role_joinable(RoleId(1), Some("pronouns")).unwrap();
+        gconn.set_role_joinable(RoleId(2), Some("pronouns")).unwrap();
+        gconn.set_role_joinable(RoleId(3), None).unwrap();
+        assert!(gconn.set_role_group_options("pronouns", true, Some(1)).unwrap());
+        assert!(!gconn.set_role_group_options("region", true, None).unwrap());
+        assert!(gconn.role_is_joinable(RoleId(3)).unwrap());
+
+        let groups = gconn.role_groups().unwrap();
+        assert_eq!(groups, vec![RoleGroup {
+            name: "pronouns".to_string(),
+            exclusive: true,
+            max: Some(1),
+            roles: vec![RoleId(1), RoleId(2)],
+        }]);
+
+        // Moving every role out of a group drops it.
+        gconn.set_role_joinable(RoleId(1), Some("region")).unwrap();
+        assert!(gconn.remove_joinable_role(RoleId(2)).unwrap());
+        assert!(!gconn.remove_joinable_role(RoleId(2)).unwrap());
+        let names: Vec<_> = gconn.role_groups().unwrap().into_iter().map(|g| g.name).collect();
+        assert_eq!(names, vec!["region".to_string()]);
+        assert_eq!(gconn.joinable_roles().unwrap(), vec![(RoleId(3), None), (RoleId(1), Some("region".to_string()))]);
+    }
+
+    #[test]
+    fn test_command_aliases() {
+        let (_dir, gconn) = test_guild_conn();
+
+        assert_eq!(gconn.command_alias("j").unwrap(), None);
+        gconn.set_command_alias("j", "me join-role").unwrap();
+        gconn.set_command_alias("l", "me leave").unwrap();
+        gconn.set_command_alias("l", "me leave-role").unwrap();
+        assert_eq!(gconn.command_alias("j").unwrap().as_deref(), Some("me join-role"));
+        assert_eq!(gconn.command_aliases().unwrap(), vec![
+            ("j".to_string(), "me join-role".to_string()),
+            ("l".to_string(), "me leave-role".to_string()),
+        ]);
+
+        assert!(gconn.remove_command_alias("j").unwrap());
+        assert!(!gconn.remove_command_alias("j").unwrap());
+        assert_eq!(gconn.command_aliases().unwrap().len(), 1);
+    }
+
+    #[test]
+    fn test_config_values() {
+        let (_dir, gconn) = test_guild_conn();
+
+        gconn.set_value("spam_enabled", "true").unwrap();
+        let values = gconn.config_values().unwrap();
+        assert_eq!(values.get("spam_enabled").map(String::as_str), Some("true"));
+        assert_eq!(values.get("command_prefix").map(String::as_str), Some("!"));
+
+        assert!(gconn.remove_value("spam_enabled").unwrap());
+        assert!(!gconn.remove_value("spam_enabled").unwrap());
+        assert!(gconn.get_value("spam_enabled").unwrap_err().no_rows_returned());
+    }
+
+    #[test]
+    fn test_snapshot() {
+        let (_dir, gconn) = test_guild_conn();
+
+        gconn.set_value("spam_enabled", "true").unwrap();
+        gconn.set_command_restricted("ping", true).unwrap();
+        gconn.set_role_joinable(RoleId(1), None).unwrap();
+        gconn.set_role_joinable(RoleId(2), Some("region")).unwrap();
+        gconn.set_role_group_options("region", true, Some(1)).unwrap();
+        gconn.add_filter_rule("bad", true, FilterAction::Delete, &[ChannelId(5)], &[RoleId(6)]).unwrap();
+        let snapshot = gconn.snapshot().unwrap();
+        assert_eq!(snapshot.joinable_roles, vec![RoleId(1)]);
+        assert_eq!(snapshot.role_groups[0].roles, vec![RoleId(2)]);
+
+        gconn.set_value("spam_enabled", "false").unwrap();
+        gconn.set_command_restricted("ping", false).unwrap();
+        gconn.remove_joinable_role(RoleId(2)).unwrap();
+        gconn.remove_filter_rule(1).unwrap();
+        gconn.add_filter_rule("worse", false, FilterAction::Warn, &[], &[]).unwrap();
+        assert_ne!(gconn.snapshot().unwrap(), snapshot);
+
+        gconn.apply_snapshot(&snapshot, &["spam_enabled", "command_prefix"]).unwrap();
+        assert_eq!(gconn.snapshot().unwrap(), snapshot);
+
+        // Keys the importer doesn't know about survive, while known keys missing from the snapshot go.
+        gconn.set_value("unloaded_module_key", "5").unwrap();
+        let mut without_spam = snapshot.clone();
+        without_spam.config.remove("spam_enabled");
+        gconn.apply_snapshot(&without_spam, &["spam_enabled", "command_prefix"]).unwrap();
+        assert_eq!(gconn.get_value("unloaded_module_key").unwrap(), "5");
+        assert!(gconn.get_value("spam_enabled").unwrap_err().no_rows_returned());
+        assert_eq!(gconn.command_prefixes().unwrap(), vec!["!"]);
     }
 }
\ No newline at end of file
@@ -80,7 +80,7 @@ pub enum DatabaseVersion {
 }
 
 mod guild_conn;
-pub use guild_conn::GuildConn;
+pub use guild_conn::{GuildConn, PermissionOverride, OverrideTarget, Infraction, Job, LinkRule, FilterRule, ReactionRole, RoleGroup, Snapshot, Tier, FilterAction};
 use crate::error::BotError;
 
 impl PartialOrd for DatabaseVersion {
@@ -437,6 +437,38 @@ pub fn user_pressure(since: &DateTime<Utc>, u: UserId, conn: &Connection) -> Res
     ).map_err(DatabaseError::from)
 }
 
+/// The number of rows [prune_messages] deletes per statement, so writers aren't blocked for long.
+pub const PRUNE_BATCH_SIZE: i64 = 1000;
+
+/// Deletes the messages recorded before the given time in small batches, then returns the freed
+/// pages to the filesystem. Returns the number of messages deleted.
+///
+/// Databases created before incremental vacuuming was enabled are converted with a full VACUUM
+/// the first time they are pruned.
+pub fn prune_messages(conn: &Connection, before: &DateTime<Utc>) -> Result<usize> {
+    let mut total = 0;
+    loop {
+        let n = conn.execute(
+            "DELETE FROM messages WHERE rowid IN (SELECT rowid FROM messages WHERE unix_time < ? LIMIT ?);",
+            params![before.timestamp(), PRUNE_BATCH_SIZE]
+        )?;
+        total += n;
+        if (n as i64) < PRUNE_BATCH_SIZE {
+            break;
+        }
+    }
+
+    let auto_vacuum: i64 = conn.query_row("PRAGMA auto_vacuum;", NO_PARAMS, |r| r.get(0))?;
+    if auto_vacuum == 2 {
+        conn.execute_batch("PRAGMA incremental_vacuum;")?;
+    } else {
+        debug!("Converting database to incremental vacuuming.");
+        conn.execute_batch("PRAGMA auto_vacuum = INCREMENTAL; VACUUM;")?;
+    }
+
+    Ok(total)
+}
+
 /// Retrieves the current database version from a guild database.
 pub fn get_db_version(conn: &Connection) -> Result<DatabaseVersion> {
     let v = conn.query_row(
@@ -471,4 +503,66 @@ mod tests {
         upgrade(&mut dummy_conn, None).unwrap();
         assert_eq!(get_db_version(&dummy_conn).unwrap(), *DB_VERSION)
     }
+
+    #[test]
+    pub fn test_migration_staff_roles() {
+        let dummy_dir = TempDir::new("migrations").unwrap();
+        let mut dummy_conn = ensure_guild_db(dummy_dir.as_ref(), GuildId::from(u64::MAX)).unwrap();
+        let staff_version = MIGRATIONS.iter().position(|m| m.contains("staff-roles")).unwrap() as u32;
+        upgrade(&mut dummy_conn, Some(DatabaseVersion::Version(staff_version - 1))).unwrap();
+        dummy_conn.execute("INSERT INTO guild_config VALUES ('admin_role', '<@&42>');", NO_PARAMS).unwrap();
+
+        upgrade(&mut dummy_conn, Some(DatabaseVersion::Version(staff_version))).unwrap();
+        let (role, tier): (i64, i64) = dummy_conn.query_row("SELECT role, tier FROM staff_roles;", NO_PARAMS,
+                                                            |r| Ok((r.get(0)?, r.get(1)?))).unwrap();
+        assert_eq!((role, tier), (42, 2));
+
+        downgrade(&mut dummy_conn, DatabaseVersion::Version(staff_version)).unwrap();
+        let admin: String = dummy_conn.query_row("SELECT value FROM guild_config WHERE key = 'admin_role';", NO_PARAMS,
+                                                 |r| r.get(0)).unwrap();
+        assert_eq!(admin, "42");
+    }
+
+    #[test]
+    pub fn test_migration_jobs() {
+        let dummy_dir = TempDir::new("migrations").unwrap();
+        let mut dummy_conn = ensure_guild_db(dummy_dir.as_ref(), GuildId::from(u64::MAX)).unwrap();
+        let jobs_version = MIGRATIONS.iter().position(|m| m.contains("jobs")).unwrap() as u32;
+        upgrade(&mut dummy_conn, Some(DatabaseVersion::Version(jobs_version - 1))).unwrap();
+        dummy_conn.execute("INSERT INTO expirations VALUES (1, 'mute', 5, 100), (2, 'ban', 0, 200);", NO_PARAMS).unwrap();
+
+        upgrade(&mut dummy_conn, Some(DatabaseVersion::Version(jobs_version))).unwrap();
+        let mut stmt = dummy_conn.prepare("SELECT kind, payload, run_at FROM jobs ORDER BY run_at;").unwrap();
+        let jobs = stmt.query_map(NO_PARAMS, |r| Ok((r.get(0)?, r.get(1)?, r.get(2)?)))
+            .unwrap()
+            .collect::<rusqlite::Result<Vec<(String, String, i64)>>>()
+            .unwrap();
+        assert_eq!(jobs, vec![("unmute".to_string(), "1 5".to_string(), 100), ("unban".to_string(), "2".to_string(), 200)]);
+        drop(stmt);
+
+        downgrade(&mut dummy_conn, DatabaseVersion::Version(jobs_version)).unwrap();
+        let count: i64 = dummy_conn.query_row("SELECT COUNT(*) FROM expirations WHERE (user, kind, role, unix_time) IN (VALUES (1, 'mute', 5, 100), (2, 'ban', 0, 200));",
+                                              NO_PARAMS, |r| r.get(0)).unwrap();
+        assert_eq!(count, 2);
+    }
+
+    #[test]
+    pub fn test_prune_messages() {
+        let dummy_dir = TempDir::new("migrations").unwrap();
+        let mut dummy_conn = ensure_guild_db(dummy_dir.as_ref(), GuildId::from(u64::MAX)).unwrap();
+        init_guild_db(&mut dummy_conn).unwrap();
+        let now = Utc::now();
+        let old = (now - chrono::Duration::days(2)).timestamp();
+        for i in 0..(PRUNE_BATCH_SIZE * 2 + 10) {
+            dummy_conn.execute("INSERT INTO messages (user, message, unix_time) VALUES (1, ?, ?);", params![i, old]).unwrap();
+        }
+        dummy_conn.execute("INSERT INTO messages (user, message, unix_time) VALUES (1, -1, ?);", params![now.timestamp()]).unwrap();
+
+        let deleted = prune_messages(&dummy_conn, &(now - chrono::Duration::days(1))).unwrap();
+        assert_eq!(deleted as i64, PRUNE_BATCH_SIZE * 2 + 10);
+        let left: i64 = dummy_conn.query_row("SELECT COUNT(*) FROM messages;", NO_PARAMS, |r| r.get(0)).unwrap();
+        assert_eq!(left, 1);
+        let auto_vacuum: i64 = dummy_conn.query_row("PRAGMA auto_vacuum;", NO_PARAMS, |r| r.get(0)).unwrap();
+        assert_eq!(auto_vacuum, 2);
+    }
 }
\ No newline at end of file
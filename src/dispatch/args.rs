@@ -24,6 +24,21 @@ use crate::modules::no_bot::deny_bot_mod;
 use crate::modules::config::config_mod;
 use crate::modules::roles::roles_module;
 use crate::modules::me::me_mod;
+use crate::modules::spam::spam_mod;
+use crate::modules::help::help_mod;
+use crate::modules::permissions::permissions_mod;
+use crate::modules::overrides::overrides_mod;
+use crate::modules::modlog::modlog_mod;
+use crate::modules::punishment::punishment_mod;
+use crate::modules::maintenance::maintenance_mod;
+use crate::modules::infractions::{warn_mod, infractions_mod};
+use crate::modules::sanctions::{mute_mod, tempban_mod};
+use crate::modules::links::links_mod;
+use crate::modules::filter::filter_mod;
+use crate::modules::raid::raid_mod;
+use crate::modules::gate::{gate_mod, verify_mod};
+use crate::modules::reaction_roles::reaction_roles_mod;
+use crate::modules::alias::alias_mod;
 
 #[doc(hidden)]
 pub fn command_parser() -> App<'static, 'static> {
@@ -40,10 +55,28 @@ pub fn handle_matches(m: &ArgMatches) -> anyhow::Result<()> {
             .with_module(base_hooks())
             .with_module(deny_bot_mod())
             .with_module(config_mod())
+            .with_module(modlog_mod())
+            .with_module(punishment_mod())
+            .with_module(maintenance_mod())
             .with_module(roles_module())
             .with_module(ping_module())
-            .with_module(me_mod());
-        let mut client = Client::new(token, dispatch)?;
+            .with_module(me_mod())
+            .with_module(spam_mod())
+            .with_module(help_mod())
+            .with_module(permissions_mod())
+            .with_module(overrides_mod())
+            .with_module(warn_mod())
+            .with_module(infractions_mod())
+            .with_module(mute_mod())
+            .with_module(tempban_mod())
+            .with_module(links_mod())
+            .with_module(filter_mod())
+            .with_module(raid_mod())
+            .with_module(gate_mod())
+            .with_module(verify_mod())
+            .with_module(reaction_roles_mod())
+            .with_module(alias_mod());
+        let mut client = Client::new(token, super::Handler::from(dispatch))?;
         client.start_autosharded()?;
     }
 
@@ -22,11 +22,16 @@ use crate::util::LogErrorExt;
 use crate::db::cache::get_cached_connection;
 use serenity::model::prelude::UserId;
 use std::sync::atomic::AtomicU64;
-use crate::modules::hook::CommandHookFn;
+use std::sync::Arc;
+use crate::modules::hook::{CommandHookFn, MessageHookFn, EventHookFn, JobHandlerFn, Event};
 use std::collections::{HashMap, HashSet};
 use crate::modules::{Module, hook};
 use crate::modules::commands::Command;
-use serenity::model::channel::Message;
+use serenity::model::channel::{Message, Reaction};
+use serenity::model::guild::{Member, Guild};
+use serenity::model::event::MessageUpdateEvent;
+use serenity::model::id::{GuildId, ChannelId, MessageId};
+use serenity::model::user::User;
 use once_cell::unsync::Lazy;
 use regex::Regex;
 use crate::error::{BotResult, BotError};
@@ -34,29 +39,78 @@ use serenity::utils::MessageBuilder;
 use std::borrow::Cow;
 use crate::db::GuildConn;
 use crate::modules::config;
-use crate::modules::config::Validator;
+use crate::modules::config::{Validator, FromConfig};
+use crate::dispatch::scheduler::{Scheduler, Clock, SystemClock};
+use crate::modules::base_hooks::COMMAND_PREFIX_KEY;
 
 pub mod args;
+pub mod scheduler;
 
-/// The primary event handler for Glimbot. Contains references to transient state for the bot.
+/// The primary dispatcher for Glimbot, which routes events to modules. Contains references to transient state for the bot.
 /// Non-transient data should live in the databases.
 pub struct Dispatch {
     owner: AtomicU64,
     modules: HashMap<String, Module>,
+    aliases: HashMap<String, String>,
     command_hooks: Vec<CommandHookFn>,
-    config_validator: config::Validator
+    message_hooks: Vec<MessageHookFn>,
+    event_hooks: Vec<EventHookFn>,
+    job_handlers: HashMap<String, JobHandlerFn>,
+    config_validator: config::Validator,
+    scheduler: Scheduler
+}
+
+/// The serenity event handler for Glimbot, which shares its [Dispatch] with the job scheduler.
+pub struct Handler(Arc<Dispatch>);
+
+impl From<Dispatch> for Handler {
+    fn from(d: Dispatch) -> Self {
+        Handler(Arc::new(d))
+    }
 }
 
 // Thread local because Regex just uses an interior mutex if used in multiple threads, blegh.
 thread_local! {
     static CMD_REGEX: Lazy<Regex> = Lazy::new(
-        || Regex::new(r#"^([\p{Math Symbol}\p{Punctuation}])(\w+)(?:\s*)(.*)"#).unwrap()
+        || Regex::new(r#"^(\w+)(?:\s*)(.*)"#).unwrap()
     );
 }
 
-impl EventHandler for Dispatch {
+/// Strips a command prefix from the start of a message. The longest matching prefix wins, and
+/// mentioning the bot (followed by whitespace) always counts as a prefix.
+fn strip_prefix<'a>(content: &'a str, prefixes: &[String], bot: UserId) -> Option<&'a str> {
+    let mentioned = [format!("<@{}>", bot), format!("<@!{}>", bot)].iter()
+        .find_map(|m| content.strip_prefix(m.as_str()))
+        .filter(|rest| rest.starts_with(char::is_whitespace))
+        .map(str::trim_start);
+
+    mentioned.or_else(|| prefixes.iter()
+        .filter(|p| !p.is_empty())
+        .filter_map(|p| content.strip_prefix(p.as_str()))
+        .min_by_key(|rest| rest.len()))
+}
+
+/// A command invocation, after checking the prefix and expanding aliases.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Invocation {
+    /// The canonical name of the command, or the name as typed if no command matched.
+    pub name: String,
+    /// The argument string, including any arguments supplied by a guild alias.
+    pub args: String,
+}
+
+/// Splits a string into its first word and the (trimmed) rest.
+fn split_command(s: &str) -> (&str, &str) {
+    let s = s.trim();
+    match s.find(char::is_whitespace) {
+        Some(i) => (&s[..i], s[i..].trim_start()),
+        None => (s, "")
+    }
+}
+
+impl EventHandler for Handler {
     fn message(&self, ctx: Context, new_message: Message) {
-        let res = self.handle_message(&ctx, &new_message);
+        let res = self.0.handle_message(&ctx, &new_message);
 
         if let Err(e) = res {
             let msg = if e.is_user_error() {
@@ -75,6 +129,42 @@ impl EventHandler for Dispatch {
         }
     }
 
+    fn guild_create(&self, ctx: Context, guild: Guild, is_new: bool) {
+        self.0.handle_event(&ctx, &Event::GuildCreate { guild: &guild, is_new });
+    }
+
+    fn guild_member_addition(&self, ctx: Context, guild_id: GuildId, new_member: Member) {
+        self.0.handle_event(&ctx, &Event::MemberAdd { guild: guild_id, member: &new_member });
+    }
+
+    fn guild_member_removal(&self, ctx: Context, guild: GuildId, user: User, member_data_if_available: Option<Member>) {
+        self.0.handle_event(&ctx, &Event::MemberRemove {
+            guild,
+            user: &user,
+            member: member_data_if_available.as_ref()
+        });
+    }
+
+    fn message_delete(&self, ctx: Context, channel_id: ChannelId, deleted_message_id: MessageId) {
+        self.0.handle_event(&ctx, &Event::MessageDelete { channel: channel_id, message: deleted_message_id });
+    }
+
+    fn message_update(&self, ctx: Context, old_if_available: Option<Message>, new: Option<Message>, event: MessageUpdateEvent) {
+        self.0.handle_event(&ctx, &Event::MessageUpdate {
+            old: old_if_available.as_ref(),
+            new: new.as_ref(),
+            event: &event
+        });
+    }
+
+    fn reaction_add(&self, ctx: Context, add_reaction: Reaction) {
+        self.0.handle_event(&ctx, &Event::ReactionAdd(&add_reaction));
+    }
+
+    fn reaction_remove(&self, ctx: Context, removed_reaction: Reaction) {
+        self.0.handle_event(&ctx, &Event::ReactionRemove(&removed_reaction));
+    }
+
     fn ready(&self, ctx: Context, data_about_bot: Ready) {
         ctx.set_activity(Activity::playing("Cultist Simulator"));
         let active_guilds = &data_about_bot.guilds;
@@ -84,6 +174,9 @@ impl EventHandler for Dispatch {
 
 
         info!("Glimbot is up and running in at least {} servers.", active_guilds.len());
+
+        // Ready fires again on reconnect and once per shard; the scheduler only starts once.
+        Scheduler::start(self.0.clone(), ctx);
     }
 }
 
@@ -99,7 +192,8 @@ pub enum KeyRetrievalError {
 }
 
 impl KeyRetrievalError {
-    fn missing_key(&self) -> bool {
+    /// Returns true if the key is valid, but was never set for the guild.
+    pub fn missing_key(&self) -> bool {
         match self {
             KeyRetrievalError::SQLError(d) => {d.no_rows_returned()},
             KeyRetrievalError::ConfigError(_) => false,
@@ -138,8 +232,13 @@ impl Dispatch {
         Dispatch {
             owner: AtomicU64::new(*owner.as_u64()),
             command_hooks: Vec::new(),
+            message_hooks: Vec::new(),
+            event_hooks: Vec::new(),
             modules: HashMap::new(),
-            config_validator: Validator::new()
+            aliases: HashMap::new(),
+            job_handlers: HashMap::new(),
+            config_validator: Validator::new(),
+            scheduler: Scheduler::new(Arc::new(SystemClock))
         }
     }
 
@@ -151,52 +250,84 @@ impl Dispatch {
         }
         trace!("Saw a message from user {}", &new_message.author);
 
-        let msg = &new_message.content;
+        self.message_hooks.iter()
+            .for_each(|h| h(self, ctx, new_message).log_error());
 
-        if CMD_REGEX.with(|r|r.is_match(msg)) {
+        if let Some(inv) = self.parse_command(ctx, new_message)? {
             // It's a command (probably).
             trace!("It's a command, probably.");
-            let m = CMD_REGEX.with(|r|r.captures(msg)).unwrap();
-            let sym = m.get(1).unwrap();
-            let req_sym = if let Some(g) = &new_message.guild_id {
-                let conn = get_cached_connection(*g)?;
-                let r = conn.borrow();
-                r.command_prefix()?
-            } else {
-                '!'
-            };
-
-            if sym.as_str().chars().next().unwrap() != req_sym {
-                return Ok(());
-            }
-
-            let command_group = m.get(2).unwrap();
-
             let command_name: Cow<str> = self.command_hooks.iter().try_fold(
-                Cow::Borrowed(command_group.as_str()),
+                Cow::Borrowed(inv.name.as_str()),
                 |acc, next: &CommandHookFn| next(self, ctx, new_message, acc)
             )?;
 
             let cmd = self.resolve_command(&command_name)
                 .ok_or_else(|| hook::Error::CommandNotFound(command_name.into_owned()))?;
 
-            let args = m.get(3).unwrap().as_str().trim().to_string();
-            cmd.invoke(self, ctx, new_message, Cow::Owned(args))?;
+            cmd.invoke(self, ctx, new_message, Cow::Owned(inv.args))?;
         }
 
 
         Ok(())
     }
 
+    /// Parses a message into a command invocation, if it starts with one of the guild's command prefixes
+    /// or a mention of the bot. Outside of guilds the default prefixes apply.
+    /// Module aliases resolve to the module's command, and guild aliases expand into a command plus arguments.
+    pub fn parse_command(&self, ctx: &Context, msg: &Message) -> BotResult<Option<Invocation>> {
+        let conn = msg.guild_id.map(get_cached_connection).transpose()?;
+        let prefixes = match &conn {
+            Some(c) => self.get_config::<Vec<String>>(&c.borrow(), COMMAND_PREFIX_KEY)?,
+            None => self.config_validator.default_for(COMMAND_PREFIX_KEY)
+                .map(|d| d.split_whitespace().map(String::from).collect())
+                .unwrap_or_default()
+        };
+
+        let bot = ctx.cache.read().user.id;
+        let m = match strip_prefix(&msg.content, &prefixes, bot).and_then(|rest| CMD_REGEX.with(|r| r.captures(rest))) {
+            Some(m) => m,
+            None => return Ok(None)
+        };
+
+        let name = m.get(1).unwrap().as_str();
+        let args = m.get(2).unwrap().as_str().trim();
+        if let Some(canonical) = self.canonical_command(name) {
+            return Ok(Some(Invocation { name: canonical.to_string(), args: args.to_string() }));
+        }
+
+        let expansion = match &conn {
+            Some(c) => c.borrow().command_alias(name)?,
+            None => None
+        };
+        let inv = match expansion.as_deref().map(split_command) {
+            Some((cmd, extra)) => Invocation {
+                name: self.canonical_command(cmd).unwrap_or(cmd).to_string(),
+                args: format!("{} {}", extra, args).trim().to_string(),
+            },
+            None => Invocation { name: name.to_string(), args: args.to_string() }
+        };
+        Ok(Some(inv))
+    }
+
+    /// Fans an event out to every event hook. Errors are logged, as with message hooks.
+    pub fn handle_event(&self, ctx: &Context, event: &Event) {
+        trace!("Saw event {}", event);
+        self.event_hooks.iter()
+            .for_each(|h| h(self, ctx, event).log_error());
+    }
+
     /// Adds a module to the dispatcher.
     pub fn with_module(mut self, m: Module) -> Self {
-        info!("Loading module {} with {} command, {} hooks, {} config values.", m.name(),
+        info!("Loading module {} with {} command, {} hooks, {} message hooks, {} event hooks, {} job handlers, {} config values.", m.name(),
             if m.command_handler().is_some() {
                 "a"
             } else {
                 "no"
             },
             m.command_hooks().len(),
+            m.message_hooks().len(),
+            m.event_hooks().len(),
+            m.job_handlers().len(),
             m.config_values().len()
         );
 
@@ -216,7 +347,24 @@ impl Dispatch {
             )
         }
 
+        if let Some(owner) = self.name_owner(m.name()) {
+            panic!("Attempted to load module {}, but its name is already used by module {}.", m.name(), owner)
+        }
+        for alias in m.aliases() {
+            if let Some(owner) = self.name_owner(alias).or_else(|| Some(m.name()).filter(|n| n == alias)) {
+                panic!("Attempted to load module {}, but its alias {} is already used by module {}.", m.name(), alias, owner)
+            }
+            self.aliases.insert(alias.clone(), m.name().to_owned());
+        }
+
         self.command_hooks.extend(m.command_hooks().iter());
+        self.message_hooks.extend(m.message_hooks().iter());
+        self.event_hooks.extend(m.event_hooks().iter());
+        for (kind, f) in m.job_handlers() {
+            if self.job_handlers.insert(kind.clone(), *f).is_some() {
+                panic!("Attempted to load module {}, but another module already handles {} jobs.", m.name(), kind)
+            }
+        }
         m.config_values().iter().for_each(|v| {
             debug!("Added config key {}", v.name());
             self.config_validator.add_value(v.clone())
@@ -225,11 +373,39 @@ impl Dispatch {
         self
     }
 
-    /// Resolves the given name to a command, if it exists.
+    /// Replaces the clock used by the job scheduler.
+    pub fn with_clock(mut self, clock: Arc<dyn Clock>) -> Self {
+        self.scheduler = Scheduler::new(clock);
+        self
+    }
+
+    /// Accessor for the job scheduler.
+    pub fn scheduler(&self) -> &Scheduler {
+        &self.scheduler
+    }
+
+    /// Retrieves the handler for the given kind of job, if any module provides one.
+    pub fn job_handler(&self, kind: impl AsRef<str>) -> Option<JobHandlerFn> {
+        self.job_handlers.get(kind.as_ref()).copied()
+    }
+
+    /// Finds the module a name or module alias belongs to.
+    fn name_owner(&self, name: &str) -> Option<&str> {
+        self.modules.get_key_value(name)
+            .map(|(k, _)| k.as_str())
+            .or_else(|| self.aliases.get(name).map(String::as_str))
+    }
+
+    /// Resolves a command name or module alias to the command's canonical name, if it exists.
+    pub fn canonical_command(&self, cmd: impl AsRef<str>) -> Option<&str> {
+        self.name_owner(cmd.as_ref())
+            .filter(|n| self.modules[*n].command_handler().is_some())
+    }
+
+    /// Resolves the given name or module alias to a command, if it exists.
     pub fn resolve_command(&self, cmd: impl AsRef<str>) -> Option<&dyn Command> {
-        self.modules
-            .get(cmd.as_ref())
-            .and_then(|x|x.command_handler())
+        self.canonical_command(cmd)
+            .and_then(|n| self.modules[n].command_handler())
             .map(|x|x.as_ref())
     }
 
@@ -240,11 +416,35 @@ impl Dispatch {
         Ok(())
     }
 
-    /// Gets the config value. Fails if the key doesn't exist.
-    pub fn get_config(&self, conn: &GuildConn, key: impl AsRef<str>) -> Result<String, KeyRetrievalError> {
-        self.config_validator.check_key(key.as_ref())?;
-        let o = conn.get_value(key)?;
-        Ok(o)
+    /// Gets the config value as the given type, falling back to the key's default if it was never set.
+    /// Fails if the key doesn't exist, or was never set and has no default. Malformed stored values are
+    /// logged and replaced by the default; without a default they are an error.
+    pub fn get_config<T: FromConfig>(&self, conn: &GuildConn, key: impl AsRef<str>) -> Result<T, KeyRetrievalError> {
+        let key = key.as_ref();
+        self.config_validator.check_key(key)?;
+        let default = self.config_validator.default_for(key).ok();
+
+        match conn.get_value(key) {
+            Ok(s) => match T::from_config(&s) {
+                Some(v) => return Ok(v),
+                None => warn!("Ignoring malformed value {:?} for config key {} in guild {}", s, key, conn.as_id())
+            },
+            Err(e) if e.no_rows_returned() && default.is_some() => {},
+            Err(e) => return Err(e.into())
+        }
+
+        default.and_then(|d| T::from_config(d))
+            .ok_or_else(|| config::Error::Malformed(key.to_string()).into())
+    }
+
+    /// Gets the config value as the given type, or None if it was never set or is malformed.
+    pub fn get_optional_config<T: FromConfig>(&self, conn: &GuildConn, key: impl AsRef<str>) -> Result<Option<T>, KeyRetrievalError> {
+        match self.get_config(conn, key) {
+            Ok(v) => Ok(Some(v)),
+            Err(e) if e.missing_key() => Ok(None),
+            Err(KeyRetrievalError::ConfigError(config::Error::Malformed(_))) => Ok(None),
+            Err(e) => Err(e)
+        }
     }
 
     /// Gets the config value or sets the config value and *then* returns it if it doesn't already exist.
@@ -268,4 +468,94 @@ impl Dispatch {
     pub fn modules(&self) -> &HashMap<String, Module> {
         &self.modules
     }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::modules::ping::Ping;
+    use std::sync::atomic::Ordering;
+    use crate::util::test_context;
+
+    fn module(name: &str) -> Module {
+        Module::with_name(name).clear_dependencies().with_command(Ping)
+    }
+
+    #[test]
+    fn test_aliases() {
+        let disp = Dispatch::new(UserId(1))
+            .with_module(module("ping").with_alias("p"))
+            .with_module(Module::with_name("hooks").clear_dependencies().with_alias("h"));
+
+        assert_eq!(disp.canonical_command("ping"), Some("ping"));
+        assert_eq!(disp.canonical_command("p"), Some("ping"));
+        assert!(disp.resolve_command("p").is_some());
+        assert_eq!(disp.canonical_command("hooks"), None);
+        assert_eq!(disp.canonical_command("h"), None);
+        assert_eq!(disp.canonical_command("pong"), None);
+    }
+
+    #[test]
+    #[should_panic]
+    fn test_alias_collision() {
+        Dispatch::new(UserId(1))
+            .with_module(module("ping"))
+            .with_module(module("pong").with_alias("ping"));
+    }
+
+    #[test]
+    #[should_panic]
+    fn test_name_collision() {
+        Dispatch::new(UserId(1))
+            .with_module(module("pong").with_alias("p"))
+            .with_module(module("p"));
+    }
+
+    #[test]
+    fn test_strip_prefix() {
+        let prefixes = vec!["!".to_string(), "!!".to_string(), "gl!".to_string()];
+        let bot = UserId(42);
+        assert_eq!(strip_prefix("!ping", &prefixes, bot), Some("ping"));
+        assert_eq!(strip_prefix("!!ping", &prefixes, bot), Some("ping"));
+        assert_eq!(strip_prefix("gl!ping x", &prefixes, bot), Some("ping x"));
+        assert_eq!(strip_prefix("<@42> ping", &prefixes, bot), Some("ping"));
+        assert_eq!(strip_prefix("<@!42>  ping", &prefixes, bot), Some("ping"));
+        assert_eq!(strip_prefix("<@42>ping", &prefixes, bot), None);
+        assert_eq!(strip_prefix("<@43> ping", &prefixes, bot), None);
+        assert_eq!(strip_prefix("?ping", &prefixes, bot), None);
+        assert_eq!(strip_prefix("!ping", &[], bot), None);
+    }
+
+    static EVENT_HOOK_CALLS: AtomicU64 = AtomicU64::new(0);
+
+    fn failing_event_hook(_disp: &Dispatch, _ctx: &Context, _event: &Event) -> hook::Result<()> {
+        EVENT_HOOK_CALLS.fetch_add(1, Ordering::SeqCst);
+        Err(hook::Error::Denied)
+    }
+
+    fn counting_event_hook(_disp: &Dispatch, _ctx: &Context, event: &Event) -> hook::Result<()> {
+        if let Event::MessageDelete { message, .. } = event {
+            EVENT_HOOK_CALLS.fetch_add(message.0, Ordering::SeqCst);
+        }
+        Ok(())
+    }
+
+    #[test]
+    fn test_handle_event() {
+        let ctx = test_context();
+        let disp = Dispatch::new(UserId(1))
+            .with_module(Module::with_name("a").clear_dependencies().with_event_hook(failing_event_hook))
+            .with_module(Module::with_name("b").clear_dependencies().with_event_hook(counting_event_hook));
+
+        // Every hook sees the event, even after an earlier one fails.
+        disp.handle_event(&ctx, &Event::MessageDelete { channel: ChannelId(2), message: MessageId(10) });
+        assert_eq!(EVENT_HOOK_CALLS.load(Ordering::SeqCst), 11);
+    }
+
+    #[test]
+    fn test_split_command() {
+        assert_eq!(split_command("me join-role"), ("me", "join-role"));
+        assert_eq!(split_command(" me  join-role  x "), ("me", "join-role  x"));
+        assert_eq!(split_command("ping"), ("ping", ""));
+    }
 }
\ No newline at end of file
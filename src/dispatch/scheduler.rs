@@ -0,0 +1,263 @@
+//  Glimbot - A Discord anti-spam and administration bot.
+//  Copyright (C) 2020 Nick Samson
+
+//  This program is free software: you can redistribute it and/or modify
+//  it under the terms of the GNU General Public License as published by
+//  the Free Software Foundation, either version 3 of the License, or
+//  (at your option) any later version.
+
+//  This program is distributed in the hope that it will be useful,
+//  but WITHOUT ANY WARRANTY; without even the implied warranty of
+//  MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+//  GNU General Public License for more details.
+
+//  You should have received a copy of the GNU General Public License
+//  along with this program.  If not, see <https://www.gnu.org/licenses/>.
+
+//! Contains the job scheduler, which runs delayed and recurring [Job]s stored in the guild databases.
+//!
+//! A single thread polls every cached guild for due jobs and hands them to a worker pool, where they
+//! are run by the job handler registered for their kind. Since jobs live in the databases, jobs which
+//! came due while Glimbot was offline are run on the first poll after startup.
+//!
+//! The poll interval (in seconds) and the number of workers are controlled through the environment
+//! variables `GLIMBOT_SCHEDULER_INTERVAL` and `GLIMBOT_JOB_THREADS`, which default to 15 and 4.
+
+use crate::dispatch::Dispatch;
+use crate::db::{GuildConn, Job};
+use crate::db::cache::get_cached_connection;
+use crate::modules::hook;
+use crate::util::LogErrorExt;
+use serenity::prelude::Context;
+use serenity::model::id::GuildId;
+use chrono::{DateTime, Duration, Utc};
+use futures::executor::ThreadPool;
+use once_cell::sync::Lazy;
+use parking_lot::Mutex;
+use std::collections::HashSet;
+use std::sync::Arc;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::thread;
+
+/// The time between polls for due jobs.
+pub static SCHEDULER_INTERVAL: Lazy<std::time::Duration> = Lazy::new(
+    || std::time::Duration::from_secs(std::env::var("GLIMBOT_SCHEDULER_INTERVAL")
+        .unwrap_or_else(|_| "15".to_string())
+        .parse::<u64>()
+        .expect("GLIMBOT_SCHEDULER_INTERVAL must be a valid u64."))
+);
+
+/// The number of worker threads jobs are run on.
+pub static JOB_THREADS: Lazy<usize> = Lazy::new(
+    || std::env::var("GLIMBOT_JOB_THREADS")
+        .unwrap_or_else(|_| "4".to_string())
+        .parse::<usize>()
+        .expect("GLIMBOT_JOB_THREADS must be a valid usize.")
+);
+
+/// The number of times a one-shot job may fail before it is dropped.
+pub const MAX_ATTEMPTS: u32 = 5;
+/// The delay before a failed job is first retried, in seconds. The delay doubles with each failure.
+pub const RETRY_DELAY: i64 = 60;
+
+/// A source of the current time, so the scheduler can be driven by a fake clock in tests.
+pub trait Clock: Send + Sync {
+    /// The current time.
+    fn now(&self) -> DateTime<Utc>;
+}
+
+/// The real wall clock.
+#[derive(Debug, Default, Clone, Copy)]
+pub struct SystemClock;
+
+impl Clock for SystemClock {
+    fn now(&self) -> DateTime<Utc> {
+        Utc::now()
+    }
+}
+
+/// A clock which only moves when told to.
+#[derive(Debug)]
+pub struct ManualClock(Mutex<DateTime<Utc>>);
+
+impl ManualClock {
+    /// Creates a clock stopped at the given time.
+    pub fn new(now: DateTime<Utc>) -> Self {
+        ManualClock(Mutex::new(now))
+    }
+
+    /// Sets the current time.
+    pub fn set(&self, now: DateTime<Utc>) {
+        *self.0.lock() = now;
+    }
+
+    /// Moves the clock forward.
+    pub fn advance(&self, by: Duration) {
+        let mut now = self.0.lock();
+        *now = *now + by;
+    }
+}
+
+impl Clock for ManualClock {
+    fn now(&self) -> DateTime<Utc> {
+        *self.0.lock()
+    }
+}
+
+/// Tracks which jobs are running and decides what happens to them once they finish.
+pub struct Scheduler {
+    clock: Arc<dyn Clock>,
+    running: Mutex<HashSet<(GuildId, i64)>>,
+    started: AtomicBool,
+}
+
+impl Scheduler {
+    /// Creates a scheduler which reads the time from the given clock.
+    pub fn new(clock: Arc<dyn Clock>) -> Self {
+        Scheduler {
+            clock,
+            running: Mutex::new(HashSet::new()),
+            started: AtomicBool::new(false),
+        }
+    }
+
+    /// Accessor for the scheduler's clock.
+    pub fn clock(&self) -> &dyn Clock {
+        self.clock.as_ref()
+    }
+
+    /// Returns the guild's due jobs which aren't already running, marking them as running.
+    pub fn claim_due(&self, conn: &GuildConn) -> crate::db::Result<Vec<Job>> {
+        let guild = *conn.as_id();
+        let due = conn.due_jobs(&self.clock.now())?;
+        let mut running = self.running.lock();
+        Ok(due.into_iter()
+            .filter(|j| running.insert((guild, j.id)))
+            .collect())
+    }
+
+    /// Records the outcome of a claimed job and releases it. Successful and recurring jobs move on
+    /// to their next run; failed one-shot jobs are retried with a growing delay until they have
+    /// failed [MAX_ATTEMPTS] times.
+    pub fn complete(&self, conn: &GuildConn, job: &Job, result: &hook::Result<()>) -> crate::db::Result<()> {
+        let now = self.clock.now();
+        let out = match result {
+            Ok(()) => conn.finish_job(job, &now),
+            Err(e) if job.interval.is_some() => {
+                error!("Recurring job {} ({}) failed, skipping this run: {}", job.id, job.kind, e);
+                conn.finish_job(job, &now)
+            },
+            Err(e) if job.attempts + 1 >= MAX_ATTEMPTS => {
+                error!("Job {} ({}) failed {} times, giving up: {}", job.id, job.kind, MAX_ATTEMPTS, e);
+                conn.remove_job(job.id).map(|_| ())
+            },
+            Err(e) => {
+                warn!("Job {} ({}) failed, retrying: {}", job.id, job.kind, e);
+                let delay = Duration::seconds(RETRY_DELAY << job.attempts.min(16));
+                conn.retry_job(job, &(now + delay))
+            },
+        };
+
+        self.running.lock().remove(&(*conn.as_id(), job.id));
+        out
+    }
+
+    /// Starts polling for jobs on a new thread, unless the scheduler has already been started.
+    pub fn start(disp: Arc<Dispatch>, ctx: Context) {
+        if disp.scheduler().started.swap(true, Ordering::SeqCst) {
+            return;
+        }
+
+        let pool = ThreadPool::builder()
+            .pool_size(*JOB_THREADS)
+            .name_prefix("glimbot-job-")
+            .create()
+            .expect("Failed to create the job worker pool.");
+
+        thread::Builder::new()
+            .name("glimbot-scheduler".to_string())
+            .spawn(move || loop {
+                thread::sleep(*SCHEDULER_INTERVAL);
+                let guilds: Vec<GuildId> = ctx.cache.read().guilds.keys().copied().collect();
+                trace!("Polling {} guilds for due jobs", guilds.len());
+                for guild in guilds {
+                    poll_guild(&disp, &ctx, &pool, guild).log_error();
+                }
+            })
+            .expect("Failed to spawn the scheduler thread.");
+    }
+}
+
+fn poll_guild(disp: &Arc<Dispatch>, ctx: &Context, pool: &ThreadPool, guild: GuildId) -> crate::db::Result<()> {
+    let conn = get_cached_connection(guild)?;
+    let jobs = disp.scheduler().claim_due(&conn.borrow())?;
+    for job in jobs {
+        let disp = disp.clone();
+        let ctx = ctx.clone();
+        pool.spawn_ok(async move { run_job(&disp, &ctx, guild, job).log_error() });
+    }
+
+    Ok(())
+}
+
+fn run_job(disp: &Dispatch, ctx: &Context, guild: GuildId, job: Job) -> crate::db::Result<()> {
+    let conn = get_cached_connection(guild)?;
+    let rconn = conn.borrow();
+    let result = match disp.job_handler(&job.kind) {
+        Some(f) => {
+            debug!("Running job {} ({}) in guild {}", job.id, job.kind, guild);
+            f(disp, ctx, &rconn, &job)
+        },
+        None => {
+            // The module may not be loaded right now; leave the job for later.
+            warn!("No handler for job {} ({}) in guild {}", job.id, job.kind, guild);
+            disp.scheduler().running.lock().remove(&(guild, job.id));
+            return Ok(());
+        }
+    };
+
+    disp.scheduler().complete(&rconn, &job, &result)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::util::test_guild_conn;
+    use chrono::TimeZone;
+
+    #[test]
+    fn test_scheduler() {
+        let (_dir, gconn) = test_guild_conn();
+
+        let start = Utc.timestamp(1_000_000, 0);
+        let clock = Arc::new(ManualClock::new(start));
+        let scheduler = Scheduler::new(clock.clone());
+        let minute = Duration::seconds(60);
+
+        let once = gconn.schedule_job("unban", "1", &(start + minute), None).unwrap();
+        let every = gconn.schedule_job("digest", "", &start, Some(minute * 10)).unwrap();
+
+        // Claimed jobs aren't handed out twice while they run.
+        let due = scheduler.claim_due(&gconn).unwrap();
+        assert_eq!(due.iter().map(|j| j.id).collect::<Vec<_>>(), vec![every]);
+        assert!(scheduler.claim_due(&gconn).unwrap().is_empty());
+        scheduler.complete(&gconn, &due[0], &Ok(())).unwrap();
+
+        clock.advance(minute);
+        let due = scheduler.claim_due(&gconn).unwrap();
+        assert_eq!(due.iter().map(|j| j.id).collect::<Vec<_>>(), vec![once]);
+
+        // Failures back off, then give up.
+        scheduler.complete(&gconn, &due[0], &Err(hook::Error::Denied)).unwrap();
+        assert!(scheduler.claim_due(&gconn).unwrap().is_empty());
+        for _ in 1..MAX_ATTEMPTS {
+            clock.advance(Duration::days(1));
+            let due = scheduler.claim_due(&gconn).unwrap();
+            let job = due.iter().find(|j| j.id == once).unwrap();
+            scheduler.complete(&gconn, job, &Err(hook::Error::Denied)).unwrap();
+            due.iter().filter(|j| j.id != once)
+                .for_each(|j| scheduler.complete(&gconn, j, &Ok(())).unwrap());
+        }
+        assert_eq!(gconn.jobs(None).unwrap().iter().map(|j| j.id).collect::<Vec<_>>(), vec![every]);
+    }
+}
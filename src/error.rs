@@ -108,6 +108,12 @@ impl SerenityError {
             .unwrap_or(false)
     }
 
+    /// Returns true if this is an HTTP 404 error.
+    pub fn not_found(&self) -> bool {
+        self.unsuccessful_request().map(|e| e.status_code == StatusCode::NOT_FOUND)
+            .unwrap_or(false)
+    }
+
     /// Returns `Some(e)` if the underlying error is an http error.
     pub fn http_error(&self) -> Option<&serenity::http::error::Error> {
         match &self.0 {
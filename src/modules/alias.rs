@@ -0,0 +1,190 @@
+//  Glimbot - A Discord anti-spam and administration bot.
+//  Copyright (C) 2020 Nick Samson
+
+//  This program is free software: you can redistribute it and/or modify
+//  it under the terms of the GNU General Public License as published by
+//  the Free Software Foundation, either version 3 of the License, or
+//  (at your option) any later version.
+
+//  This program is distributed in the hope that it will be useful,
+//  but WITHOUT ANY WARRANTY; without even the implied warranty of
+//  MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+//  GNU General Public License for more details.
+
+//  You should have received a copy of the GNU General Public License
+//  along with this program.  If not, see <https://www.gnu.org/licenses/>.
+
+//! Guild command aliases. An alias expands into a command plus leading arguments, so `j` could stand
+//! for `me join-role`. Aliases may not shadow a command name or a module's built-in alias, and always
+//! expand to a built-in command, never to another guild alias.
+
+use crate::modules::commands::Command;
+use crate::modules::Module;
+use crate::modules::hook::Error::DeniedWithReason;
+use crate::modules::modlog::{record, AuditEntry};
+use crate::modules::permissions::check_command;
+use crate::dispatch::Dispatch;
+use crate::args::{parse_app_matches, subcommand_name, subcommand_names};
+use crate::util::help_str;
+use crate::db::cache::get_cached_connection;
+use serenity::prelude::Context;
+use serenity::model::channel::Message;
+use serenity::utils::MessageBuilder;
+use std::borrow::Cow;
+use std::fmt::Write;
+use itertools::Itertools;
+use once_cell::unsync::Lazy;
+use clap::{App, AppSettings, Arg, ArgMatches, SubCommand};
+
+/// Longest allowed alias name.
+const MAX_ALIAS_LEN: usize = 32;
+
+fn valid_alias_name(s: &str) -> bool {
+    !s.is_empty()
+        && s.chars().count() <= MAX_ALIAS_LEN
+        && s.chars().all(|c| c.is_alphanumeric() || c == '_')
+}
+
+/// ZST struct for processing the `alias` command
+pub struct Alias;
+
+thread_local! {
+    static PARSER: Lazy<App<'static, 'static>> = Lazy::new(
+        || {
+            let name = Arg::with_name("name")
+                .value_name("NAME")
+                .help("The alias, typed in place of a command name.")
+                .takes_value(true)
+                .required(true);
+
+            App::new("alias")
+                .about("Command for managing this guild's command aliases.")
+                .setting(AppSettings::SubcommandRequiredElseHelp)
+                .subcommand(
+                    SubCommand::with_name("add")
+                        .about("Adds an alias, or replaces an existing one.")
+                        .setting(AppSettings::TrailingVarArg)
+                        .arg(name.clone())
+                        .arg(Arg::with_name("command")
+                            .value_name("COMMAND")
+                            .help("The command the alias expands to, optionally followed by arguments.")
+                            .takes_value(true)
+                            .multiple(true)
+                            .allow_hyphen_values(true)
+                            .required(true)
+                        )
+                )
+                .subcommand(
+                    SubCommand::with_name("remove")
+                        .about("Removes an alias.")
+                        .arg(name)
+                )
+                .subcommand(
+                    SubCommand::with_name("list")
+                        .about("Lists this guild's aliases, along with the aliases built into modules.")
+                )
+        }
+    );
+}
+
+impl Command for Alias {
+    fn invoke(&self, disp: &Dispatch, ctx: &Context, msg: &Message, args: Cow<str>) -> super::commands::Result<()> {
+        let m: ArgMatches = PARSER.with(|p| parse_app_matches("alias", args, p))?;
+
+        let conn = get_cached_connection(msg.guild_id.unwrap())?;
+        let rconn = conn.borrow();
+
+        let reply = match m.subcommand() {
+            ("add", Some(m)) => {
+                let name = m.value_of("name").unwrap();
+                if !valid_alias_name(name) {
+                    return Err(DeniedWithReason(format!(
+                        "Aliases must be at most {} letters, numbers or underscores.", MAX_ALIAS_LEN
+                    ).into()).into());
+                }
+                if disp.canonical_command(name).is_some() || disp.modules().contains_key(name) {
+                    return Err(DeniedWithReason("That name is already taken by a command.".into()).into());
+                }
+
+                let mut words = m.values_of("command").unwrap();
+                let command = check_command(disp, words.next().unwrap())?;
+                let expansion = std::iter::once(command).chain(words).join(" ");
+
+                rconn.set_command_alias(name, &expansion)?;
+                record(disp, ctx, &rconn, &AuditEntry::new(msg.author.id, "Set alias", name)
+                    .with_reason(expansion.clone()));
+                format!("{} now runs {}.", name, expansion)
+            },
+            ("remove", Some(m)) => {
+                let name = m.value_of("name").unwrap();
+                if rconn.remove_command_alias(name)? {
+                    record(disp, ctx, &rconn, &AuditEntry::new(msg.author.id, "Removed alias", name));
+                    format!("Removed alias {}.", name)
+                } else {
+                    format!("There is no alias {}.", name)
+                }
+            },
+            ("list", Some(_)) => {
+                let mut out = String::new();
+                for (alias, expansion) in rconn.command_aliases()? {
+                    writeln!(out, "{} -> {}", alias, expansion).unwrap();
+                }
+                if out.is_empty() {
+                    out.push_str("This guild has no aliases.\n");
+                }
+
+                out.push_str("\nBuilt in:\n");
+                for module in disp.modules().values().sorted_by_key(|m| m.name()) {
+                    for alias in module.aliases() {
+                        writeln!(out, "{} -> {}", alias, module.name()).unwrap();
+                    }
+                }
+                out
+            },
+            _ => unreachable!()
+        };
+
+        msg.channel_id.say(ctx, MessageBuilder::new()
+            .push_codeblock_safe(reply, None)
+            .build())?;
+
+        Ok(())
+    }
+
+    fn subcommand(&self, args: &str) -> Option<String> {
+        PARSER.with(|p| subcommand_name("alias", args, p))
+    }
+
+    fn subcommands(&self) -> Vec<String> {
+        PARSER.with(|p| subcommand_names(p))
+    }
+
+    fn help(&self) -> Cow<'static, str> {
+        PARSER.with(|p| help_str(p).into())
+    }
+}
+
+/// Creates the alias module.
+pub fn alias_mod() -> Module {
+    Module::with_name("alias")
+        .with_command(Alias)
+        .with_sensitivity(true)
+        .with_dependency("roles")
+        .with_dependency("modlog")
+        .with_dependency("permissions")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_valid_alias_name() {
+        assert!(valid_alias_name("j"));
+        assert!(valid_alias_name("join_role2"));
+        assert!(!valid_alias_name(""));
+        assert!(!valid_alias_name("join-role"));
+        assert!(!valid_alias_name("!j"));
+        assert!(!valid_alias_name(&"a".repeat(MAX_ALIAS_LEN + 1)));
+    }
+}
\ No newline at end of file
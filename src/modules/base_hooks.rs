@@ -23,8 +23,7 @@ use serenity::model::prelude::Message;
 use std::borrow::Cow;
 use once_cell::sync::Lazy;
 use regex::Regex;
-use crate::modules::config::simple_validator;
-use std::sync::Arc;
+use crate::modules::config::{Kind, simple_check};
 
 const MAX_COMMAND_INVOCATION_LENGTH: usize = 1500; // Max length for messages to be passed to commands.
 
@@ -52,9 +51,27 @@ pub fn reject_dm_command_hook<'a, 'b, 'c, 'd>(_disp: &'a Dispatch, _ctx: &'b Con
     }
 }
 
-fn validate_command_prefix(s: &str) -> bool {
+/// The config key holding the guild's command prefixes, separated by spaces.
+pub static COMMAND_PREFIX_KEY: &str = "command_prefix";
+
+const MAX_PREFIXES: usize = 5;
+const MAX_PREFIX_LENGTH: usize = 16; // Characters
+
+/// Checks a space separated list of command prefixes. Each prefix must contain a symbol or punctuation
+/// character, so that ordinary words can't trigger commands.
+fn validate_command_prefix(s: &str) -> Result<(), Cow<'static, str>> {
     static COMMAND_PREFIX_RE: Lazy<Regex> = Lazy::new(|| Regex::new(r"[\p{Math Symbol}\p{Punctuation}]").unwrap());
-    COMMAND_PREFIX_RE.is_match(s)
+    let prefixes: Vec<&str> = s.split_whitespace().collect();
+    if prefixes.is_empty() || prefixes.len() > MAX_PREFIXES {
+        return Err(format!("expected between 1 and {} prefixes", MAX_PREFIXES).into());
+    }
+
+    match prefixes.iter().find(|p| p.chars().count() > MAX_PREFIX_LENGTH || !COMMAND_PREFIX_RE.is_match(p)) {
+        Some(p) => Err(format!(
+            "{} must be at most {} characters and contain a symbol or punctuation", p, MAX_PREFIX_LENGTH
+        ).into()),
+        None => Ok(())
+    }
 }
 
 /// Returns a module with hook functionality not related to any particular command module.
@@ -62,10 +79,27 @@ pub fn base_hooks() -> Module {
     Module::with_name("base_hooks")
         .with_command_hook(reject_dm_command_hook)
         .with_command_hook(length_hook)
-        .with_config_value(config::Value::new("command_prefix",
-                                              "The single character before a command.",
-                                              Arc::new(simple_validator(validate_command_prefix)),
-                                              Some("!")))
+        .with_config_value(config::Value::new(COMMAND_PREFIX_KEY,
+                                              "Up to 5 space separated prefixes, any of which may start a command. Mentioning Glimbot always works too.",
+                                              Kind::List(Box::new(Kind::Word)),
+                                              Some("!"))
+            .with_check(simple_check(validate_command_prefix)))
         .clear_dependencies()
         .with_sensitivity(true)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_validate_command_prefix() {
+        assert!(validate_command_prefix("!").is_ok());
+        assert!(validate_command_prefix("! gl! ~~").is_ok());
+        assert!(validate_command_prefix("").is_err());
+        assert!(validate_command_prefix("   ").is_err());
+        assert!(validate_command_prefix("! gl").is_err());
+        assert!(validate_command_prefix("! ? . , ; :").is_err());
+        assert!(validate_command_prefix(&"!".repeat(MAX_PREFIX_LENGTH + 1)).is_err());
+    }
 }
\ No newline at end of file
@@ -67,6 +67,17 @@ pub trait Command: Send + Sync {
     /// The primary entry point for the command.
     fn invoke(&self, disp: &Dispatch, ctx: &Context, msg: &Message, args: Cow<str>) -> Result<()>;
 
+    /// Returns the name of the subcommand the given arguments would invoke, if any.
+    /// Used to apply permission overrides to individual subcommands.
+    fn subcommand(&self, _args: &str) -> Option<String> {
+        None
+    }
+
+    /// Lists the names of the command's subcommands, which overrides may be limited to.
+    fn subcommands(&self) -> Vec<String> {
+        Vec::new()
+    }
+
     /// Returns a help string for the given command, invoked by the "help" module.
     fn help(&self) -> Cow<'static, str> {
         Cow::Borrowed("No help specified.")
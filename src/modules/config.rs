@@ -15,8 +15,12 @@
 //  along with this program.  If not, see <https://www.gnu.org/licenses/>.
 
 //! The config module manages configuration values for Glimbot
+//!
+//! Each value declares a [Kind], which input is checked against before it is stored, and may add
+//! further checks of its own. Values are read back as typed data with [Dispatch::get_config].
 
 use std::collections::HashMap;
+use std::fmt;
 use std::str::FromStr;
 use crate::error::{BotError, AnyError};
 use clap::{App, SubCommand, Arg, ArgMatches, AppSettings};
@@ -25,20 +29,32 @@ use serenity::prelude::Context;
 use serenity::model::channel::Message;
 use crate::dispatch::Dispatch;
 use std::borrow::Cow;
-use crate::args::parse_app_matches;
+use crate::args::{parse_app_matches, subcommand_name, subcommand_names};
 use once_cell::unsync::Lazy;
 use crate::db::cache::get_cached_connection;
-use serenity::utils::MessageBuilder;
 use crate::modules::Module;
+use crate::modules::hook::Error::DeniedWithReason;
+use crate::modules::roles::user_tier;
+use crate::db::Tier;
 use crate::db::GuildConn;
 use std::sync::Arc;
-use std::io::Cursor;
-
-/// A validation function for config values. Should return true if the value would be valid input.
-pub type ConfigValidatorFn = dyn (Fn(&Dispatch, &Context, &GuildConn, &str) -> bool) + Send + Sync + 'static;
-
-/// Pointer to a [ConfigValidatorFnPtr]
-pub type ConfigValidatorFnPtr = Arc<ConfigValidatorFn>;
+use std::fmt::Write;
+use itertools::Itertools;
+use crate::util::{help_str, say_codeblocks};
+use crate::modules::modlog::{record, AuditEntry};
+use crate::modules::export::{diff, Document, GuildNames};
+use crate::modules::filter;
+use crate::modules::punishment::parse_duration;
+use serenity::model::guild::Guild;
+use serenity::model::id::{ChannelId, RoleId, UserId};
+use chrono::Duration;
+
+/// An extra check for config values, run after the value has been checked against its [Kind].
+/// Returns a description of the problem if the value is invalid.
+pub type ConfigCheckFn = dyn (Fn(&Dispatch, &Context, &GuildConn, &str) -> std::result::Result<(), Cow<'static, str>>) + Send + Sync + 'static;
+
+/// Pointer to a [ConfigCheckFn]
+pub type ConfigCheckFnPtr = Arc<ConfigCheckFn>;
 
 /// Alias for config validation failures.
 pub type Result<T> = std::result::Result<T, Error>;
@@ -55,6 +71,9 @@ pub enum Error {
     /// There is no default value for the given value.
     #[error("There is no default value for that.")]
     NoDefault,
+    /// The stored value could not be read, and there is no default to fall back on.
+    #[error("The stored value of {0} is malformed. Please set it again.")]
+    Malformed(String),
 }
 
 impl From<Error> for super::commands::Error {
@@ -86,11 +105,8 @@ impl Validator {
     pub fn validate(&self, disp: &Dispatch, ctx: &Context, conn: &GuildConn, config_name: impl AsRef<str>, value: impl AsRef<str>) -> Result<()> {
         let cval = self.validators.get(config_name.as_ref())
             .ok_or(Error::NoSuchKey(config_name.as_ref().to_string()))?;
-        if cval.is_valid(disp, ctx, conn,value.as_ref()) {
-            Ok(())
-        } else {
-            Err(Error::InvalidValue(value.as_ref().to_string().into()))
-        }
+        cval.validate(disp, ctx, conn, value.as_ref())
+            .map_err(|reason| Error::InvalidValue(format!("{} for {}: {}", value.as_ref(), cval.name, reason).into()))
     }
 
     /// Checks to see if this is a valid config key.
@@ -119,6 +135,148 @@ impl Validator {
         self.check_key(key.as_ref())?;
         Ok(self.validators.get(key.as_ref()).unwrap().help)
     }
+
+    /// Retrieves the kind of the given key.
+    pub fn kind_for(&self, key: impl AsRef<str>) -> Result<&Kind> {
+        self.value(key).map(|v| &v.kind)
+    }
+
+    /// Retrieves the config value with the given key.
+    pub fn value(&self, key: impl AsRef<str>) -> Result<&Value> {
+        self.validators.get(key.as_ref())
+            .ok_or_else(|| Error::NoSuchKey(key.as_ref().to_string()))
+    }
+
+    /// Iterates over every config value, in no particular order.
+    pub fn values(&self) -> impl Iterator<Item=&Value> {
+        self.validators.values()
+    }
+}
+
+/// The kind of data a config value holds.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Kind {
+    /// true or false.
+    Bool,
+    /// A whole number within an inclusive range.
+    Int {
+        /// The smallest allowed number.
+        min: i64,
+        /// The largest allowed number.
+        max: i64,
+    },
+    /// A length of time, like 90, 10m or 2h30m. Bare numbers are seconds.
+    Duration,
+    /// A role in the guild.
+    Role,
+    /// A channel in the guild.
+    Channel,
+    /// Any user, by mention or id.
+    User,
+    /// One of a fixed set of words.
+    Enum(&'static [&'static str]),
+    /// A single word, without whitespace.
+    Word,
+    /// A space separated list of values of another kind.
+    List(Box<Kind>),
+}
+
+impl Kind {
+    /// Any whole number from 0 up to [u32::MAX].
+    pub const UINT: Kind = Kind::Int { min: 0, max: u32::MAX as i64 };
+
+    /// Checks a value against the kind. Roles and channels are looked up in `guild`, and are
+    /// rejected if it isn't available.
+    pub fn validate(&self, guild: Option<&Guild>, s: &str) -> std::result::Result<(), Cow<'static, str>> {
+        match self {
+            Kind::Bool => s.parse::<bool>()
+                .map(drop)
+                .map_err(|_| "expected true or false".into()),
+            Kind::Int { min, max } => match s.parse::<i64>() {
+                Ok(n) if n >= *min && n <= *max => Ok(()),
+                Ok(_) => Err(format!("expected a number from {} to {}", min, max).into()),
+                Err(_) => Err("expected a whole number".into())
+            },
+            Kind::Duration => parse_duration(s)
+                .map(drop)
+                .ok_or_else(|| "expected a length of time like 90, 10m or 2h30m, up to 365d".into()),
+            Kind::Role => match (RoleId::from_str(s), guild) {
+                (Ok(id), Some(g)) if g.roles.contains_key(&id) => Ok(()),
+                (Ok(_), _) => Err("no such role in this guild".into()),
+                (Err(_), _) => Err("expected a role mention or id".into())
+            },
+            Kind::Channel => match (ChannelId::from_str(s), guild) {
+                (Ok(id), Some(g)) if g.channels.contains_key(&id) => Ok(()),
+                (Ok(_), _) => Err("no such channel in this guild".into()),
+                (Err(_), _) => Err("expected a channel mention or id".into())
+            },
+            Kind::User => UserId::from_str(s)
+                .map(drop)
+                .map_err(|_| "expected a user mention or id".into()),
+            Kind::Enum(options) => if options.contains(&s) {
+                Ok(())
+            } else {
+                Err(format!("expected one of {}", options.join(", ")).into())
+            },
+            Kind::Word => if s.is_empty() || s.contains(char::is_whitespace) {
+                Err("expected a single word".into())
+            } else {
+                Ok(())
+            },
+            Kind::List(kind) => s.split_whitespace()
+                .enumerate()
+                .try_for_each(|(i, item)| kind.validate(guild, item)
+                    .map_err(|e| format!("item {} ({}): {}", i + 1, item, e).into())),
+        }
+    }
+}
+
+impl fmt::Display for Kind {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Kind::Bool => write!(f, "true or false"),
+            Kind::Int { min, max } => write!(f, "a number from {} to {}", min, max),
+            Kind::Duration => write!(f, "a length of time"),
+            Kind::Role => write!(f, "a role"),
+            Kind::Channel => write!(f, "a channel"),
+            Kind::User => write!(f, "a user"),
+            Kind::Enum(options) => write!(f, "one of {}", options.join(", ")),
+            Kind::Word => write!(f, "a word"),
+            Kind::List(kind) => write!(f, "a space separated list, each {}", kind),
+        }
+    }
+}
+
+/// Types which config values can be read as, using [Dispatch::get_config].
+pub trait FromConfig: Sized {
+    /// Parses a stored value, returning None if it is malformed.
+    fn from_config(s: &str) -> Option<Self>;
+}
+
+macro_rules! from_config_via_parse {
+    ($($t:ty),*) => {
+        $(
+            impl FromConfig for $t {
+                fn from_config(s: &str) -> Option<Self> {
+                    s.parse().ok()
+                }
+            }
+        )*
+    };
+}
+
+from_config_via_parse!(String, bool, i64, u32, u64, usize, RoleId, ChannelId, UserId);
+
+impl FromConfig for Duration {
+    fn from_config(s: &str) -> Option<Self> {
+        parse_duration(s)
+    }
+}
+
+impl<T: FromConfig> FromConfig for Vec<T> {
+    fn from_config(s: &str) -> Option<Self> {
+        s.split_whitespace().map(T::from_config).collect()
+    }
 }
 
 /// Represents a validatable config value for Glimbot.
@@ -126,14 +284,34 @@ impl Validator {
 pub struct Value {
     name: &'static str,
     help: &'static str,
-    validator: ConfigValidatorFnPtr,
+    kind: Kind,
+    check: Option<ConfigCheckFnPtr>,
     default: Option<String>,
+    sensitive: bool,
 }
 
 impl Value {
     /// Creates a new Value.
-    pub fn new(name: &'static str, help: &'static str, validator: ConfigValidatorFnPtr, default: Option<impl Into<String>>) -> Self {
-        Value { name, help, validator, default: default.map(|x| x.into()) }
+    pub fn new(name: &'static str, help: &'static str, kind: Kind, default: Option<impl Into<String>>) -> Self {
+        Value { name, help, kind, check: None, default: default.map(|x| x.into()), sensitive: false }
+    }
+
+    /// Sets whether the value is sensitive. Sensitive values may only be seen or changed by admins,
+    /// even if a permission override lets others use the config command.
+    pub fn with_sensitivity(mut self, is_sensitive: bool) -> Self {
+        self.sensitive = is_sensitive;
+        self
+    }
+
+    /// Returns whether the value is sensitive.
+    pub fn is_sensitive(&self) -> bool {
+        self.sensitive
+    }
+
+    /// Adds a check to run on input after it has been checked against the value's kind.
+    pub fn with_check(mut self, check: ConfigCheckFnPtr) -> Self {
+        self.check = Some(check);
+        self
     }
 
     /// Returns the name of the config key.
@@ -146,9 +324,23 @@ impl Value {
         self.help
     }
 
-    /// Returns whether or not the given value is a valid config value
-    pub fn is_valid(&self, disp: &Dispatch, ctx: &Context, conn: &GuildConn, s: &str) -> bool {
-        (self.validator)(disp, ctx, conn, s)
+    /// Returns the kind of the config value.
+    pub fn kind(&self) -> &Kind {
+        &self.kind
+    }
+
+    /// Checks that the given string is a valid setting, describing the problem if it isn't.
+    pub fn validate(&self, disp: &Dispatch, ctx: &Context, conn: &GuildConn, s: &str) -> std::result::Result<(), Cow<'static, str>> {
+        {
+            let guild = ctx.cache.read().guild(*conn.as_id());
+            let rg = guild.as_ref().map(|g| g.read());
+            self.kind.validate(rg.as_deref(), s)?;
+        }
+
+        match &self.check {
+            Some(check) => check(disp, ctx, conn, s),
+            None => Ok(())
+        }
     }
 
     /// Returns an optional default setting for this config value.
@@ -157,24 +349,71 @@ impl Value {
     }
 }
 
-/// Helper function to validate boolean config values.
-pub fn valid_bool(s: &str) -> bool {
-    valid_parseable::<bool>(s)
+/// Function for creating validators for parseable types
+pub fn fallible_validator<T: FromStr<Err=E>, E: std::error::Error>(s: String) -> std::result::Result<(), String> {
+    s.parse::<T>().map_err(|e| format!("{}", e)).map(|_|())
 }
 
-/// Helper function to validate any parseable ([FromStr]) config values.
-pub fn valid_parseable<T: FromStr>(s: &str) -> bool {
-    s.parse::<T>().is_ok()
+/// Creates a check for values that can be checked without any context.
+pub fn simple_check(f: fn(&str) -> std::result::Result<(), Cow<'static, str>>) -> ConfigCheckFnPtr {
+    Arc::new(move |_, _, _, s| f(s))
 }
 
-/// Function for creating validators for parseable types
-pub fn fallible_validator<T: FromStr<Err=E>, E: std::error::Error>(s: String) -> std::result::Result<(), String> {
-    s.parse::<T>().map_err(|e| format!("{}", e)).map(|_|())
+/// The largest file `config import` will read.
+const MAX_IMPORT_SIZE: u64 = 1 << 18;
+
+/// The name of the file uploaded by `config export`.
+static EXPORT_FILE_NAME: &str = "glimbot-config.yaml";
+
+fn require_admin(is_admin: bool) -> crate::modules::commands::Result<()> {
+    if is_admin {
+        Ok(())
+    } else {
+        Err(DeniedWithReason("Only admins may export or import the config.".into()).into())
+    }
+}
+
+/// Fails if the key is sensitive and the user isn't an admin. Only admins may use the config command
+/// unless a permission override says otherwise, and overrides don't extend to sensitive values.
+fn check_visible(validator: &Validator, key: &str, is_admin: bool) -> crate::modules::commands::Result<()> {
+    if !is_admin && validator.value(key)?.is_sensitive() {
+        return Err(DeniedWithReason(format!("Only admins may see or change {}.", key).into()).into());
+    }
+    Ok(())
 }
 
-/// Creates a validator for types that can be checked just by a parser or regex
-pub fn simple_validator(f: impl Fn(&str) -> bool) -> impl Fn(&Dispatch, &Context, &GuildConn, &str) -> bool {
-    move |_, _, _, s| f(s)
+/// Lists the config values visible to the user.
+fn list_values(validator: &Validator, conn: &GuildConn, is_admin: bool, changed_only: bool) -> crate::db::Result<String> {
+    let stored = conn.config_values()?;
+    let mut hidden = 0;
+    let mut out = String::new();
+    for v in validator.values().sorted_by_key(|v| v.name()) {
+        if v.is_sensitive() && !is_admin {
+            hidden += 1;
+            continue;
+        }
+
+        let current = stored.get(v.name());
+        if changed_only && (current.is_none() || current == v.default()) {
+            continue;
+        }
+
+        match (current, v.default()) {
+            (Some(c), Some(d)) if c != d => writeln!(out, "{} = {} (default {})", v.name(), c, d),
+            (_, Some(d)) => writeln!(out, "{} = {} (default)", v.name(), d),
+            (Some(c), None) => writeln!(out, "{} = {}", v.name(), c),
+            (None, None) => writeln!(out, "{} is not set", v.name()),
+        }.unwrap();
+        writeln!(out, "    {}", v.help()).unwrap();
+    }
+
+    if out.is_empty() {
+        out.push_str("Every value is set to its default.\n");
+    }
+    if hidden > 0 {
+        write!(out, "\n{} sensitive values are hidden.", hidden).unwrap();
+    }
+    Ok(out)
 }
 
 /// The config command structure. Contains the parser for command arguments.
@@ -210,6 +449,38 @@ static PARSER: Lazy<App<'static, 'static>> = Lazy::new (
                     .arg(key_arg.clone())
                     .about("Displays help for the given config value.")
             )
+            .subcommand(
+                SubCommand::with_name("list")
+                    .arg(Arg::with_name("changed")
+                        .long("changed")
+                        .short("c")
+                        .help("Only lists values which differ from their defaults.")
+                    )
+                    .about("Lists every config value along with its current setting, default and help.")
+            )
+            .subcommand(
+                SubCommand::with_name("reset")
+                    .arg(key_arg.clone())
+                    .about("Sets CONFIG_KEY back to its default value.")
+            )
+            .subcommand(
+                SubCommand::with_name("export")
+                    .about("Uploads this guild's config values, restricted commands, joinable roles and filters as a YAML file.")
+            )
+            .subcommand(
+                SubCommand::with_name("import")
+                    .arg(Arg::with_name("dry-run")
+                        .long("dry-run")
+                        .short("n")
+                        .help("Shows what would change without changing anything.")
+                    )
+                    .about("Applies the YAML file attached to the message, as made by export. Sections left out of the file are left alone.")
+            )
+            .subcommand(
+                SubCommand::with_name("unset")
+                    .arg(key_arg.clone())
+                    .about("Deletes this guild's setting for CONFIG_KEY. Values with a default go back to it; the rest are turned off.")
+            )
             .setting(AppSettings::SubcommandRequiredElseHelp)
     }
 );
@@ -221,20 +492,28 @@ impl Cmd for Command {
             parse_app_matches("config", args, &p)
         )?;
 
+        let guild = msg.guild_id.unwrap();
+        let conn = get_cached_connection(guild)?;
+        let rl = conn.as_ref().borrow();
+        let validator = disp.config_validator();
+        let roles = msg.member.as_ref().map_or(&[][..], |m| &m.roles[..]);
+        let is_admin = user_tier(ctx, &rl, guild, msg.author.id, roles)? >= Tier::Admin;
+
         let reply = match m.subcommand() {
             ("info", Some(subm)) => {
                 let key = subm.value_of("config-key").unwrap();
-                let help = disp.config_validator().help_for(key)?;
-                format!("{}: {}", key, help)
+                let help = validator.help_for(key)?;
+                let kind = validator.kind_for(key)?;
+                format!("{}: {}\nExpects {}.", key, help, kind)
             },
+            ("list", Some(subm)) => list_values(validator, &rl, is_admin, subm.is_present("changed"))?,
             ("get", Some(subm)) => {
                 let key = subm.value_of("config-key").unwrap();
-                let conn = get_cached_connection(msg.guild_id.unwrap())?;
-                let rl = conn.as_ref().borrow();
-                let val = if disp.config_validator().default_for(key).is_ok() {
+                check_visible(validator, key, is_admin)?;
+                let val = if validator.default_for(key).is_ok() {
                     disp.get_or_set_config(&rl, key)?
                 } else {
-                    disp.get_config(&rl, key)?
+                    disp.get_config::<String>(&rl, key)?
                 };
 
                 format!("{} is set to {}", key, val)
@@ -242,27 +521,87 @@ impl Cmd for Command {
             ("set", Some(subm)) => {
                 let key = subm.value_of("config-key").unwrap();
                 let val = subm.value_of("value").unwrap();
-                let conn = get_cached_connection(msg.guild_id.unwrap())?;
-                let rl = conn.as_ref().borrow();
+                check_visible(validator, key, is_admin)?;
                 disp.set_config(ctx, &rl, key, val)?;
+                record(disp, ctx, &rl, &AuditEntry::new(msg.author.id, format!("Set config value to {}", val), key));
 
                 format!("Set {} to {}", key, val)
-            }
+            },
+            ("reset", Some(subm)) => {
+                let key = subm.value_of("config-key").unwrap();
+                check_visible(validator, key, is_admin)?;
+                let default = validator.default_for(key)?;
+                rl.set_value(key, default)?;
+                record(disp, ctx, &rl, &AuditEntry::new(msg.author.id, "Reset config value", key));
+
+                format!("Reset {} to {}", key, default)
+            },
+            ("export", Some(_)) => {
+                require_admin(is_admin)?;
+                let names = GuildNames::from_guild(&msg.guild(ctx).unwrap().read());
+                let yaml = Document::from_snapshot(&rl.snapshot()?, validator, &names).to_yaml()?;
+                msg.channel_id.send_files(ctx, vec![(yaml.as_bytes(), EXPORT_FILE_NAME)], |m| m)
+                    .map_err(AnyError::boxed)?;
+                return Ok(());
+            },
+            ("import", Some(subm)) => {
+                require_admin(is_admin)?;
+                let file = msg.attachments.first()
+                    .ok_or_else(|| DeniedWithReason("Attach the file to import to your message.".into()))?;
+                if file.size > MAX_IMPORT_SIZE {
+                    return Err(DeniedWithReason("That file is too large to be a config.".into()).into());
+                }
+                let text = String::from_utf8(file.download().map_err(AnyError::boxed)?)
+                    .map_err(|_| DeniedWithReason("That file isn't text.".into()))?;
+
+                // Validation looks the guild up again, so the names must be collected up front.
+                let names = GuildNames::from_guild(&msg.guild(ctx).unwrap().read());
+                let current = Document::from_snapshot(&rl.snapshot()?, validator, &names);
+                let snapshot = Document::from_yaml(&text)?
+                    .or(current.clone())
+                    .resolve(disp, &names, |key, value| validator.validate(disp, ctx, &rl, key, value))?;
+                let changes = diff(&current, &Document::from_snapshot(&snapshot, validator, &names));
+
+                if changes.is_empty() {
+                    "Importing that file wouldn't change anything.".to_string()
+                } else if subm.is_present("dry-run") {
+                    format!("Dry run; nothing was changed. Importing would make these changes:\n{}", changes.join("\n"))
+                } else {
+                    let keys: Vec<&str> = validator.values().map(Value::name).collect();
+                    rl.apply_snapshot(&snapshot, &keys)?;
+                    filter::invalidate(guild);
+                    record(disp, ctx, &rl, &AuditEntry::new(msg.author.id, "Imported config", file.filename.as_str())
+                        .with_reason(format!("{} changes", changes.len())));
+                    format!("Imported the config, making these changes:\n{}", changes.join("\n"))
+                }
+            },
+            ("unset", Some(subm)) => {
+                let key = subm.value_of("config-key").unwrap();
+                check_visible(validator, key, is_admin)?;
+                if rl.remove_value(key)? {
+                    record(disp, ctx, &rl, &AuditEntry::new(msg.author.id, "Unset config value", key));
+                    format!("Unset {}", key)
+                } else {
+                    format!("{} is not set", key)
+                }
+            },
             _ => unreachable!()
         };
 
-        msg.channel_id.say(ctx, MessageBuilder::new()
-            .push_codeblock_safe(reply, None)
-            .build()
-        ).map_err(AnyError::boxed)?;
+        say_codeblocks(ctx, msg.channel_id, &reply).map_err(AnyError::boxed)?;
         Ok(())
     }
 
+    fn subcommand(&self, args: &str) -> Option<String> {
+        PARSER.with(|p| subcommand_name("config", args, p))
+    }
+
+    fn subcommands(&self) -> Vec<String> {
+        PARSER.with(|p| subcommand_names(p))
+    }
+
     fn help(&self) -> Cow<'static, str> {
-        let c = PARSER.with(|p| (*p).clone());
-        let mut curs = Cursor::new(Vec::new());
-        c.write_help(&mut curs).unwrap();
-        Cow::Owned(String::from_utf8(curs.into_inner()).unwrap())
+        PARSER.with(|p| help_str(p).into())
     }
 }
 
@@ -270,5 +609,78 @@ impl Cmd for Command {
 pub fn config_mod() -> Module {
     Module::with_name("config")
         .with_command(Command)
+        .with_alias("cfg")
         .with_sensitivity(true)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::util::test_guild_conn;
+    use serenity::model::id::UserId;
+
+    #[test]
+    fn test_kind_validate() {
+        assert!(Kind::Bool.validate(None, "true").is_ok());
+        assert!(Kind::Bool.validate(None, "yes").is_err());
+        assert!(Kind::UINT.validate(None, "0").is_ok());
+        assert!(Kind::UINT.validate(None, "-1").is_err());
+        assert!(Kind::Int { min: 1, max: 5 }.validate(None, "6").is_err());
+        assert!(Kind::Duration.validate(None, "2h30m").is_ok());
+        assert!(Kind::Duration.validate(None, "99999999999999999").is_err());
+        assert!(Kind::Duration.validate(None, "999999999999d").is_err());
+        assert!(Kind::Enum(&["guild", "channel"]).validate(None, "channel").is_ok());
+        assert!(Kind::Enum(&["guild", "channel"]).validate(None, "user").is_err());
+        assert!(Kind::User.validate(None, "<@1234>").is_ok());
+        // Roles can't be checked without a guild.
+        assert!(Kind::Role.validate(None, "1234").is_err());
+
+        let list = Kind::List(Box::new(Kind::Word));
+        assert!(list.validate(None, "! ?? glim").is_ok());
+        let list = Kind::List(Box::new(Kind::UINT));
+        assert_eq!(list.validate(None, "1 x").unwrap_err(), "item 2 (x): expected a whole number");
+    }
+
+    #[test]
+    fn test_from_config() {
+        assert_eq!(bool::from_config("true"), Some(true));
+        assert_eq!(u32::from_config("-1"), None);
+        assert_eq!(Duration::from_config("10m"), Some(Duration::minutes(10)));
+        assert_eq!(Duration::from_config("99999999999999999"), None);
+        assert_eq!(Vec::<u32>::from_config("1 2 3"), Some(vec![1, 2, 3]));
+        assert_eq!(Vec::<u32>::from_config("1 x 3"), None);
+    }
+
+    #[test]
+    fn test_get_oversized_duration() {
+        let (_dir, gconn) = test_guild_conn();
+        let disp = Dispatch::new(UserId(1)).with_module(Module::with_name("t").clear_dependencies()
+            .with_config_value(Value::new("t_time", "A time.", Kind::Duration, Some("10m")))
+            .with_config_value(Value::new("t_other", "Another time.", Kind::Duration, Option::<String>::None)));
+
+        // Stored values too long to be durations fall back to the default rather than panicking.
+        gconn.set_value("t_time", "99999999999999999").unwrap();
+        assert_eq!(disp.get_config::<Duration>(&gconn, "t_time").unwrap(), Duration::minutes(10));
+        gconn.set_value("t_other", "999999999999d").unwrap();
+        assert!(disp.get_config::<Duration>(&gconn, "t_other").is_err());
+    }
+
+    #[test]
+    fn test_list_values() {
+        let (_dir, gconn) = test_guild_conn();
+
+        let mut validator = Validator::new();
+        validator.add_value(Value::new("a_enabled", "Enables a.", Kind::Bool, Some("false")));
+        validator.add_value(Value::new("b_role", "The role for b.", Kind::Role, Option::<String>::None));
+        validator.add_value(Value::new("c_limit", "The limit for c.", Kind::UINT, Some("5")).with_sensitivity(true));
+        gconn.set_value("a_enabled", "true").unwrap();
+
+        let out = list_values(&validator, &gconn, true, false).unwrap();
+        assert_eq!(out, "a_enabled = true (default false)\n    Enables a.\n\
+            b_role is not set\n    The role for b.\n\
+            c_limit = 5 (default)\n    The limit for c.\n");
+
+        let out = list_values(&validator, &gconn, false, true).unwrap();
+        assert_eq!(out, "a_enabled = true (default false)\n    Enables a.\n\n1 sensitive values are hidden.");
+    }
 }
\ No newline at end of file
@@ -0,0 +1,692 @@
+//  Glimbot - A Discord anti-spam and administration bot.
+//  Copyright (C) 2020 Nick Samson
+
+//  This program is free software: you can redistribute it and/or modify
+//  it under the terms of the GNU General Public License as published by
+//  the Free Software Foundation, either version 3 of the License, or
+//  (at your option) any later version.
+
+//  This program is distributed in the hope that it will be useful,
+//  but WITHOUT ANY WARRANTY; without even the implied warranty of
+//  MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+//  GNU General Public License for more details.
+
+//  You should have received a copy of the GNU General Public License
+//  along with this program.  If not, see <https://www.gnu.org/licenses/>.
+
+//! Conversion between a guild's [Snapshot] and the YAML files used by `config export` and
+//! `config import`.
+//!
+//! Files refer to roles and channels by name, so settings can be copied between guilds. Ids are
+//! written instead when a name is ambiguous, and are accepted on import wherever a name is.
+//! A file may leave out whole sections; importing it leaves those settings alone.
+
+use crate::db::{FilterRule, FilterAction, RoleGroup, Snapshot};
+use crate::dispatch::Dispatch;
+use crate::error::BotError;
+use crate::modules::config::{self, Kind, Validator};
+use crate::modules::filter::{validate_pattern, CompiledFilter};
+use crate::modules::permissions::check_command;
+use crate::modules::roles::valid_group_name;
+use serenity::model::guild::Guild;
+use serenity::model::id::{ChannelId, RoleId};
+use serde_yaml::{Mapping, Value};
+use std::collections::{BTreeMap, HashMap, HashSet};
+use std::fmt::{Display, Write};
+use std::hash::Hash;
+use std::str::FromStr;
+use itertools::Itertools;
+
+static CONFIG: &str = "config";
+static RESTRICTED_COMMANDS: &str = "restricted_commands";
+static JOINABLE_ROLES: &str = "joinable_roles";
+static ROLE_GROUPS: &str = "role_groups";
+static FILTERS: &str = "filters";
+
+/// Error returned when a file can't be imported.
+#[derive(thiserror::Error, Debug)]
+pub enum Error {
+    /// The file isn't YAML at all.
+    #[error("The file isn't valid YAML: {0}")]
+    Yaml(#[from] serde_yaml::Error),
+    /// The file is YAML, but doesn't describe valid settings. Holds every problem found.
+    #[error("The file can't be imported:\n{}", .0.join("\n"))]
+    Invalid(Vec<String>),
+}
+
+impl From<Error> for super::commands::Error {
+    fn from(e: Error) -> Self {
+        super::commands::Error::RuntimeFailure(e.into())
+    }
+}
+
+impl BotError for Error {
+    fn is_user_error(&self) -> bool {
+        true
+    }
+}
+
+/// The names of a guild's roles and channels.
+#[derive(Debug, Clone, Default)]
+pub struct GuildNames {
+    roles: HashMap<RoleId, String>,
+    channels: HashMap<ChannelId, String>,
+}
+
+impl GuildNames {
+    /// Creates a name table from roles and channels.
+    pub fn new(roles: HashMap<RoleId, String>, channels: HashMap<ChannelId, String>) -> Self {
+        GuildNames { roles, channels }
+    }
+
+    /// Collects the names of the guild's roles and channels.
+    pub fn from_guild(guild: &Guild) -> Self {
+        GuildNames {
+            roles: guild.roles.iter().map(|(id, r)| (*id, r.name.clone())).collect(),
+            channels: guild.channels.iter().map(|(id, c)| (*id, c.read().name.clone())).collect(),
+        }
+    }
+
+    fn role_name(&self, id: RoleId) -> String {
+        name_or_id(&self.roles, id)
+    }
+
+    fn channel_name(&self, id: ChannelId) -> String {
+        name_or_id(&self.channels, id)
+    }
+
+    fn role_id(&self, s: &str) -> Option<RoleId> {
+        lookup(&self.roles, s)
+    }
+
+    fn channel_id(&self, s: &str) -> Option<ChannelId> {
+        lookup(&self.channels, s)
+    }
+}
+
+fn name_or_id<T: Copy + Eq + Hash + Display>(names: &HashMap<T, String>, id: T) -> String {
+    match names.get(&id) {
+        Some(name) if names.values().filter(|n| *n == name).count() == 1 => name.clone(),
+        _ => id.to_string()
+    }
+}
+
+fn lookup<T: Copy + Eq + Hash + FromStr>(names: &HashMap<T, String>, s: &str) -> Option<T> {
+    if let Some(id) = s.parse::<T>().ok().filter(|id| names.contains_key(id)) {
+        return Some(id);
+    }
+
+    names.iter()
+        .filter(|(_, n)| *n == s)
+        .map(|(id, _)| *id)
+        .exactly_one()
+        .ok()
+}
+
+/// A joinable role group, with its roles by name.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct GroupEntry {
+    /// The group's name.
+    pub name: String,
+    /// Whether users may only hold one of the group's roles.
+    pub exclusive: bool,
+    /// The most roles from the group a user may hold, if capped.
+    pub max: Option<u32>,
+    /// The group's roles.
+    pub roles: Vec<String>,
+}
+
+/// A filter rule, with its exemptions by name.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct FilterEntry {
+    /// The pattern to match.
+    pub pattern: String,
+    /// Whether the pattern is matched literally rather than as a regex.
+    pub literal: bool,
+    /// What to do with matching messages.
+    pub action: String,
+    /// Channels the rule doesn't apply in.
+    pub exempt_channels: Vec<String>,
+    /// Roles whose members the rule doesn't apply to.
+    pub exempt_roles: Vec<String>,
+}
+
+/// A guild's portable settings as they appear in a file. Sections which are None weren't in the file.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct Document {
+    /// Config values by key.
+    pub config: Option<BTreeMap<String, String>>,
+    /// Commands restricted to admins.
+    pub restricted_commands: Option<Vec<String>>,
+    /// Joinable roles which aren't in a group.
+    pub joinable_roles: Option<Vec<String>>,
+    /// Role groups.
+    pub role_groups: Option<Vec<GroupEntry>>,
+    /// Filter rules.
+    pub filters: Option<Vec<FilterEntry>>,
+}
+
+fn string_seq(v: &[String]) -> Value {
+    v.into()
+}
+
+fn scalar(v: &Value) -> Option<String> {
+    match v {
+        Value::String(s) => Some(s.clone()),
+        Value::Bool(b) => Some(b.to_string()),
+        Value::Number(n) => Some(n.to_string()),
+        _ => None
+    }
+}
+
+fn scalar_seq(v: &Value, what: &str, problems: &mut Vec<String>) -> Vec<String> {
+    match v.as_sequence() {
+        Some(items) => items.iter()
+            .filter_map(|item| {
+                let s = scalar(item);
+                if s.is_none() {
+                    problems.push(format!("{}: expected a list of names", what));
+                }
+                s
+            })
+            .collect(),
+        None => {
+            problems.push(format!("{}: expected a list", what));
+            Vec::new()
+        }
+    }
+}
+
+fn mapping<'a>(v: &'a Value, what: &str, problems: &mut Vec<String>) -> Option<&'a Mapping> {
+    let m = v.as_mapping();
+    if m.is_none() {
+        problems.push(format!("{}: expected a mapping", what));
+    }
+    m
+}
+
+fn field<'a>(m: &'a Mapping, name: &str) -> Option<&'a Value> {
+    m.get(&Value::from(name))
+}
+
+fn bool_field(m: &Mapping, name: &str, what: &str, problems: &mut Vec<String>) -> bool {
+    match field(m, name) {
+        None => false,
+        Some(v) => v.as_bool().unwrap_or_else(|| {
+            problems.push(format!("{}: {} must be true or false", what, name));
+            false
+        })
+    }
+}
+
+fn parse_group(v: &Value, what: &str, problems: &mut Vec<String>) -> Option<GroupEntry> {
+    let m = mapping(v, what, problems)?;
+    let name = field(m, "name").and_then(scalar);
+    if name.is_none() {
+        problems.push(format!("{}: missing name", what));
+    }
+    let max = match field(m, "max") {
+        None | Some(Value::Null) => None,
+        Some(v) => {
+            let max = v.as_u64().filter(|n| *n > 0 && *n <= u32::MAX.into());
+            if max.is_none() {
+                problems.push(format!("{}: max must be a positive number", what));
+            }
+            max.map(|n| n as u32)
+        }
+    };
+    let roles = field(m, "roles")
+        .map_or_else(Vec::new, |v| scalar_seq(v, &format!("{} roles", what), problems));
+
+    Some(GroupEntry {
+        name: name?,
+        exclusive: bool_field(m, "exclusive", what, problems),
+        max,
+        roles,
+    })
+}
+
+fn parse_filter(v: &Value, what: &str, problems: &mut Vec<String>) -> Option<FilterEntry> {
+    let m = mapping(v, what, problems)?;
+    let pattern = field(m, "pattern").and_then(scalar);
+    if pattern.is_none() {
+        problems.push(format!("{}: missing pattern", what));
+    }
+    let action = field(m, "action").and_then(scalar);
+    if action.is_none() {
+        problems.push(format!("{}: missing action", what));
+    }
+    let list = |name: &str, problems: &mut Vec<String>| field(m, name)
+        .map_or_else(Vec::new, |v| scalar_seq(v, &format!("{} {}", what, name), problems));
+
+    Some(FilterEntry {
+        literal: bool_field(m, "literal", what, problems),
+        exempt_channels: list("exempt_channels", problems),
+        exempt_roles: list("exempt_roles", problems),
+        pattern: pattern?,
+        action: action?,
+    })
+}
+
+/// Parses a list of entries, describing each by its position in the list.
+fn entries<T>(v: &Value, section: &str, what: &str, problems: &mut Vec<String>, parse: fn(&Value, &str, &mut Vec<String>) -> Option<T>) -> Vec<T> {
+    match v.as_sequence() {
+        Some(items) => items.iter()
+            .enumerate()
+            .filter_map(|(i, item)| parse(item, &format!("{} {}", what, i + 1), problems))
+            .collect(),
+        None => {
+            problems.push(format!("{}: expected a list", section));
+            Vec::new()
+        }
+    }
+}
+
+impl Document {
+    /// Describes a snapshot, naming roles and channels where possible. Config keys which the
+    /// validator doesn't know are left out.
+    pub fn from_snapshot(snapshot: &Snapshot, validator: &Validator, names: &GuildNames) -> Self {
+        let config = snapshot.config.iter()
+            .filter_map(|(key, value)| {
+                let value = match validator.kind_for(key).ok()? {
+                    Kind::Role => value.parse::<RoleId>().map_or_else(|_| value.clone(), |r| names.role_name(r)),
+                    Kind::Channel => value.parse::<ChannelId>().map_or_else(|_| value.clone(), |c| names.channel_name(c)),
+                    _ => value.clone()
+                };
+                Some((key.clone(), value))
+            })
+            .collect();
+
+        let role_names = |roles: &[RoleId]| roles.iter().map(|r| names.role_name(*r)).collect();
+        Document {
+            config: Some(config),
+            restricted_commands: Some(snapshot.restricted_commands.clone()),
+            joinable_roles: Some(role_names(&snapshot.joinable_roles)),
+            role_groups: Some(snapshot.role_groups.iter()
+                .map(|g| GroupEntry {
+                    name: g.name.clone(),
+                    exclusive: g.exclusive,
+                    max: g.max,
+                    roles: role_names(&g.roles),
+                })
+                .collect()),
+            filters: Some(snapshot.filters.iter()
+                .map(|f| FilterEntry {
+                    pattern: f.pattern.clone(),
+                    literal: f.literal,
+                    action: f.action.as_str().to_string(),
+                    exempt_channels: f.exempt_channels.iter().map(|c| names.channel_name(*c)).collect(),
+                    exempt_roles: role_names(&f.exempt_roles),
+                })
+                .collect()),
+        }
+    }
+
+    /// Fills in the sections missing from this document with those of another.
+    pub fn or(self, other: Document) -> Self {
+        Document {
+            config: self.config.or(other.config),
+            restricted_commands: self.restricted_commands.or(other.restricted_commands),
+            joinable_roles: self.joinable_roles.or(other.joinable_roles),
+            role_groups: self.role_groups.or(other.role_groups),
+            filters: self.filters.or(other.filters),
+        }
+    }
+
+    /// Writes the document as YAML.
+    pub fn to_yaml(&self) -> Result<String, Error> {
+        let mut doc = Mapping::new();
+        if let Some(config) = &self.config {
+            let mut m = Mapping::new();
+            for (key, value) in config {
+                m.insert(key.as_str().into(), value.as_str().into());
+            }
+            doc.insert(CONFIG.into(), m.into());
+        }
+        if let Some(commands) = &self.restricted_commands {
+            doc.insert(RESTRICTED_COMMANDS.into(), string_seq(commands));
+        }
+        if let Some(roles) = &self.joinable_roles {
+            doc.insert(JOINABLE_ROLES.into(), string_seq(roles));
+        }
+        if let Some(groups) = &self.role_groups {
+            let groups: Vec<Value> = groups.iter()
+                .map(|g| {
+                    let mut m = Mapping::new();
+                    m.insert("name".into(), g.name.as_str().into());
+                    m.insert("exclusive".into(), g.exclusive.into());
+                    if let Some(max) = g.max {
+                        m.insert("max".into(), Value::Number(max.into()));
+                    }
+                    m.insert("roles".into(), string_seq(&g.roles));
+                    m.into()
+                })
+                .collect();
+            doc.insert(ROLE_GROUPS.into(), groups.into());
+        }
+        if let Some(filters) = &self.filters {
+            let filters: Vec<Value> = filters.iter()
+                .map(|f| {
+                    let mut m = Mapping::new();
+                    m.insert("pattern".into(), f.pattern.as_str().into());
+                    m.insert("literal".into(), f.literal.into());
+                    m.insert("action".into(), f.action.as_str().into());
+                    if !f.exempt_channels.is_empty() {
+                        m.insert("exempt_channels".into(), string_seq(&f.exempt_channels));
+                    }
+                    if !f.exempt_roles.is_empty() {
+                        m.insert("exempt_roles".into(), string_seq(&f.exempt_roles));
+                    }
+                    m.into()
+                })
+                .collect();
+            doc.insert(FILTERS.into(), filters.into());
+        }
+
+        Ok(serde_yaml::to_string(&Value::Mapping(doc))?)
+    }
+
+    /// Reads a document from YAML, describing every problem found if it can't.
+    pub fn from_yaml(s: &str) -> Result<Self, Error> {
+        let root: Value = serde_yaml::from_str(s)?;
+        let mut problems = Vec::new();
+        let root = match root.as_mapping() {
+            Some(m) => m,
+            None => return Err(Error::Invalid(vec!["The file must be a mapping of sections.".to_string()]))
+        };
+
+        let mut doc = Document::default();
+        for (section, v) in root {
+            match section.as_str() {
+                Some(s) if s == CONFIG => {
+                    let config = mapping(v, CONFIG, &mut problems).map_or_else(BTreeMap::new, |m| m.iter()
+                        .filter_map(|(key, value)| {
+                            let key = scalar(key)?;
+                            let value = match value {
+                                Value::Sequence(_) => Some(scalar_seq(value, &key, &mut problems).join(" ")),
+                                _ => scalar(value)
+                            };
+                            if value.is_none() {
+                                problems.push(format!("config: {} must be a value or a list of values", key));
+                            }
+                            Some((key, value?))
+                        })
+                        .collect());
+                    doc.config = Some(config);
+                },
+                Some(s) if s == RESTRICTED_COMMANDS => {
+                    doc.restricted_commands = Some(scalar_seq(v, RESTRICTED_COMMANDS, &mut problems));
+                },
+                Some(s) if s == JOINABLE_ROLES => {
+                    doc.joinable_roles = Some(scalar_seq(v, JOINABLE_ROLES, &mut problems));
+                },
+                Some(s) if s == ROLE_GROUPS => {
+                    doc.role_groups = Some(entries(v, ROLE_GROUPS, "role group", &mut problems, parse_group));
+                },
+                Some(s) if s == FILTERS => {
+                    doc.filters = Some(entries(v, FILTERS, "filter", &mut problems, parse_filter));
+                },
+                _ => problems.push(format!("Unknown section {}.", scalar(section).unwrap_or_default()))
+            }
+        }
+
+        if problems.is_empty() {
+            Ok(doc)
+        } else {
+            Err(Error::Invalid(problems))
+        }
+    }
+
+    /// Resolves names into ids and checks every setting, returning a snapshot which can be applied.
+    /// Config values are passed to `check` once their roles and channels have been resolved.
+    /// Missing sections are treated as empty.
+    pub fn resolve(&self, disp: &Dispatch, names: &GuildNames, mut check: impl FnMut(&str, &str) -> config::Result<()>) -> Result<Snapshot, Error> {
+        let mut problems = Vec::new();
+        let mut snapshot = Snapshot::default();
+
+        for (key, value) in self.config.iter().flatten() {
+            let value = match disp.config_validator().kind_for(key) {
+                Ok(Kind::Role) => names.role_id(value).map_or_else(|| value.clone(), |r| r.to_string()),
+                Ok(Kind::Channel) => names.channel_id(value).map_or_else(|| value.clone(), |c| c.to_string()),
+                Ok(_) => value.clone(),
+                Err(e) => {
+                    problems.push(format!("config: {}", e));
+                    continue;
+                }
+            };
+            match check(key, &value) {
+                Ok(()) => { snapshot.config.insert(key.clone(), value); },
+                Err(e) => problems.push(format!("config: {}", e))
+            }
+        }
+
+        for name in self.restricted_commands.iter().flatten() {
+            match check_command(disp, name) {
+                Ok(c) => snapshot.restricted_commands.push(c.to_string()),
+                Err(e) => problems.push(format!("restricted command {}: {}", name, e))
+            }
+        }
+        snapshot.restricted_commands.sort();
+        snapshot.restricted_commands.dedup();
+
+        let mut seen = HashSet::new();
+        let role = |name: &str, what: &str, problems: &mut Vec<String>| {
+            let id = names.role_id(name);
+            if id.is_none() {
+                problems.push(format!("{}: there is no role {}", what, name));
+            }
+            id
+        };
+        for name in self.joinable_roles.iter().flatten() {
+            if let Some(id) = role(name, JOINABLE_ROLES, &mut problems) {
+                if seen.insert(id) {
+                    snapshot.joinable_roles.push(id);
+                } else {
+                    problems.push(format!("{}: {} is listed more than once", JOINABLE_ROLES, name));
+                }
+            }
+        }
+        for g in self.role_groups.iter().flatten() {
+            let what = format!("role group {}", g.name);
+            if let Err(e) = valid_group_name(g.name.clone()) {
+                problems.push(format!("{}: {}", what, e));
+            }
+            if g.roles.is_empty() {
+                problems.push(format!("{}: a group needs at least one role", what));
+            }
+            if snapshot.role_groups.iter().any(|other: &RoleGroup| other.name == g.name) {
+                problems.push(format!("{}: the name is used more than once", what));
+            }
+
+            let mut group = RoleGroup { name: g.name.clone(), exclusive: g.exclusive, max: g.max, roles: Vec::new() };
+            for name in &g.roles {
+                if let Some(id) = role(name, &what, &mut problems) {
+                    if seen.insert(id) {
+                        group.roles.push(id);
+                    } else {
+                        problems.push(format!("{}: {} is listed more than once", what, name));
+                    }
+                }
+            }
+            snapshot.role_groups.push(group);
+        }
+
+        for (i, f) in self.filters.iter().flatten().enumerate() {
+            let what = format!("filter {}", i + 1);
+            let action = f.action.parse::<FilterAction>();
+            if action.is_err() {
+                problems.push(format!("{}: unknown action {}", what, f.action));
+            }
+            if !f.literal {
+                if let Err(e) = validate_pattern(&f.pattern) {
+                    problems.push(format!("{}: {}", what, e));
+                }
+            }
+            let exempt_channels = f.exempt_channels.iter()
+                .filter_map(|c| {
+                    let id = names.channel_id(c);
+                    if id.is_none() {
+                        problems.push(format!("{}: there is no channel {}", what, c));
+                    }
+                    id
+                })
+                .collect();
+            let exempt_roles = f.exempt_roles.iter()
+                .filter_map(|r| role(r, &what, &mut problems))
+                .collect();
+
+            snapshot.filters.push(FilterRule {
+                id: 0,
+                pattern: f.pattern.clone(),
+                literal: f.literal,
+                action: action.unwrap_or(FilterAction::Log),
+                exempt_channels,
+                exempt_roles,
+            });
+        }
+        if problems.is_empty() {
+            if let Err(e) = CompiledFilter::new(snapshot.filters.clone()) {
+                problems.push(format!("filters: the rules could not be compiled together: {}", e));
+            }
+        }
+
+        if problems.is_empty() {
+            Ok(snapshot)
+        } else {
+            Err(Error::Invalid(problems))
+        }
+    }
+
+    /// Describes each setting in the document on a line of its own.
+    fn lines(&self) -> Vec<String> {
+        let mut out = Vec::new();
+        for (key, value) in self.config.iter().flatten() {
+            out.push(format!("config {} = {}", key, value));
+        }
+        for name in self.restricted_commands.iter().flatten() {
+            out.push(format!("restricted command {}", name));
+        }
+        for name in self.joinable_roles.iter().flatten() {
+            out.push(format!("joinable role {}", name));
+        }
+        for g in self.role_groups.iter().flatten() {
+            let mut line = format!("role group {}: {}", g.name, g.roles.join(", "));
+            if g.exclusive {
+                line.push_str(" (exclusive)");
+            }
+            if let Some(max) = g.max {
+                write!(line, " (max {})", max).unwrap();
+            }
+            out.push(line);
+        }
+        for f in self.filters.iter().flatten() {
+            let mut line = format!("filter {} {} {:?}", f.action, if f.literal { "literal" } else { "regex" }, f.pattern);
+            if !f.exempt_channels.is_empty() {
+                write!(line, " except in {}", f.exempt_channels.join(", ")).unwrap();
+            }
+            if !f.exempt_roles.is_empty() {
+                write!(line, " except for {}", f.exempt_roles.join(", ")).unwrap();
+            }
+            out.push(line);
+        }
+        out
+    }
+}
+
+/// Lists the settings which differ between two documents, as lines starting with `-` for settings
+/// only in `old` and `+` for settings only in `new`.
+pub fn diff(old: &Document, new: &Document) -> Vec<String> {
+    let mut added = new.lines();
+    let mut out = Vec::new();
+    for line in old.lines() {
+        match added.iter().position(|l| *l == line) {
+            Some(i) => { added.remove(i); },
+            None => out.push(format!("- {}", line))
+        }
+    }
+    out.extend(added.into_iter().map(|l| format!("+ {}", l)));
+    // Keep each removal next to whatever replaced it.
+    out.sort_by(|a, b| a[2..].cmp(&b[2..]));
+    out
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn names() -> GuildNames {
+        GuildNames::new(
+            vec![(RoleId(1), "EU".to_string()), (RoleId(2), "NA".to_string()), (RoleId(3), "Mods".to_string()), (RoleId(4), "Mods".to_string())]
+                .into_iter().collect(),
+            vec![(ChannelId(10), "general".to_string())].into_iter().collect(),
+        )
+    }
+
+    #[test]
+    fn test_names() {
+        let names = names();
+        assert_eq!(names.role_name(RoleId(1)), "EU");
+        assert_eq!(names.role_name(RoleId(3)), "3");
+        assert_eq!(names.role_name(RoleId(9)), "9");
+        assert_eq!(names.role_id("NA"), Some(RoleId(2)));
+        assert_eq!(names.role_id("<@&2>"), Some(RoleId(2)));
+        assert_eq!(names.role_id("Mods"), None);
+        assert_eq!(names.role_id("4"), Some(RoleId(4)));
+        assert_eq!(names.channel_id("general"), Some(ChannelId(10)));
+    }
+
+    #[test]
+    fn test_yaml_round_trip() {
+        let doc = Document {
+            config: Some(vec![("spam_enabled".to_string(), "true".to_string())].into_iter().collect()),
+            restricted_commands: Some(vec!["ping".to_string()]),
+            joinable_roles: None,
+            role_groups: Some(vec![GroupEntry { name: "region".to_string(), exclusive: true, max: Some(1), roles: vec!["EU".to_string()] }]),
+            filters: Some(vec![FilterEntry {
+                pattern: "bad: word".to_string(),
+                literal: true,
+                action: "delete".to_string(),
+                exempt_channels: vec!["general".to_string()],
+                exempt_roles: Vec::new(),
+            }]),
+        };
+        assert_eq!(Document::from_yaml(&doc.to_yaml().unwrap()).unwrap(), doc);
+    }
+
+    #[test]
+    fn test_from_yaml() {
+        let doc = Document::from_yaml("config:\n  spam_enabled: true\n  command_prefix: ['!', '?']\njoinable_roles: [EU, 2]\n").unwrap();
+        let config = doc.config.unwrap();
+        assert_eq!(config["spam_enabled"], "true");
+        assert_eq!(config["command_prefix"], "! ?");
+        assert_eq!(doc.joinable_roles.unwrap(), vec!["EU", "2"]);
+        assert!(doc.filters.is_none());
+
+        match Document::from_yaml("filters:\n  - pattern: x\nroles: []\n") {
+            Err(Error::Invalid(problems)) => assert_eq!(problems, vec!["filter 1: missing action", "Unknown section roles."]),
+            r => panic!("unexpected result {:?}", r)
+        }
+        assert!(matches!(Document::from_yaml("config: ["), Err(Error::Yaml(_))));
+    }
+
+    #[test]
+    fn test_diff() {
+        let old = Document {
+            config: Some(vec![("a".to_string(), "1".to_string()), ("b".to_string(), "2".to_string())].into_iter().collect()),
+            joinable_roles: Some(vec!["EU".to_string()]),
+            ..Document::default()
+        };
+        let new = Document {
+            config: Some(vec![("a".to_string(), "1".to_string()), ("b".to_string(), "3".to_string())].into_iter().collect()),
+            joinable_roles: Some(vec!["NA".to_string()]),
+            ..Document::default()
+        };
+        assert_eq!(diff(&old, &new), vec![
+            "- config b = 2",
+            "+ config b = 3",
+            "- joinable role EU",
+            "+ joinable role NA",
+        ]);
+        assert!(diff(&old, &old).is_empty());
+    }
+}
\ No newline at end of file
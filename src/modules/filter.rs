@@ -0,0 +1,421 @@
+//  Glimbot - A Discord anti-spam and administration bot.
+//  Copyright (C) 2020 Nick Samson
+
+//  This program is free software: you can redistribute it and/or modify
+//  it under the terms of the GNU General Public License as published by
+//  the Free Software Foundation, either version 3 of the License, or
+//  (at your option) any later version.
+
+//  This program is distributed in the hope that it will be useful,
+//  but WITHOUT ANY WARRANTY; without even the implied warranty of
+//  MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+//  GNU General Public License for more details.
+
+//  You should have received a copy of the GNU General Public License
+//  along with this program.  If not, see <https://www.gnu.org/licenses/>.
+
+//! The word filter. Guilds define rules matching message content, either literally or by regex,
+//! always ignoring case. Each rule has an action, and may exempt channels or roles.
+//!
+//! A guild's rules are compiled into a single [RegexSet], which is cached until a rule changes.
+//! When several rules match a message, only the most severe action is taken.
+
+use crate::modules::commands::Command;
+use crate::modules::{Module, config};
+use crate::modules::config::Kind;
+use crate::modules::hook::Error::DeniedWithReason;
+use crate::modules::modlog::{record, AuditEntry};
+use crate::modules::punishment::{Action, Target};
+use crate::modules::roles::resolve_role;
+use crate::dispatch::Dispatch;
+use crate::args::{parse_app_matches, subcommand_name, subcommand_names};
+use crate::util::help_str;
+use crate::db::cache::get_cached_connection;
+use crate::db::{FilterRule, FilterAction, GuildConn};
+use serenity::prelude::Context;
+use serenity::model::channel::Message;
+use serenity::model::id::{GuildId, ChannelId, RoleId};
+use serenity::utils::MessageBuilder;
+use std::borrow::Cow;
+use std::collections::HashMap;
+use std::fmt::Write;
+use std::str::FromStr;
+use std::sync::Arc;
+use once_cell::unsync::Lazy;
+use once_cell::sync::Lazy as SyncLazy;
+use parking_lot::RwLock;
+use regex::{RegexBuilder, RegexSet, RegexSetBuilder};
+use clap::{App, AppSettings, Arg, ArgMatches, SubCommand};
+
+static FILTER_PRESSURE_KEY: &str = "filter_pressure";
+const DEFAULT_FILTER_PRESSURE: i64 = 30;
+
+/// The most memory a single compiled pattern may use.
+pub const PATTERN_SIZE_LIMIT: usize = 1 << 16;
+/// The most memory a guild's compiled rule set may use.
+pub const SET_SIZE_LIMIT: usize = 1 << 22;
+
+// Compiled rule sets by guild. Entries are dropped whenever a guild's rules change.
+static FILTERS: SyncLazy<RwLock<HashMap<GuildId, Arc<CompiledFilter>>>> = SyncLazy::new(
+    || RwLock::new(HashMap::new())
+);
+
+/// A guild's filter rules, compiled into a single set. Pattern `i` of the set belongs to rule `i`.
+#[derive(Debug)]
+pub struct CompiledFilter {
+    set: RegexSet,
+    rules: Vec<FilterRule>,
+}
+
+impl CompiledFilter {
+    /// Compiles a set of rules.
+    pub fn new(rules: Vec<FilterRule>) -> Result<Self, regex::Error> {
+        let set = RegexSetBuilder::new(rules.iter().map(rule_regex))
+            .case_insensitive(true)
+            .size_limit(SET_SIZE_LIMIT)
+            .build()?;
+        Ok(CompiledFilter { set, rules })
+    }
+
+    /// Finds the most severe rule which matches the content and applies in the channel to a user
+    /// with the given roles.
+    pub fn check(&self, content: &str, channel: ChannelId, roles: &[RoleId]) -> Option<&FilterRule> {
+        self.set.matches(content).into_iter()
+            .map(|i| &self.rules[i])
+            .filter(|r| !r.exempt_channels.contains(&channel) && !r.exempt_roles.iter().any(|role| roles.contains(role)))
+            .max_by_key(|r| r.action)
+    }
+}
+
+fn rule_regex(rule: &FilterRule) -> Cow<'_, str> {
+    if rule.literal {
+        Cow::Owned(regex::escape(&rule.pattern))
+    } else {
+        Cow::Borrowed(&rule.pattern)
+    }
+}
+
+/// Checks that a pattern compiles, returning a user error describing the problem if it doesn't.
+pub fn validate_pattern(pattern: &str) -> super::hook::Result<()> {
+    RegexBuilder::new(pattern)
+        .case_insensitive(true)
+        .size_limit(PATTERN_SIZE_LIMIT)
+        .build()
+        .map(|_| ())
+        .map_err(|e| DeniedWithReason(format!("Invalid pattern: {}", e).into()))
+}
+
+/// Checks that a guild's rules compile together, returning a user error if they don't. Each pattern
+/// may be valid on its own while the set is still too large.
+pub fn validate_rules(rules: Vec<FilterRule>) -> super::hook::Result<()> {
+    CompiledFilter::new(rules)
+        .map(|_| ())
+        .map_err(|e| DeniedWithReason(format!("The filter rules could not be compiled together: {}", e).into()))
+}
+
+/// Retrieves the guild's compiled rules, compiling and caching them if needed.
+fn compiled_filter(conn: &GuildConn) -> super::hook::Result<Arc<CompiledFilter>> {
+    let guild = *conn.as_id();
+    if let Some(f) = FILTERS.read().get(&guild) {
+        return Ok(f.clone());
+    }
+
+    let filter = CompiledFilter::new(conn.filter_rules()?)
+        .map_err(|e| DeniedWithReason(format!("The filter rules could not be compiled: {}", e).into()))?;
+    let filter = Arc::new(filter);
+    FILTERS.write().insert(guild, filter.clone());
+    Ok(filter)
+}
+
+/// Drops the guild's compiled rules, so they are compiled again from the database when next needed.
+pub fn invalidate(guild: GuildId) {
+    FILTERS.write().remove(&guild);
+}
+
+fn filter_hook(disp: &Dispatch, ctx: &Context, msg: &Message) -> super::hook::Result<()> {
+    let guild = match msg.guild_id {
+        Some(g) => g,
+        None => return Ok(())
+    };
+
+    let conn = get_cached_connection(guild)?;
+    let rconn = conn.as_ref().borrow();
+
+    let filter = compiled_filter(&rconn)?;
+    let roles = msg.member.as_ref().map_or(&[][..], |m| &m.roles[..]);
+    let rule = match filter.check(&msg.content, msg.channel_id, roles) {
+        Some(r) => r,
+        None => return Ok(())
+    };
+
+    debug!("Message {} from {} in guild {} matched filter rule #{}", msg.id, msg.author.id, guild, rule.id);
+    let target = Target::from_message(msg);
+    let reason = format!("Matched filter rule #{}.", rule.id);
+    let audit_action = match rule.action {
+        FilterAction::Log => "Matched filter",
+        FilterAction::Pressure => {
+            let pressure = disp.get_config::<i64>(&rconn, FILTER_PRESSURE_KEY)?;
+            if !rconn.add_pressure(msg.author.id, msg.id, pressure)? {
+                // The pressure engine is disabled, so there's nothing to record.
+                return Ok(());
+            }
+            "Added filter pressure"
+        },
+        FilterAction::Delete => {
+            Action::Delete.apply(ctx, &rconn, &target, &reason)?;
+            "Deleted message"
+        },
+        FilterAction::Warn => {
+            Action::Delete.apply(ctx, &rconn, &target, &reason)?;
+            Action::Warn.apply(ctx, &rconn, &target, "Your message was removed by the filter.")?;
+            "Deleted message and warned"
+        },
+    };
+
+    let entry = AuditEntry::automatic(ctx, audit_action, format!("<@{}>", msg.author.id))
+        .with_reason(&reason);
+    record(disp, ctx, &rconn, &entry);
+    Ok(())
+}
+
+fn describe(ctx: &Context, rule: &FilterRule) -> String {
+    let mut out = format!("#{} {} {} {:?}", rule.id, rule.action.as_str(), if rule.literal { "literal" } else { "regex" }, rule.pattern);
+    for c in &rule.exempt_channels {
+        write!(out, " except in #{}", c.name(ctx).unwrap_or_else(|| c.to_string())).unwrap();
+    }
+    for r in &rule.exempt_roles {
+        write!(out, " except for role {}", r.to_role_cached(ctx).map_or_else(|| r.to_string(), |r| r.name)).unwrap();
+    }
+    out
+}
+
+/// ZST struct for processing the `filter` command
+pub struct Filter;
+
+thread_local! {
+    static PARSER: Lazy<App<'static, 'static>> = Lazy::new(
+        || {
+            App::new("filter")
+                .about("Command for managing the rules messages are filtered by. Matching always ignores case.")
+                .setting(AppSettings::SubcommandRequiredElseHelp)
+                .subcommand(
+                    SubCommand::with_name("add")
+                        .about("Adds a filter rule.")
+                        .arg(Arg::with_name("pattern")
+                            .value_name("PATTERN")
+                            .help("The regex to match. Quote it if it contains spaces.")
+                            .takes_value(true)
+                            .required(true)
+                        )
+                        .arg(Arg::with_name("literal")
+                            .long("literal")
+                            .short("l")
+                            .help("Matches the pattern as plain text rather than as a regex.")
+                        )
+                        .arg(Arg::with_name("action")
+                            .long("action")
+                            .short("a")
+                            .value_name("ACTION")
+                            .help("What to do with matching messages.")
+                            .takes_value(true)
+                            .possible_values(&["delete", "warn", "pressure", "log"])
+                            .default_value("delete")
+                        )
+                        .arg(Arg::with_name("exempt_channel")
+                            .long("exempt-channel")
+                            .short("c")
+                            .value_name("CHANNEL")
+                            .help("A channel the rule doesn't apply in. May be given more than once.")
+                            .takes_value(true)
+                            .multiple(true)
+                            .number_of_values(1)
+                        )
+                        .arg(Arg::with_name("exempt_role")
+                            .long("exempt-role")
+                            .short("r")
+                            .value_name("ROLE")
+                            .help("A role whose members the rule doesn't apply to. May be given more than once.")
+                            .takes_value(true)
+                            .multiple(true)
+                            .number_of_values(1)
+                        )
+                )
+                .subcommand(
+                    SubCommand::with_name("list")
+                        .about("Lists the filter rules in this guild.")
+                )
+                .subcommand(
+                    SubCommand::with_name("remove")
+                        .about("Removes a filter rule by its number, as shown by `list`.")
+                        .arg(Arg::with_name("id")
+                            .value_name("ID")
+                            .help("The number of the rule.")
+                            .takes_value(true)
+                            .required(true)
+                        )
+                )
+        }
+    );
+}
+
+impl Command for Filter {
+    fn invoke(&self, disp: &Dispatch, ctx: &Context, msg: &Message, args: Cow<str>) -> super::commands::Result<()> {
+        let m: ArgMatches = PARSER.with(|p| parse_app_matches("filter", args, p))?;
+
+        let guild = msg.guild(ctx).unwrap();
+        let rg = guild.read();
+        let conn = get_cached_connection(rg.id)?;
+        let rconn = conn.borrow();
+
+        let reply = match m.subcommand() {
+            ("add", Some(m)) => {
+                let pattern = m.value_of("pattern").unwrap();
+                let literal = m.is_present("literal");
+                if !literal {
+                    validate_pattern(pattern)?;
+                }
+                let action = m.value_of("action").unwrap().parse::<FilterAction>().unwrap();
+
+                let channels = m.values_of("exempt_channel").into_iter().flatten()
+                    .map(|c| ChannelId::from_str(c)
+                        .ok()
+                        .filter(|c| rg.channels.contains_key(c))
+                        .ok_or_else(|| DeniedWithReason("No such channel.".into())))
+                    .collect::<Result<Vec<_>, _>>()?;
+                let roles = m.values_of("exempt_role").into_iter().flatten()
+                    .map(|r| resolve_role(&rg, r).map(|r| r.id))
+                    .collect::<Result<Vec<_>, _>>()?;
+
+                let mut rules = rconn.filter_rules()?;
+                rules.push(FilterRule {
+                    id: 0,
+                    pattern: pattern.to_string(),
+                    literal,
+                    action,
+                    exempt_channels: channels.clone(),
+                    exempt_roles: roles.clone(),
+                });
+                validate_rules(rules)?;
+
+                let id = rconn.add_filter_rule(pattern, literal, action, &channels, &roles)?;
+                invalidate(rg.id);
+                record(disp, ctx, &rconn, &AuditEntry::new(msg.author.id, "Added filter rule", format!("#{}", id))
+                    .with_reason(format!("{} {:?}", action.as_str(), pattern)));
+                format!("Added filter rule #{}.", id)
+            },
+            ("list", Some(_)) => {
+                let rules = rconn.filter_rules()?;
+                if rules.is_empty() {
+                    "No filter rules are set.".to_string()
+                } else {
+                    rules.iter()
+                        .map(|r| describe(ctx, r))
+                        .collect::<Vec<_>>()
+                        .join("\n")
+                }
+            },
+            ("remove", Some(m)) => {
+                let id = m.value_of("id").unwrap().trim_start_matches('#').parse::<i64>()
+                    .map_err(|_| DeniedWithReason("Rule numbers must be integers.".into()))?;
+                if rconn.remove_filter_rule(id)? {
+                    invalidate(rg.id);
+                    record(disp, ctx, &rconn, &AuditEntry::new(msg.author.id, "Removed filter rule", format!("#{}", id)));
+                    format!("Removed filter rule #{}.", id)
+                } else {
+                    format!("There is no filter rule #{}.", id)
+                }
+            },
+            _ => unreachable!()
+        };
+
+        msg.channel_id.say(ctx, MessageBuilder::new()
+            .push_codeblock_safe(reply, None)
+            .build())?;
+
+        Ok(())
+    }
+
+    fn subcommand(&self, args: &str) -> Option<String> {
+        PARSER.with(|p| subcommand_name("filter", args, p))
+    }
+
+    fn subcommands(&self) -> Vec<String> {
+        PARSER.with(|p| subcommand_names(p))
+    }
+
+    fn help(&self) -> Cow<'static, str> {
+        PARSER.with(|p| help_str(p).into())
+    }
+}
+
+/// Creates the filter module.
+pub fn filter_mod() -> Module {
+    Module::with_name("filter")
+        .with_command(Filter)
+        .with_config_value(config::Value::new(
+            FILTER_PRESSURE_KEY,
+            "The pressure added to messages matching a filter rule with the pressure action.",
+            Kind::UINT,
+            Some(DEFAULT_FILTER_PRESSURE.to_string()),
+        ).with_sensitivity(true))
+        .with_message_hook(filter_hook)
+        .with_sensitivity(true)
+        // The spam module's hook must record messages before we can add to their pressure.
+        .with_dependency("spam")
+        .with_dependency("punishment")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn rule(id: i64, pattern: &str, literal: bool, action: FilterAction) -> FilterRule {
+        FilterRule {
+            id,
+            pattern: pattern.to_string(),
+            literal,
+            action,
+            exempt_channels: Vec::new(),
+            exempt_roles: Vec::new(),
+        }
+    }
+
+    #[test]
+    fn test_validate_pattern() {
+        assert!(validate_pattern(r"\bfree\s+nitro\b").is_ok());
+        assert!(validate_pattern("(unclosed").is_err());
+        assert!(validate_pattern(r"\w{1000}{1000}").is_err());
+    }
+
+    #[test]
+    fn test_validate_rules() {
+        // Each pattern fits within the per-pattern limit, but not all of them within the set limit.
+        let pattern = "[a-z]{200}";
+        assert!(validate_pattern(pattern).is_ok());
+        let mut rules: Vec<_> = (0..8).map(|i| rule(i, pattern, false, FilterAction::Log)).collect();
+        assert!(validate_rules(rules.clone()).is_ok());
+        rules.extend((8..200).map(|i| rule(i, pattern, false, FilterAction::Log)));
+        assert!(validate_rules(rules).is_err());
+    }
+
+    #[test]
+    fn test_compiled_filter() {
+        let mut exempt = rule(3, "f.ck", true, FilterAction::Warn);
+        exempt.exempt_channels.push(ChannelId(10));
+        exempt.exempt_roles.push(RoleId(20));
+        let filter = CompiledFilter::new(vec![
+            rule(1, r"free\s+nitro", false, FilterAction::Delete),
+            rule(2, "nitro", true, FilterAction::Log),
+            exempt,
+        ]).unwrap();
+
+        let check = |s, channel, roles: &[RoleId]| filter.check(s, ChannelId(channel), roles).map(|r| r.id);
+        assert_eq!(check("FREE   Nitro here", 1, &[]), Some(1));
+        assert_eq!(check("just nitro", 1, &[]), Some(2));
+        assert_eq!(check("fuck", 1, &[]), None);
+        assert_eq!(check("F.CK nitro", 1, &[]), Some(3));
+        assert_eq!(check("f.ck nitro", 10, &[]), Some(2));
+        assert_eq!(check("f.ck", 1, &[RoleId(5), RoleId(20)]), None);
+        assert_eq!(check("hello", 1, &[]), None);
+    }
+}
\ No newline at end of file
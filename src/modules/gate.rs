@@ -0,0 +1,577 @@
+//  Glimbot - A Discord anti-spam and administration bot.
+//  Copyright (C) 2020 Nick Samson
+
+//  This program is free software: you can redistribute it and/or modify
+//  it under the terms of the GNU General Public License as published by
+//  the Free Software Foundation, either version 3 of the License, or
+//  (at your option) any later version.
+
+//  This program is distributed in the hope that it will be useful,
+//  but WITHOUT ANY WARRANTY; without even the implied warranty of
+//  MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+//  GNU General Public License for more details.
+
+//  You should have received a copy of the GNU General Public License
+//  along with this program.  If not, see <https://www.gnu.org/licenses/>.
+
+//! The join gate. New members are given a quarantine role and held in the `gate_pending` table
+//! until they pass a check: reacting to the gate message, running `verify`, or their account
+//! reaching the minimum age. Members who never pass are kicked by a `gate_timeout` job. Accounts
+//! already past the minimum age skip the gate entirely.
+//!
+//! The quarantine role must not be joinable, or held members could just leave it, so it can be
+//! neither set to a joinable role nor made joinable afterwards. Nor may it be a staff role, which
+//! would hand every new member that role's tier.
+
+use crate::modules::commands::Command;
+use crate::modules::{Module, config, hook};
+use crate::modules::config::Kind;
+use crate::modules::hook::Event;
+use crate::modules::modlog::{record, AuditEntry};
+use crate::modules::roles::{resolve_role, resolve_user, is_staff_role};
+use crate::dispatch::{Dispatch, KeyRetrievalError};
+use crate::args::{parse_app_matches, subcommand_name, subcommand_names};
+use crate::error::SerenityError;
+use crate::util::help_str;
+use crate::db::cache::get_cached_connection;
+use crate::db::{GuildConn, Job};
+use serenity::prelude::Context;
+use serenity::model::channel::Message;
+use serenity::model::id::{GuildId, RoleId, UserId};
+use serenity::utils::MessageBuilder;
+use std::borrow::Cow;
+use std::sync::Arc;
+use once_cell::unsync::Lazy;
+use chrono::{DateTime, Duration, Utc};
+use clap::{App, AppSettings, Arg, ArgMatches, SubCommand};
+
+static GATE_ENABLED_KEY: &str = "gate_enabled";
+static GATE_ROLE_KEY: &str = "gate_role";
+static GATE_MESSAGE_KEY: &str = "gate_message";
+static MIN_ACCOUNT_AGE_KEY: &str = "gate_min_account_age";
+static TIMEOUT_KEY: &str = "gate_timeout";
+
+const DEFAULT_ENABLED: bool = false;
+const DEFAULT_MIN_ACCOUNT_AGE: i64 = 0; // Seconds
+const DEFAULT_TIMEOUT: i64 = 86400; // Seconds
+
+static TIMEOUT_JOB: &str = "gate_timeout";
+static RELEASE_JOB: &str = "gate_release";
+
+/// Retrieves the guild's quarantine role, if one is set.
+pub fn gate_role(disp: &Dispatch, conn: &GuildConn) -> Result<Option<RoleId>, KeyRetrievalError> {
+    disp.get_optional_config(conn, GATE_ROLE_KEY)
+}
+
+fn setting_secs(disp: &Dispatch, conn: &GuildConn, key: &str) -> Result<Duration, KeyRetrievalError> {
+    Ok(Duration::seconds(disp.get_config::<u32>(conn, key)?.into()))
+}
+
+/// Whether the role is the guild's quarantine role. Always false if the gate module isn't loaded.
+pub fn is_gate_role(disp: &Dispatch, conn: &GuildConn, role: RoleId) -> Result<bool, KeyRetrievalError> {
+    if disp.config_validator().check_key(GATE_ROLE_KEY).is_err() {
+        return Ok(false);
+    }
+    Ok(gate_role(disp, conn)? == Some(role))
+}
+
+/// Checks that the quarantine role is neither joinable nor a staff role.
+fn check_gate_role(_disp: &Dispatch, _ctx: &Context, conn: &GuildConn, s: &str) -> Result<(), Cow<'static, str>> {
+    let checks = s.parse::<RoleId>().ok()
+        .map(|r| -> crate::db::Result<_> { Ok((conn.role_is_joinable(r)?, is_staff_role(conn, r)?)) });
+    match checks {
+        Some(Ok((false, false))) => Ok(()),
+        Some(Ok((true, _))) => Err("the quarantine role must not be joinable".into()),
+        Some(Ok((_, true))) => Err("the quarantine role must not be a staff role".into()),
+        _ => Err("couldn't check the role".into())
+    }
+}
+
+/// Lets a user through the gate, removing their quarantine role. Returns false if they weren't held.
+fn pass(disp: &Dispatch, ctx: &Context, conn: &GuildConn, user: UserId, entry: AuditEntry) -> hook::Result<bool> {
+    if !conn.is_gate_pending(user)? {
+        return Ok(false);
+    }
+
+    if let Some(role) = gate_role(disp, conn)? {
+        ctx.http.remove_member_role(conn.as_id().0, user.0, role.0)?;
+    }
+
+    release(conn, user)?;
+    record(disp, ctx, conn, &entry);
+    Ok(true)
+}
+
+/// Forgets a held user, cancelling their jobs.
+fn release(conn: &GuildConn, user: UserId) -> crate::db::Result<()> {
+    let payload = user.to_string();
+    conn.remove_gate_pending(user)?;
+    conn.cancel_jobs(TIMEOUT_JOB, &payload)?;
+    conn.cancel_jobs(RELEASE_JOB, &payload)?;
+    Ok(())
+}
+
+/// Returns the quarantine role if a user whose account was created at the given time must be held.
+fn must_hold(disp: &Dispatch, conn: &GuildConn, created: &DateTime<Utc>, now: &DateTime<Utc>) -> Result<Option<RoleId>, KeyRetrievalError> {
+    let enabled = disp.get_config::<bool>(conn, GATE_ENABLED_KEY)?;
+    let role = match gate_role(disp, conn)? {
+        Some(r) if enabled => r,
+        _ => return Ok(None)
+    };
+
+    let min_age = setting_secs(disp, conn, MIN_ACCOUNT_AGE_KEY)?;
+    if min_age > Duration::zero() && *now - *created >= min_age {
+        return Ok(None);
+    }
+
+    Ok(Some(role))
+}
+
+/// Records a held user and schedules their timeout, and their release once their account is old enough.
+fn schedule_hold(disp: &Dispatch, conn: &GuildConn, user: UserId, created: &DateTime<Utc>, now: &DateTime<Utc>) -> hook::Result<()> {
+    conn.add_gate_pending(user, now)?;
+
+    let payload = user.to_string();
+    let timeout = setting_secs(disp, conn, TIMEOUT_KEY)?;
+    let timeout_at = Some(*now + timeout).filter(|_| timeout > Duration::zero());
+    if let Some(t) = timeout_at {
+        conn.schedule_job(TIMEOUT_JOB, &payload, &t, None)?;
+    }
+
+    let min_age = setting_secs(disp, conn, MIN_ACCOUNT_AGE_KEY)?;
+    let release_at = *created + min_age;
+    if min_age > Duration::zero() && !matches!(timeout_at, Some(t) if release_at >= t) {
+        conn.schedule_job(RELEASE_JOB, &payload, &release_at, None)?;
+    }
+
+    Ok(())
+}
+
+fn hold(disp: &Dispatch, ctx: &Context, guild: GuildId, user: UserId, created: DateTime<Utc>) -> hook::Result<()> {
+    let conn = get_cached_connection(guild)?;
+    let rconn = conn.borrow();
+
+    let now = disp.scheduler().clock().now();
+    let role = match must_hold(disp, &rconn, &created, &now)? {
+        Some(r) => r,
+        None => {
+            trace!("User {} does not need to pass the gate in guild {}", user, guild);
+            return Ok(());
+        }
+    };
+
+    ctx.http.add_member_role(guild.0, user.0, role.0)?;
+    schedule_hold(disp, &rconn, user, &created, &now)?;
+    debug!("Holding user {} at the gate in guild {}", user, guild);
+    Ok(())
+}
+
+fn gate_hook(disp: &Dispatch, ctx: &Context, event: &Event) -> hook::Result<()> {
+    match event {
+        Event::MemberAdd { guild, member } => {
+            let (user, created, bot) = {
+                let u = member.user.read();
+                (u.id, u.created_at().with_timezone(&Utc), u.bot)
+            };
+            if !bot {
+                hold(disp, ctx, *guild, user, created)?;
+            }
+        },
+        Event::MemberRemove { guild, user, .. } => {
+            let conn = get_cached_connection(*guild)?;
+            release(&conn.borrow(), user.id)?;
+        },
+        Event::ReactionAdd(r) => {
+            let guild = match r.guild_id {
+                Some(g) => g,
+                None => return Ok(())
+            };
+
+            let conn = get_cached_connection(guild)?;
+            let rconn = conn.borrow();
+            let gate_message = disp.get_optional_config::<u64>(&rconn, GATE_MESSAGE_KEY)?;
+
+            if gate_message == Some(r.message_id.0) {
+                let entry = AuditEntry::automatic(ctx, "Passed gate", format!("<@{}>", r.user_id))
+                    .with_reason("Reacted to the gate message.");
+                pass(disp, ctx, &rconn, r.user_id, entry)?;
+            }
+        },
+        _ => {}
+    }
+
+    Ok(())
+}
+
+fn user_payload(job: &Job) -> Option<UserId> {
+    job.payload.parse::<u64>().ok().map(UserId)
+}
+
+fn timeout_job(disp: &Dispatch, ctx: &Context, conn: &GuildConn, job: &Job) -> hook::Result<()> {
+    let user = match user_payload(job) {
+        Some(u) => u,
+        None => {
+            warn!("Dropping gate timeout job {} with malformed payload {:?}", job.id, job.payload);
+            return Ok(());
+        }
+    };
+    if !conn.is_gate_pending(user)? {
+        return Ok(());
+    }
+
+    let reason = "Did not verify in time.";
+    match conn.as_id().kick_with_reason(ctx, user, reason).map_err(SerenityError::from) {
+        Ok(()) => {
+            let entry = AuditEntry::automatic(ctx, "kick", format!("<@{}>", user)).with_reason(reason);
+            record(disp, ctx, conn, &entry);
+        },
+        Err(e) if matches!(e.unsuccessful_request(), Some(r) if r.status_code.is_client_error()) => {
+            warn!("Could not kick unverified user {} in guild {}: {}", user, conn.as_id(), e);
+        },
+        Err(e) => return Err(e.into())
+    }
+
+    release(conn, user)?;
+    Ok(())
+}
+
+fn release_job(disp: &Dispatch, ctx: &Context, conn: &GuildConn, job: &Job) -> hook::Result<()> {
+    let user = match user_payload(job) {
+        Some(u) => u,
+        None => {
+            warn!("Dropping gate release job {} with malformed payload {:?}", job.id, job.payload);
+            return Ok(());
+        }
+    };
+
+    let entry = AuditEntry::automatic(ctx, "Passed gate", format!("<@{}>", user))
+        .with_reason("The account reached the minimum age.");
+    pass(disp, ctx, conn, user, entry)?;
+    Ok(())
+}
+
+/// ZST struct for processing the `gate` command
+pub struct Gate;
+
+thread_local! {
+    static GATE_PARSER: Lazy<App<'static, 'static>> = Lazy::new(
+        || {
+            App::new("gate")
+                .about("Command for managing the join gate.")
+                .setting(AppSettings::SubcommandRequiredElseHelp)
+                .subcommand(
+                    SubCommand::with_name("setup")
+                        .about("Sets the quarantine role and turns the gate on.")
+                        .arg(Arg::with_name("role")
+                            .value_name("ROLE")
+                            .help("The role held users are given. It must be neither joinable nor a staff role.")
+                            .takes_value(true)
+                            .required(true)
+                        )
+                )
+                .subcommand(
+                    SubCommand::with_name("list")
+                        .about("Lists the users held at the gate.")
+                )
+                .subcommand(
+                    SubCommand::with_name("pass")
+                        .about("Lets a held user through the gate.")
+                        .arg(Arg::with_name("user")
+                            .value_name("USER")
+                            .help("The user to let through.")
+                            .takes_value(true)
+                            .required(true)
+                        )
+                )
+        }
+    );
+}
+
+impl Command for Gate {
+    fn invoke(&self, disp: &Dispatch, ctx: &Context, msg: &Message, args: Cow<str>) -> super::commands::Result<()> {
+        let m: ArgMatches = GATE_PARSER.with(|p| parse_app_matches("gate", args, p))?;
+
+        let guild = msg.guild(ctx).unwrap();
+        let rg = guild.read();
+        let conn = get_cached_connection(rg.id)?;
+        let rconn = conn.borrow();
+
+        let reply = match m.subcommand() {
+            ("setup", Some(m)) => {
+                let role = resolve_role(&rg, m.value_of("role").unwrap())?;
+                disp.set_config(ctx, &rconn, GATE_ROLE_KEY, role.id.to_string())?;
+                disp.set_config(ctx, &rconn, GATE_ENABLED_KEY, "true")?;
+                record(disp, ctx, &rconn, &AuditEntry::new(msg.author.id, "Set up gate", format!("<@&{}>", role.id)));
+                format!("New members will be held with role {}.", role.name)
+            },
+            ("list", Some(_)) => {
+                let pending = rconn.gate_pending()?;
+                if pending.is_empty() {
+                    "No users are held at the gate.".to_string()
+                } else {
+                    pending.iter()
+                        .map(|(u, t)| {
+                            let name = rg.members.get(u).map_or_else(|| u.to_string(), |m| m.display_name().into_owned());
+                            format!("{} since {}", name, t.format("%Y-%m-%d %H:%M:%S UTC"))
+                        })
+                        .collect::<Vec<_>>()
+                        .join("\n")
+                }
+            },
+            ("pass", Some(m)) => {
+                let user = resolve_user(&rg, m.value_of("user").unwrap())?;
+                let entry = AuditEntry::new(msg.author.id, "Passed gate", format!("<@{}>", user))
+                    .with_reason("Let through by staff.");
+                if pass(disp, ctx, &rconn, user, entry)? {
+                    "Let the user through.".to_string()
+                } else {
+                    "That user isn't held at the gate.".to_string()
+                }
+            },
+            _ => unreachable!()
+        };
+
+        msg.channel_id.say(ctx, MessageBuilder::new()
+            .push_codeblock_safe(reply, None)
+            .build())?;
+
+        Ok(())
+    }
+
+    fn subcommand(&self, args: &str) -> Option<String> {
+        GATE_PARSER.with(|p| subcommand_name("gate", args, p))
+    }
+
+    fn subcommands(&self) -> Vec<String> {
+        GATE_PARSER.with(|p| subcommand_names(p))
+    }
+
+    fn help(&self) -> Cow<'static, str> {
+        GATE_PARSER.with(|p| help_str(p).into())
+    }
+}
+
+/// ZST struct for processing the `verify` command
+pub struct Verify;
+
+thread_local! {
+    static VERIFY_PARSER: Lazy<App<'static, 'static>> = Lazy::new(
+        || App::new("verify").about("Lets you through the join gate.")
+    );
+}
+
+impl Command for Verify {
+    fn invoke(&self, disp: &Dispatch, ctx: &Context, msg: &Message, args: Cow<str>) -> super::commands::Result<()> {
+        VERIFY_PARSER.with(|p| parse_app_matches("verify", args, p))?;
+
+        let guild = msg.guild_id.unwrap();
+        let conn = get_cached_connection(guild)?;
+        let rconn = conn.borrow();
+
+        let entry = AuditEntry::new(msg.author.id, "Passed gate", format!("<@{}>", msg.author.id))
+            .with_reason("Ran verify.");
+        let reply = if pass(disp, ctx, &rconn, msg.author.id, entry)? {
+            "You have been verified."
+        } else {
+            "You aren't waiting to be verified."
+        };
+
+        msg.channel_id.say(ctx, MessageBuilder::new()
+            .push_codeblock_safe(reply, None)
+            .build())?;
+
+        Ok(())
+    }
+
+    fn help(&self) -> Cow<'static, str> {
+        VERIFY_PARSER.with(|p| help_str(p).into())
+    }
+}
+
+/// Creates the gate module.
+pub fn gate_mod() -> Module {
+    Module::with_name("gate")
+        .with_command(Gate)
+        .with_config_value(config::Value::new(
+            GATE_ENABLED_KEY,
+            "Whether new members are held at the gate until they verify. Default is false.",
+            Kind::Bool,
+            Some(DEFAULT_ENABLED.to_string()),
+        ))
+        .with_config_value(config::Value::new(
+            GATE_ROLE_KEY,
+            "The role given to members held at the gate. It must be neither joinable nor a staff role.",
+            Kind::Role,
+            Option::<String>::None,
+        ).with_check(Arc::new(check_gate_role)))
+        .with_config_value(config::Value::new(
+            GATE_MESSAGE_KEY,
+            "The id of a message which held members can react to in order to verify.",
+            Kind::Int { min: 0, max: i64::MAX },
+            Option::<String>::None,
+        ))
+        .with_config_value(config::Value::new(
+            MIN_ACCOUNT_AGE_KEY,
+            "Accounts at least this many seconds old skip the gate, and held members are let through once their account reaches it. 0 disables the check.",
+            Kind::UINT,
+            Some(DEFAULT_MIN_ACCOUNT_AGE.to_string()),
+        ).with_sensitivity(true))
+        .with_config_value(config::Value::new(
+            TIMEOUT_KEY,
+            "The number of seconds held members have to verify before they are kicked. 0 never kicks.",
+            Kind::UINT,
+            Some(DEFAULT_TIMEOUT.to_string()),
+        ))
+        .with_event_hook(gate_hook)
+        .with_job_handler(TIMEOUT_JOB, timeout_job)
+        .with_job_handler(RELEASE_JOB, release_job)
+        .with_sensitivity(true)
+        .with_dependency("roles")
+        .with_dependency("modlog")
+}
+
+/// Creates the verify module, which lets members through the gate.
+pub fn verify_mod() -> Module {
+    Module::with_name("verify")
+        .with_command(Verify)
+        .with_sensitivity(false)
+        .with_dependency("gate")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::util::test_guild_conn;
+    use crate::modules::modlog::modlog_mod;
+    use crate::util::test_context;
+    use chrono::TimeZone;
+    use rusqlite::NO_PARAMS;
+    use tempdir::TempDir;
+
+    fn setup() -> (TempDir, GuildConn, Dispatch) {
+        let (dir, gconn) = test_guild_conn();
+        let disp = Dispatch::new(UserId(1))
+            .with_module(modlog_mod().clear_dependencies())
+            .with_module(gate_mod().clear_dependencies());
+        (dir, gconn, disp)
+    }
+
+    fn job_times(conn: &GuildConn, kind: &str) -> Vec<(String, DateTime<Utc>)> {
+        conn.jobs(Some(kind)).unwrap().into_iter().map(|j| (j.payload, j.run_at)).collect()
+    }
+
+    #[test]
+    fn test_must_hold() {
+        let (_dir, gconn, disp) = setup();
+        let now = Utc::now();
+        let created = now - Duration::days(1);
+
+        assert_eq!(must_hold(&disp, &gconn, &created, &now).unwrap(), None);
+        gconn.set_value(GATE_ROLE_KEY, "5").unwrap();
+        assert_eq!(must_hold(&disp, &gconn, &created, &now).unwrap(), None);
+        gconn.set_value(GATE_ENABLED_KEY, "true").unwrap();
+        assert_eq!(must_hold(&disp, &gconn, &created, &now).unwrap(), Some(RoleId(5)));
+
+        gconn.set_value(MIN_ACCOUNT_AGE_KEY, "3600").unwrap();
+        assert_eq!(must_hold(&disp, &gconn, &created, &now).unwrap(), None);
+        assert_eq!(must_hold(&disp, &gconn, &(now - Duration::minutes(5)), &now).unwrap(), Some(RoleId(5)));
+
+        assert!(is_gate_role(&disp, &gconn, RoleId(5)).unwrap());
+        assert!(!is_gate_role(&disp, &gconn, RoleId(6)).unwrap());
+        assert!(!is_gate_role(&Dispatch::new(UserId(1)), &gconn, RoleId(5)).unwrap());
+    }
+
+    #[test]
+    fn test_hold_and_release() {
+        let (_dir, gconn, disp) = setup();
+        let now = Utc.timestamp(1_000_000, 0);
+        let (young, old) = (UserId(2), UserId(3));
+
+        // Accounts which come of age before the timeout are released then.
+        gconn.set_value(MIN_ACCOUNT_AGE_KEY, "3600").unwrap();
+        schedule_hold(&disp, &gconn, young, &(now - Duration::minutes(30)), &now).unwrap();
+        assert!(gconn.is_gate_pending(young).unwrap());
+        assert_eq!(job_times(&gconn, TIMEOUT_JOB), vec![("2".to_string(), now + Duration::seconds(DEFAULT_TIMEOUT))]);
+        assert_eq!(job_times(&gconn, RELEASE_JOB), vec![("2".to_string(), now + Duration::minutes(30))]);
+
+        // Others only time out.
+        gconn.set_value(MIN_ACCOUNT_AGE_KEY, "172800").unwrap();
+        schedule_hold(&disp, &gconn, old, &now, &now).unwrap();
+        assert_eq!(job_times(&gconn, TIMEOUT_JOB).len(), 2);
+        assert_eq!(job_times(&gconn, RELEASE_JOB).len(), 1);
+
+        release(&gconn, young).unwrap();
+        assert!(!gconn.is_gate_pending(young).unwrap());
+        assert_eq!(job_times(&gconn, TIMEOUT_JOB), vec![("3".to_string(), now + Duration::seconds(DEFAULT_TIMEOUT))]);
+        assert!(job_times(&gconn, RELEASE_JOB).is_empty());
+
+        // Without a timeout, nobody is kicked.
+        gconn.set_value(TIMEOUT_KEY, "0").unwrap();
+        schedule_hold(&disp, &gconn, UserId(4), &now, &now).unwrap();
+        assert_eq!(job_times(&gconn, TIMEOUT_JOB).len(), 1);
+        assert_eq!(job_times(&gconn, RELEASE_JOB), vec![("4".to_string(), now + Duration::days(2))]);
+    }
+
+    #[test]
+    fn test_pass() {
+        let (_dir, gconn, disp) = setup();
+        let ctx = test_context();
+        let now = Utc::now();
+        let user = UserId(2);
+        let entry = || AuditEntry::new(UserId(1), "Passed gate", "<@2>");
+
+        assert!(!pass(&disp, &ctx, &gconn, user, entry()).unwrap());
+
+        // No quarantine role is set, so no request is made to Discord.
+        gconn.set_value(MIN_ACCOUNT_AGE_KEY, "3600").unwrap();
+        schedule_hold(&disp, &gconn, user, &now, &now).unwrap();
+        assert!(pass(&disp, &ctx, &gconn, user, entry()).unwrap());
+        assert!(!gconn.is_gate_pending(user).unwrap());
+        assert!(gconn.jobs(None).unwrap().is_empty());
+        let passed: i64 = gconn.as_ref().query_row("SELECT COUNT(*) FROM audit_log WHERE action = 'Passed gate';", NO_PARAMS, |r| r.get(0)).unwrap();
+        assert_eq!(passed, 1);
+
+        // Jobs for users who already left or passed do nothing.
+        let job = |kind: &str, payload: &str| Job {
+            id: 1,
+            kind: kind.to_string(),
+            payload: payload.to_string(),
+            run_at: now,
+            interval: None,
+            attempts: 0,
+        };
+        timeout_job(&disp, &ctx, &gconn, &job(TIMEOUT_JOB, "2")).unwrap();
+        timeout_job(&disp, &ctx, &gconn, &job(TIMEOUT_JOB, "bad")).unwrap();
+        release_job(&disp, &ctx, &gconn, &job(RELEASE_JOB, "2")).unwrap();
+        release_job(&disp, &ctx, &gconn, &job(RELEASE_JOB, "bad")).unwrap();
+        assert!(gconn.gate_pending().unwrap().is_empty());
+    }
+
+    #[test]
+    fn test_user_payload() {
+        let job = |payload: &str| Job {
+            id: 1,
+            kind: TIMEOUT_JOB.to_string(),
+            payload: payload.to_string(),
+            run_at: Utc::now(),
+            interval: None,
+            attempts: 0,
+        };
+
+        assert_eq!(user_payload(&job("1234")), Some(UserId(1234)));
+        assert_eq!(user_payload(&job("12 34")), None);
+        assert_eq!(user_payload(&job("")), None);
+    }
+
+    #[test]
+    fn test_check_gate_role() {
+        let (_dir, conn, disp) = setup();
+        let ctx = test_context();
+        conn.set_role_joinable(RoleId(6), None).unwrap();
+        conn.set_role_tier(RoleId(7), crate::db::Tier::Moderator).unwrap();
+
+        assert!(check_gate_role(&disp, &ctx, &conn, "5").is_ok());
+        assert!(check_gate_role(&disp, &ctx, &conn, "6").is_err());
+        assert!(check_gate_role(&disp, &ctx, &conn, "7").is_err());
+    }
+}
\ No newline at end of file
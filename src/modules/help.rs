@@ -0,0 +1,102 @@
+//  Glimbot - A Discord anti-spam and administration bot.
+//  Copyright (C) 2020 Nick Samson
+
+//  This program is free software: you can redistribute it and/or modify
+//  it under the terms of the GNU General Public License as published by
+//  the Free Software Foundation, either version 3 of the License, or
+//  (at your option) any later version.
+
+//  This program is distributed in the hope that it will be useful,
+//  but WITHOUT ANY WARRANTY; without even the implied warranty of
+//  MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+//  GNU General Public License for more details.
+
+//  You should have received a copy of the GNU General Public License
+//  along with this program.  If not, see <https://www.gnu.org/licenses/>.
+
+//! The help module, which lists the loaded modules and displays help for individual commands.
+
+use crate::modules::commands::Command;
+use crate::modules::Module;
+use crate::modules::hook::Error::CommandNotFound;
+use crate::modules::roles::check_permission;
+use crate::dispatch::Dispatch;
+use crate::args::parse_app_matches;
+use crate::util::help_str;
+use serenity::prelude::Context;
+use serenity::model::channel::Message;
+use serenity::utils::MessageBuilder;
+use std::borrow::Cow;
+use std::fmt::Write;
+use once_cell::unsync::Lazy;
+use clap::{App, Arg, ArgMatches};
+use itertools::Itertools;
+
+/// ZST struct for processing the `help` command
+pub struct Help;
+
+thread_local! {
+    static PARSER: Lazy<App<'static, 'static>> = Lazy::new(
+        || {
+            App::new("help")
+                .about("Lists the available commands, or displays help for a single command.")
+                .arg(Arg::with_name("command")
+                    .value_name("COMMAND")
+                    .help("The command to display help for.")
+                    .takes_value(true)
+                    .required(false)
+                )
+        }
+    );
+}
+
+fn yes_no(b: bool) -> &'static str {
+    if b { "yes" } else { "no" }
+}
+
+impl Command for Help {
+    fn invoke(&self, disp: &Dispatch, ctx: &Context, msg: &Message, args: Cow<str>) -> super::commands::Result<()> {
+        let m: ArgMatches = PARSER.with(|p| parse_app_matches("help", args, p))?;
+
+        let reply = if let Some(name) = m.value_of("command") {
+            let cmd = disp.resolve_command(name)
+                .ok_or_else(|| CommandNotFound(name.to_string()))?;
+            cmd.help().into_owned()
+        } else {
+            let guild = msg.guild_id.unwrap();
+            let mut out = format!("{:<16}{:<10}{:<11}{}\n", "Module", "Command", "Requires", "Can run");
+            for module in disp.modules().values().sorted_by_key(|m| m.name()) {
+                let has_command = module.command_handler().is_some();
+                let can_run = has_command
+                    && check_permission(disp, ctx, guild, Some(msg.channel_id), &msg.author, module.name(), None).is_ok();
+                writeln!(out, "{:<16}{:<10}{:<11}{}",
+                         module.name(),
+                         yes_no(has_command),
+                         module.required_tier().to_string(),
+                         yes_no(can_run)
+                ).unwrap();
+            }
+            out.push_str("\nUse `help <command>` for more information about a command.");
+            out
+        };
+
+        msg.channel_id.say(ctx, MessageBuilder::new()
+            .push_codeblock_safe(reply, None)
+            .build())?;
+
+        Ok(())
+    }
+
+    fn help(&self) -> Cow<'static, str> {
+        PARSER.with(|p| help_str(p).into())
+    }
+}
+
+/// Creates the help module.
+pub fn help_mod() -> Module {
+    Module::with_name("help")
+        .with_command(Help)
+        .with_alias("commands")
+        .with_sensitivity(false)
+        .with_dependency("roles")
+}
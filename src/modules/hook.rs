@@ -19,9 +19,11 @@
 use std::borrow::Cow;
 use crate::dispatch::Dispatch;
 use serenity::prelude::Context;
-use serenity::model::prelude::Message;
+use serenity::model::prelude::{Message, Member, User, Reaction, Guild, GuildId, ChannelId, MessageId};
+use serenity::model::event::MessageUpdateEvent;
 use crate::error::{BotError, SerenityError};
-use crate::db::DatabaseError;
+use crate::db::{DatabaseError, GuildConn, Job};
+use std::fmt;
 
 /// Errors that can result from the application of a hook.
 #[derive(thiserror::Error, Debug)]
@@ -85,4 +87,92 @@ pub type Result<T> = std::result::Result<T, Error>;
 /// ```
 /// fn length_hook<'a, 'b, 'c, 'd>(disp: &'a Dispatch, ctx: &'b Context, msg: &'c Message, name: Cow<'d, str>) -> super::hook::Result<Cow<'d, str>>
 /// ```
-pub type CommandHookFn = for <'a, 'b, 'c, 'd> fn(&'a Dispatch, &'b Context, &'c Message, Cow<'d, str>) -> Result<Cow<'d, str>>;
\ No newline at end of file
+pub type CommandHookFn = for <'a, 'b, 'c, 'd> fn(&'a Dispatch, &'b Context, &'c Message, Cow<'d, str>) -> Result<Cow<'d, str>>;
+
+/// A function that will be called on every message Glimbot sees, whether or not it is a command.
+/// Errors from message hooks are logged rather than reported back to the channel.
+/// Example function signature:
+/// ```
+/// fn spam_hook(disp: &Dispatch, ctx: &Context, msg: &Message) -> super::hook::Result<()>
+/// ```
+pub type MessageHookFn = fn(&Dispatch, &Context, &Message) -> Result<()>;
+
+/// A Discord event other than a new message which modules may hook.
+#[derive(Debug, Clone, Copy)]
+pub enum Event<'a> {
+    /// A user joined a guild.
+    MemberAdd {
+        /// The guild the user joined.
+        guild: GuildId,
+        /// The new member.
+        member: &'a Member,
+    },
+    /// A user left or was removed from a guild.
+    MemberRemove {
+        /// The guild the user left.
+        guild: GuildId,
+        /// The user who left.
+        user: &'a User,
+        /// The user's member data, if it was cached.
+        member: Option<&'a Member>,
+    },
+    /// A message was edited.
+    MessageUpdate {
+        /// The message before the edit, if it was cached.
+        old: Option<&'a Message>,
+        /// The message after the edit, if it was cached.
+        new: Option<&'a Message>,
+        /// The raw update from Discord.
+        event: &'a MessageUpdateEvent,
+    },
+    /// A message was deleted.
+    MessageDelete {
+        /// The channel the message was in.
+        channel: ChannelId,
+        /// The deleted message.
+        message: MessageId,
+    },
+    /// A reaction was added to a message.
+    ReactionAdd(&'a Reaction),
+    /// A reaction was removed from a message.
+    ReactionRemove(&'a Reaction),
+    /// A guild became available, either on startup or because Glimbot joined it.
+    GuildCreate {
+        /// The guild.
+        guild: &'a Guild,
+        /// Whether Glimbot just joined the guild.
+        is_new: bool,
+    },
+}
+
+/// Describes the event by its kind and the ids involved, without dumping its whole contents.
+impl fmt::Display for Event<'_> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Event::MemberAdd { guild, member } => write!(f, "MemberAdd (guild {}, user {})", guild, member.user.read().id),
+            Event::MemberRemove { guild, user, .. } => write!(f, "MemberRemove (guild {}, user {})", guild, user.id),
+            Event::MessageUpdate { event, .. } => write!(f, "MessageUpdate (channel {}, message {})", event.channel_id, event.id),
+            Event::MessageDelete { channel, message } => write!(f, "MessageDelete (channel {}, message {})", channel, message),
+            Event::ReactionAdd(r) => write!(f, "ReactionAdd (channel {}, message {}, user {})", r.channel_id, r.message_id, r.user_id),
+            Event::ReactionRemove(r) => write!(f, "ReactionRemove (channel {}, message {}, user {})", r.channel_id, r.message_id, r.user_id),
+            Event::GuildCreate { guild, is_new } => write!(f, "GuildCreate (guild {}, new: {})", guild.id, is_new),
+        }
+    }
+}
+
+/// A function that will be called on every [Event] Glimbot sees. Hooks should ignore events they
+/// aren't interested in. Errors from event hooks are logged.
+/// Example function signature:
+/// ```
+/// fn join_hook(disp: &Dispatch, ctx: &Context, event: &Event) -> super::hook::Result<()>
+/// ```
+pub type EventHookFn = fn(&Dispatch, &Context, &Event) -> Result<()>;
+
+/// A function that runs scheduled [Job][crate::db::Job]s of a single kind, on one of the scheduler's
+/// worker threads. Returning an error retries the job later; handlers should return `Ok` for failures
+/// which retrying can't fix.
+/// Example function signature:
+/// ```
+/// fn unmute_job(disp: &Dispatch, ctx: &Context, conn: &GuildConn, job: &Job) -> super::hook::Result<()>
+/// ```
+pub type JobHandlerFn = fn(&Dispatch, &Context, &GuildConn, &Job) -> Result<()>;
\ No newline at end of file
@@ -0,0 +1,337 @@
+//  Glimbot - A Discord anti-spam and administration bot.
+//  Copyright (C) 2020 Nick Samson
+
+//  This program is free software: you can redistribute it and/or modify
+//  it under the terms of the GNU General Public License as published by
+//  the Free Software Foundation, either version 3 of the License, or
+//  (at your option) any later version.
+
+//  This program is distributed in the hope that it will be useful,
+//  but WITHOUT ANY WARRANTY; without even the implied warranty of
+//  MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+//  GNU General Public License for more details.
+
+//  You should have received a copy of the GNU General Public License
+//  along with this program.  If not, see <https://www.gnu.org/licenses/>.
+
+//! Modules for warning users and keeping a per-user history of those warnings.
+//!
+//! Every warning is stored as an infraction. Guilds may configure automatic mutes, kicks and bans
+//! once a user has collected enough infractions; all of these are disabled by default.
+
+use crate::modules::commands::Command;
+use crate::modules::{Module, config, hook};
+use crate::modules::config::Kind;
+use crate::modules::hook::Error::DeniedWithReason;
+use crate::modules::modlog::{record, AuditEntry};
+use crate::modules::punishment::{Action, Target};
+use crate::modules::roles::{resolve_user, check_outranks};
+use crate::db::Tier;
+use crate::modules::spam::{mute_role, Escalation};
+use crate::dispatch::{Dispatch, KeyRetrievalError};
+use crate::args::{parse_app_matches, subcommand_name, subcommand_names};
+use crate::util::help_str;
+use crate::db::cache::get_cached_connection;
+use crate::db::{GuildConn, Infraction};
+use serenity::prelude::Context;
+use serenity::model::channel::Message;
+use serenity::model::guild::Guild;
+use serenity::model::id::UserId;
+use serenity::utils::MessageBuilder;
+use std::borrow::Cow;
+use once_cell::unsync::Lazy;
+use clap::{App, AppSettings, Arg, ArgMatches, SubCommand};
+use chrono::Utc;
+
+static MUTE_AT_KEY: &str = "warn_mute_at";
+static KICK_AT_KEY: &str = "warn_kick_at";
+static BAN_AT_KEY: &str = "warn_ban_at";
+static MUTE_DURATION_KEY: &str = "warn_mute_duration";
+
+const DEFAULT_MUTE_AT: u32 = 0;
+const DEFAULT_KICK_AT: u32 = 0;
+const DEFAULT_BAN_AT: u32 = 0;
+const DEFAULT_MUTE_DURATION: i64 = 3600; // Seconds
+
+/// Loads the guild's escalation ladder for warnings. Warning is never an automatic step, since
+/// the user has just been warned.
+pub fn load_escalation(disp: &Dispatch, conn: &GuildConn) -> Result<Escalation, KeyRetrievalError> {
+    let step = |key: &str| disp.get_config::<u32>(conn, key);
+
+    Ok(Escalation {
+        warn_at: 0,
+        mute_at: step(MUTE_AT_KEY)?,
+        kick_at: step(KICK_AT_KEY)?,
+        ban_at: step(BAN_AT_KEY)?,
+        mute_role: mute_role(disp, conn)?,
+        mute_duration: disp.get_config(conn, MUTE_DURATION_KEY)?,
+    })
+}
+
+/// The actions to take automatically against a user with the given number of infractions.
+pub fn automatic_actions(escalation: &Escalation, infractions: u32) -> Vec<Action> {
+    escalation.actions_for(infractions)
+        .into_iter()
+        .filter(|a| !matches!(a, Action::Delete | Action::Warn))
+        .collect()
+}
+
+fn describe(guild: &Guild, i: &Infraction) -> String {
+    let moderator = guild.members.get(&i.moderator)
+        .map_or_else(|| i.moderator.to_string(), |m| m.display_name().into_owned());
+    let reason = if i.reason.is_empty() { "(no reason given)" } else { &i.reason };
+    format!("#{} {} by {}: {}", i.id, i.time.format("%Y-%m-%d %H:%M"), moderator, reason)
+}
+
+/// ZST struct for processing the `warn` command
+pub struct Warn;
+
+thread_local! {
+    static WARN_PARSER: Lazy<App<'static, 'static>> = Lazy::new(
+        || {
+            App::new("warn")
+                .about("Warns a user and records the warning in their infraction history.")
+                .arg(Arg::with_name("user")
+                    .value_name("USER")
+                    .help("The user to warn.")
+                    .takes_value(true)
+                    .required(true)
+                )
+                .arg(Arg::with_name("reason")
+                    .value_name("REASON")
+                    .help("Why the user is being warned.")
+                    .takes_value(true)
+                    .multiple(true)
+                    .required(true)
+                )
+        }
+    );
+}
+
+impl Command for Warn {
+    fn invoke(&self, disp: &Dispatch, ctx: &Context, msg: &Message, args: Cow<str>) -> super::commands::Result<()> {
+        let m: ArgMatches = WARN_PARSER.with(|p| parse_app_matches("warn", args, p))?;
+        let reason = m.values_of("reason").unwrap().collect::<Vec<_>>().join(" ");
+
+        let guild = msg.guild(ctx).unwrap();
+        let rg = guild.read();
+        let user = resolve_user(&rg, m.value_of("user").unwrap())?;
+        if user == msg.author.id {
+            return Err(DeniedWithReason("You cannot warn yourself.".into()).into());
+        }
+
+        let conn = get_cached_connection(rg.id)?;
+        let rconn = conn.borrow();
+        check_outranks(ctx, &rconn, &rg, msg.author.id, user)?;
+
+        let id = rconn.add_infraction(user, msg.author.id, &reason, &Utc::now())?;
+        let target = Target { guild: rg.id, user, channel: msg.channel_id, message: None };
+        Action::Warn.apply(ctx, &rconn, &target, &reason)?;
+        record(disp, ctx, &rconn, &AuditEntry::new(msg.author.id, format!("Warned (infraction #{})", id), format!("<@{}>", user))
+            .with_reason(&reason));
+
+        let count = rconn.infractions(user)?.len() as u32;
+        let auto_reason = format!("Reached {} infractions.", count);
+        for action in automatic_actions(&load_escalation(disp, &rconn)?, count) {
+            action.apply(ctx, &rconn, &target, &auto_reason)?;
+            record(disp, ctx, &rconn, &AuditEntry::automatic(ctx, action.to_string(), format!("<@{}>", user))
+                .with_reason(&auto_reason));
+        }
+
+        msg.channel_id.say(ctx, MessageBuilder::new()
+            .push_codeblock_safe(format!("Recorded infraction #{}. The user now has {} infraction(s).", id, count), None)
+            .build())?;
+
+        Ok(())
+    }
+
+    fn help(&self) -> Cow<'static, str> {
+        WARN_PARSER.with(|p| help_str(p).into())
+    }
+}
+
+/// ZST struct for processing the `infractions` command
+pub struct Infractions;
+
+thread_local! {
+    static INFRACTIONS_PARSER: Lazy<App<'static, 'static>> = Lazy::new(
+        || {
+            let user = Arg::with_name("user")
+                .value_name("USER")
+                .help("The user whose infractions should be shown.")
+                .takes_value(true)
+                .required(true);
+
+            App::new("infractions")
+                .about("Command for viewing and managing the warnings users have received.")
+                .setting(AppSettings::ArgRequiredElseHelp)
+                .setting(AppSettings::ArgsNegateSubcommands)
+                .setting(AppSettings::SubcommandsNegateReqs)
+                .arg(user.clone())
+                .subcommand(
+                    SubCommand::with_name("remove")
+                        .about("Removes a single infraction by its number, as shown when listing.")
+                        .arg(Arg::with_name("id")
+                            .value_name("ID")
+                            .help("The number of the infraction.")
+                            .takes_value(true)
+                            .required(true)
+                        )
+                )
+                .subcommand(
+                    SubCommand::with_name("clear")
+                        .about("Removes every infraction for a user.")
+                        .arg(user.help("The user whose infractions should be cleared."))
+                )
+        }
+    );
+}
+
+/// Checks that the moderator may remove the user's infractions: never their own, and only for users
+/// below them.
+fn check_may_pardon(ctx: &Context, conn: &GuildConn, guild: &Guild, moderator: UserId, user: UserId) -> hook::Result<()> {
+    if user == moderator {
+        return Err(DeniedWithReason("You cannot remove your own infractions.".into()));
+    }
+    check_outranks(ctx, conn, guild, moderator, user)
+}
+
+impl Command for Infractions {
+    fn invoke(&self, disp: &Dispatch, ctx: &Context, msg: &Message, args: Cow<str>) -> super::commands::Result<()> {
+        let m: ArgMatches = INFRACTIONS_PARSER.with(|p| parse_app_matches("infractions", args, p))?;
+
+        let guild = msg.guild(ctx).unwrap();
+        let rg = guild.read();
+        let conn = get_cached_connection(rg.id)?;
+        let rconn = conn.borrow();
+
+        let reply = match m.subcommand() {
+            ("remove", Some(m)) => {
+                let id = m.value_of("id").unwrap().trim_start_matches('#').parse::<i64>()
+                    .map_err(|_| DeniedWithReason("Infraction numbers must be integers.".into()))?;
+                match rconn.infraction(id)? {
+                    Some(i) => {
+                        check_may_pardon(ctx, &rconn, &rg, msg.author.id, i.user)?;
+                        rconn.remove_infraction(id)?;
+                        record(disp, ctx, &rconn, &AuditEntry::new(msg.author.id, format!("Removed infraction #{}", id), format!("<@{}>", i.user)));
+                        format!("Removed infraction #{}.", id)
+                    },
+                    None => format!("There is no infraction #{}.", id)
+                }
+            },
+            ("clear", Some(m)) => {
+                let user = resolve_user(&rg, m.value_of("user").unwrap())?;
+                check_may_pardon(ctx, &rconn, &rg, msg.author.id, user)?;
+                let n = rconn.clear_infractions(user)?;
+                if n > 0 {
+                    record(disp, ctx, &rconn, &AuditEntry::new(msg.author.id, "Cleared infractions", format!("<@{}>", user)));
+                }
+                format!("Cleared {} infraction(s).", n)
+            },
+            _ => {
+                let user = resolve_user(&rg, m.value_of("user").unwrap())?;
+                let infractions = rconn.infractions(user)?;
+                if infractions.is_empty() {
+                    "That user has no infractions.".to_string()
+                } else {
+                    infractions.iter()
+                        .map(|i| describe(&rg, i))
+                        .collect::<Vec<_>>()
+                        .join("\n")
+                }
+            }
+        };
+
+        msg.channel_id.say(ctx, MessageBuilder::new()
+            .push_codeblock_safe(reply, None)
+            .build())?;
+
+        Ok(())
+    }
+
+    fn subcommand(&self, args: &str) -> Option<String> {
+        INFRACTIONS_PARSER.with(|p| subcommand_name("infractions", args, p))
+    }
+
+    fn subcommands(&self) -> Vec<String> {
+        INFRACTIONS_PARSER.with(|p| subcommand_names(p))
+    }
+
+    fn help(&self) -> Cow<'static, str> {
+        INFRACTIONS_PARSER.with(|p| help_str(p).into())
+    }
+}
+
+/// Creates the warn module, which also owns the automatic escalation settings for warnings.
+pub fn warn_mod() -> Module {
+    Module::with_name("warn")
+        .with_command(Warn)
+        .with_config_value(config::Value::new(
+            MUTE_AT_KEY,
+            "The number of infractions after which a user is automatically muted. 0 disables muting.",
+            Kind::UINT,
+            Some(DEFAULT_MUTE_AT.to_string()),
+        ))
+        .with_config_value(config::Value::new(
+            KICK_AT_KEY,
+            "The number of infractions after which a user is automatically kicked. 0 disables kicking.",
+            Kind::UINT,
+            Some(DEFAULT_KICK_AT.to_string()),
+        ))
+        .with_config_value(config::Value::new(
+            BAN_AT_KEY,
+            "The number of infractions after which a user is automatically banned. 0 disables banning.",
+            Kind::UINT,
+            Some(DEFAULT_BAN_AT.to_string()),
+        ))
+        .with_config_value(config::Value::new(
+            MUTE_DURATION_KEY,
+            "How long a user stays muted after reaching the mute threshold, e.g. 3600, 1h or 1d, up to 365d.",
+            Kind::Duration,
+            Some(DEFAULT_MUTE_DURATION.to_string()),
+        ))
+        .with_required_tier(Tier::Moderator)
+        .with_dependency("spam")
+        .with_dependency("modlog")
+}
+
+/// Creates the infractions module.
+pub fn infractions_mod() -> Module {
+    Module::with_name("infractions")
+        .with_command(Infractions)
+        .with_required_tier(Tier::Moderator)
+        .with_dependency("warn")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serenity::model::id::RoleId;
+    use chrono::Duration;
+
+    #[test]
+    fn test_automatic_actions() {
+        let role = RoleId(1);
+        let duration = Duration::seconds(60);
+        let mut e = Escalation {
+            warn_at: 0,
+            mute_at: 0,
+            kick_at: 0,
+            ban_at: 0,
+            mute_role: Some(role),
+            mute_duration: duration,
+        };
+
+        assert!(automatic_actions(&e, 10).is_empty());
+
+        e.mute_at = 3;
+        e.kick_at = 5;
+        assert!(automatic_actions(&e, 2).is_empty());
+        assert_eq!(automatic_actions(&e, 3), vec![Action::Mute { role, duration }]);
+        assert_eq!(automatic_actions(&e, 6), vec![Action::Kick]);
+
+        // Without a mute role there is nothing to do until the kick threshold.
+        e.mute_role = None;
+        assert!(automatic_actions(&e, 4).is_empty());
+    }
+}
@@ -0,0 +1,401 @@
+//  Glimbot - A Discord anti-spam and administration bot.
+//  Copyright (C) 2020 Nick Samson
+
+//  This program is free software: you can redistribute it and/or modify
+//  it under the terms of the GNU General Public License as published by
+//  the Free Software Foundation, either version 3 of the License, or
+//  (at your option) any later version.
+
+//  This program is distributed in the hope that it will be useful,
+//  but WITHOUT ANY WARRANTY; without even the implied warranty of
+//  MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+//  GNU General Public License for more details.
+
+//  You should have received a copy of the GNU General Public License
+//  along with this program.  If not, see <https://www.gnu.org/licenses/>.
+
+//! Link and invite filtering. Links and Discord invites are pulled out of every guild message and
+//! checked according to the guild's `links_mode`:
+//!
+//! * `off` checks nothing.
+//! * `invites` blocks invites to any guild but this one.
+//! * `deny` also blocks links to domains with a deny rule.
+//! * `allow` also blocks links to any domain without an allow rule.
+//!
+//! Rules for `example.com` match only that domain; rules for `*.example.com` match it and every
+//! subdomain. The most specific matching rule wins. Offending messages are deleted and recorded
+//! in the audit log. Staff are exempt.
+
+use crate::modules::commands::Command;
+use crate::modules::{Module, config};
+use crate::modules::config::{FromConfig, Kind};
+use crate::modules::hook::Error::DeniedWithReason;
+use crate::modules::modlog::{record, AuditEntry};
+use crate::modules::punishment::{Action, Target};
+use crate::modules::roles::user_tier;
+use crate::db::Tier;
+use crate::dispatch::Dispatch;
+use crate::args::{parse_app_matches, subcommand_name, subcommand_names};
+use crate::util::help_str;
+use crate::db::cache::get_cached_connection;
+use crate::db::LinkRule;
+use crate::error::SerenityError;
+use serenity::prelude::Context;
+use serenity::model::channel::Message;
+use serenity::model::id::GuildId;
+use serenity::utils::MessageBuilder;
+use std::borrow::Cow;
+use std::fmt;
+use std::str::FromStr;
+use once_cell::unsync::Lazy;
+use once_cell::sync::Lazy as SyncLazy;
+use parking_lot::Mutex;
+use lru_cache::LruCache;
+use regex::Regex;
+use clap::{App, AppSettings, Arg, ArgMatches, SubCommand};
+
+static LINKS_MODE_KEY: &str = "links_mode";
+
+static INVITE_RE: SyncLazy<Regex> = SyncLazy::new(
+    || Regex::new(r"(?i)\b(?:https?://)?(?:www\.)?(?:discord(?:app)?\.com/invite|discord\.gg)/([a-z0-9-]+)").unwrap()
+);
+static URL_RE: SyncLazy<Regex> = SyncLazy::new(
+    || Regex::new(r"(?i)\bhttps?://(?:[^\s/?#<>@]*@)?([^\s/?#<>:@]+)").unwrap()
+);
+static DOMAIN_RE: SyncLazy<Regex> = SyncLazy::new(
+    || Regex::new(r"^(?:\*\.)?(?:[a-z0-9-]+\.)+[a-z0-9-]+$").unwrap()
+);
+
+// Maps invite codes to the guilds they lead to, so we don't ask Discord about the same invite twice.
+// Lookups which fail for reasons other than the invite not existing are not cached.
+static INVITE_GUILDS: SyncLazy<Mutex<LruCache<String, Option<GuildId>>>> = SyncLazy::new(
+    || Mutex::new(LruCache::new(1024))
+);
+
+/// How strictly a guild filters links.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Mode {
+    /// Links are not checked.
+    Off,
+    /// Invites to other guilds are blocked.
+    Invites,
+    /// Invites to other guilds and links to denied domains are blocked.
+    Deny,
+    /// Invites to other guilds and links to any domain which isn't allowed are blocked.
+    Allow,
+}
+
+impl FromStr for Mode {
+    type Err = ();
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "off" => Ok(Mode::Off),
+            "invites" => Ok(Mode::Invites),
+            "deny" => Ok(Mode::Deny),
+            "allow" => Ok(Mode::Allow),
+            _ => Err(())
+        }
+    }
+}
+
+impl FromConfig for Mode {
+    fn from_config(s: &str) -> Option<Self> {
+        s.parse().ok()
+    }
+}
+
+/// A link found in a message.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Link {
+    /// A Discord invite, by code.
+    Invite(String),
+    /// A link to any other site, by lowercased host name.
+    Domain(String),
+}
+
+impl fmt::Display for Link {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Link::Invite(code) => write!(f, "discord.gg/{}", code),
+            Link::Domain(host) => write!(f, "{}", host),
+        }
+    }
+}
+
+/// Pulls every invite and link out of message content. Links are only recognized with an
+/// `http://` or `https://` scheme; invites are recognized with or without one.
+pub fn extract_links(content: &str) -> Vec<Link> {
+    let mut links: Vec<Link> = INVITE_RE.captures_iter(content)
+        .map(|c| Link::Invite(c[1].to_string()))
+        .collect();
+
+    let rest = INVITE_RE.replace_all(content, "");
+    links.extend(URL_RE.captures_iter(&rest)
+        .map(|c| Link::Domain(c[1].trim_end_matches('.').to_lowercase())));
+    links
+}
+
+/// Normalizes a domain given by a user, accepting a full URL. Returns None if it isn't a domain.
+pub fn normalize_domain(s: &str) -> Option<String> {
+    let s = s.trim().to_lowercase();
+    let s = s.splitn(2, "://").last().unwrap();
+    let s = s.split(&['/', '?', '#'][..]).next().unwrap().trim_end_matches('.');
+    Some(s.to_string()).filter(|s| DOMAIN_RE.is_match(s))
+}
+
+/// Finds the most specific rule matching the host. Exact rules beat wildcard rules for the same domain.
+pub fn rule_for<'a>(rules: &'a [LinkRule], host: &str) -> Option<&'a LinkRule> {
+    rules.iter()
+        .filter_map(|r| match r.domain.strip_prefix("*.") {
+            Some(base) if host == base || host.ends_with(&format!(".{}", base)) => Some((r, base.len(), false)),
+            None if host == r.domain => Some((r, r.domain.len(), true)),
+            _ => None
+        })
+        .max_by_key(|(_, len, exact)| (*len, *exact))
+        .map(|(r, _, _)| r)
+}
+
+/// Whether links to the host are allowed under the mode and rules.
+pub fn domain_allowed(mode: Mode, rules: &[LinkRule], host: &str) -> bool {
+    match mode {
+        Mode::Off | Mode::Invites => true,
+        Mode::Deny => !matches!(rule_for(rules, host), Some(r) if !r.allow),
+        Mode::Allow => matches!(rule_for(rules, host), Some(r) if r.allow),
+    }
+}
+
+/// Looks up the guild an invite leads to. Invites Discord doesn't know about lead nowhere. Other
+/// failures, like rate limits or server errors, are returned, since the invite may still be valid.
+fn invite_guild(ctx: &Context, code: &str) -> Result<Option<GuildId>, SerenityError> {
+    if let Some(g) = INVITE_GUILDS.lock().get_mut(code) {
+        return Ok(*g);
+    }
+
+    match ctx.http.get_invite(code, false).map_err(SerenityError::from) {
+        Ok(invite) => {
+            let guild = invite.guild.map(|g| g.id);
+            INVITE_GUILDS.lock().insert(code.to_string(), guild);
+            Ok(guild)
+        },
+        Err(e) if e.not_found() => {
+            debug!("Invite {} does not exist", code);
+            INVITE_GUILDS.lock().insert(code.to_string(), None);
+            Ok(None)
+        },
+        Err(e) => Err(e)
+    }
+}
+
+fn link_hook(disp: &Dispatch, ctx: &Context, msg: &Message) -> super::hook::Result<()> {
+    let guild = match msg.guild_id {
+        Some(g) => g,
+        None => return Ok(())
+    };
+
+    let conn = get_cached_connection(guild)?;
+    let rconn = conn.as_ref().borrow();
+
+    let mode = disp.get_config::<Mode>(&rconn, LINKS_MODE_KEY)?;
+    if mode == Mode::Off {
+        return Ok(());
+    }
+
+    let links = extract_links(&msg.content);
+    if links.is_empty() {
+        return Ok(());
+    }
+
+    let roles = msg.member.as_ref().map_or(&[][..], |m| &m.roles[..]);
+    if user_tier(ctx, &rconn, guild, msg.author.id, roles)? >= Tier::Moderator {
+        return Ok(());
+    }
+
+    let rules = rconn.link_rules()?;
+    let offending = links.iter().find(|l| match l {
+        Link::Invite(code) => match invite_guild(ctx, code) {
+            Ok(g) => g != Some(guild),
+            Err(e) => {
+                warn!("Could not resolve invite {}, so leaving message {} alone: {}", code, msg.id, e);
+                false
+            }
+        },
+        Link::Domain(host) => !domain_allowed(mode, &rules, host),
+    });
+
+    if let Some(link) = offending {
+        let reason = format!("Posted a forbidden link to {}.", link);
+        debug!("Deleting message {} from {} in guild {}: {}", msg.id, msg.author.id, guild, reason);
+        Action::Delete.apply(ctx, &rconn, &Target::from_message(msg), &reason)?;
+        let entry = AuditEntry::automatic(ctx, "Deleted message", format!("<@{}>", msg.author.id))
+            .with_reason(&reason);
+        record(disp, ctx, &rconn, &entry);
+    }
+
+    Ok(())
+}
+
+/// ZST struct for processing the `links` command
+pub struct Links;
+
+thread_local! {
+    static PARSER: Lazy<App<'static, 'static>> = Lazy::new(
+        || {
+            let domain = Arg::with_name("domain")
+                .value_name("DOMAIN")
+                .help("The domain, e.g. example.com. Use *.example.com to include every subdomain.")
+                .takes_value(true)
+                .required(true);
+
+            App::new("links")
+                .about("Command for managing which domains may be linked. See the links_mode config value.")
+                .setting(AppSettings::SubcommandRequiredElseHelp)
+                .subcommand(
+                    SubCommand::with_name("allow")
+                        .about("Allows links to a domain.")
+                        .arg(domain.clone())
+                )
+                .subcommand(
+                    SubCommand::with_name("deny")
+                        .about("Denies links to a domain.")
+                        .arg(domain.clone())
+                )
+                .subcommand(
+                    SubCommand::with_name("list")
+                        .about("Lists the link rules in this guild.")
+                )
+                .subcommand(
+                    SubCommand::with_name("remove")
+                        .about("Removes the rule for a domain.")
+                        .arg(domain.clone())
+                )
+        }
+    );
+}
+
+impl Command for Links {
+    fn invoke(&self, disp: &Dispatch, ctx: &Context, msg: &Message, args: Cow<str>) -> super::commands::Result<()> {
+        let m: ArgMatches = PARSER.with(|p| parse_app_matches("links", args, p))?;
+
+        let guild = msg.guild_id.unwrap();
+        let conn = get_cached_connection(guild)?;
+        let rconn = conn.borrow();
+
+        let domain = || m.subcommand_matches(m.subcommand_name().unwrap())
+            .and_then(|m| m.value_of("domain"))
+            .and_then(normalize_domain)
+            .ok_or_else(|| DeniedWithReason("That isn't a domain.".into()));
+
+        let reply = match m.subcommand_name() {
+            Some("list") => {
+                let rules = rconn.link_rules()?;
+                let mode = disp.get_config::<String>(&rconn, LINKS_MODE_KEY)?;
+                let mut out = format!("Mode: {}", mode);
+                for r in rules {
+                    out.push_str(&format!("\n{} {}", if r.allow { "allow" } else { "deny" }, r.domain));
+                }
+                out
+            },
+            Some("remove") => {
+                let domain = domain()?;
+                if rconn.remove_link_rule(&domain)? {
+                    record(disp, ctx, &rconn, &AuditEntry::new(msg.author.id, "Removed link rule", domain.clone()));
+                    format!("Removed the rule for {}.", domain)
+                } else {
+                    format!("There is no rule for {}.", domain)
+                }
+            },
+            Some(s) => {
+                let domain = domain()?;
+                rconn.set_link_rule(&domain, s == "allow")?;
+                let action = format!("Set {} link rule", s);
+                record(disp, ctx, &rconn, &AuditEntry::new(msg.author.id, action, domain.clone()));
+                format!("Links to {} are now {}.", domain, if s == "allow" { "allowed" } else { "denied" })
+            },
+            None => unreachable!()
+        };
+
+        msg.channel_id.say(ctx, MessageBuilder::new()
+            .push_codeblock_safe(reply, None)
+            .build())?;
+
+        Ok(())
+    }
+
+    fn subcommand(&self, args: &str) -> Option<String> {
+        PARSER.with(|p| subcommand_name("links", args, p))
+    }
+
+    fn subcommands(&self) -> Vec<String> {
+        PARSER.with(|p| subcommand_names(p))
+    }
+
+    fn help(&self) -> Cow<'static, str> {
+        PARSER.with(|p| help_str(p).into())
+    }
+}
+
+/// Creates the links module.
+pub fn links_mod() -> Module {
+    Module::with_name("links")
+        .with_command(Links)
+        .with_config_value(config::Value::new(
+            LINKS_MODE_KEY,
+            "How strictly links are filtered. One of off, invites (block invites to other guilds), deny (also block denied domains) or allow (also block domains which aren't allowed).",
+            Kind::Enum(&["off", "invites", "deny", "allow"]),
+            Some("off".to_string()),
+        ))
+        .with_message_hook(link_hook)
+        .with_sensitivity(true)
+        .with_dependency("roles")
+        .with_dependency("punishment")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn rule(domain: &str, allow: bool) -> LinkRule {
+        LinkRule { domain: domain.to_string(), allow }
+    }
+
+    #[test]
+    fn test_extract_links() {
+        let links = extract_links("join discord.gg/abc123 or https://discord.com/invite/XyZ and see <https://Docs.RS./foo> http://user@example.com:80/x");
+        assert_eq!(links, vec![
+            Link::Invite("abc123".to_string()),
+            Link::Invite("XyZ".to_string()),
+            Link::Domain("docs.rs".to_string()),
+            Link::Domain("example.com".to_string()),
+        ]);
+        assert!(extract_links("no links here, example.com").is_empty());
+    }
+
+    #[test]
+    fn test_normalize_domain() {
+        assert_eq!(normalize_domain("Example.COM"), Some("example.com".to_string()));
+        assert_eq!(normalize_domain("https://example.com/path?q"), Some("example.com".to_string()));
+        assert_eq!(normalize_domain("*.example.com"), Some("*.example.com".to_string()));
+        assert_eq!(normalize_domain("localhost"), None);
+        assert_eq!(normalize_domain("ex ample.com"), None);
+    }
+
+    #[test]
+    fn test_domain_allowed() {
+        let rules = [rule("*.example.com", false), rule("good.example.com", true), rule("docs.rs", true)];
+
+        assert_eq!(rule_for(&rules, "example.com"), Some(&rules[0]));
+        assert_eq!(rule_for(&rules, "a.b.example.com"), Some(&rules[0]));
+        assert_eq!(rule_for(&rules, "good.example.com"), Some(&rules[1]));
+        assert_eq!(rule_for(&rules, "notexample.com"), None);
+        assert_eq!(rule_for(&rules, "sub.docs.rs"), None);
+
+        assert!(domain_allowed(Mode::Invites, &rules, "example.com"));
+        assert!(!domain_allowed(Mode::Deny, &rules, "x.example.com"));
+        assert!(domain_allowed(Mode::Deny, &rules, "good.example.com"));
+        assert!(domain_allowed(Mode::Deny, &rules, "other.org"));
+        assert!(domain_allowed(Mode::Allow, &rules, "docs.rs"));
+        assert!(!domain_allowed(Mode::Allow, &rules, "other.org"));
+        assert!(!domain_allowed(Mode::Allow, &rules, "example.com"));
+    }
+}
\ No newline at end of file
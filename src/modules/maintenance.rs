@@ -0,0 +1,134 @@
+//  Glimbot - A Discord anti-spam and administration bot.
+//  Copyright (C) 2020 Nick Samson
+
+//  This program is free software: you can redistribute it and/or modify
+//  it under the terms of the GNU General Public License as published by
+//  the Free Software Foundation, either version 3 of the License, or
+//  (at your option) any later version.
+
+//  This program is distributed in the hope that it will be useful,
+//  but WITHOUT ANY WARRANTY; without even the implied warranty of
+//  MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+//  GNU General Public License for more details.
+
+//  You should have received a copy of the GNU General Public License
+//  along with this program.  If not, see <https://www.gnu.org/licenses/>.
+
+//! Database maintenance. Keeps guild databases from growing without bound by regularly pruning
+//! message records which are too old to affect message pressure.
+
+use crate::dispatch::Dispatch;
+use crate::db::{GuildConn, Job, prune_messages};
+use crate::db::cache::get_cached_connection;
+use crate::modules::{Module, config, hook};
+use crate::modules::config::Kind;
+use crate::modules::hook::Event;
+use crate::modules::punishment::parse_duration;
+use crate::modules::spam::{PRESSURE_WINDOW_KEY, DEFAULT_PRESSURE_WINDOW, DUPLICATE_WINDOW_KEY, DEFAULT_DUPLICATE_WINDOW};
+use serenity::prelude::Context;
+use chrono::{DateTime, Duration, Utc};
+
+/// The config key for how long message records are kept.
+pub static RETENTION_KEY: &str = "message_retention";
+/// The default message retention, in seconds.
+pub const DEFAULT_RETENTION: i64 = 86400;
+
+static PRUNE_JOB: &str = "prune_messages";
+const PRUNE_INTERVAL: i64 = 3600; // Seconds
+
+fn setting_secs(conn: &GuildConn, key: &str, default: i64) -> crate::db::Result<i64> {
+    match conn.get_value(key) {
+        Ok(v) => Ok(parse_duration(&v).map_or(default, |d| d.num_seconds())),
+        Err(e) if e.no_rows_returned() => Ok(default),
+        Err(e) => Err(e)
+    }
+}
+
+/// The time before which messages may be pruned. This is the configured retention, but never
+/// less than the pressure or duplicate windows, so pruning can't change what the spam module sees.
+/// Reads the guild config directly so it can be used on databases outside the bot.
+pub fn prune_cutoff(conn: &GuildConn, now: &DateTime<Utc>) -> crate::db::Result<DateTime<Utc>> {
+    let retention = setting_secs(conn, RETENTION_KEY, DEFAULT_RETENTION)?;
+    let pressure = setting_secs(conn, PRESSURE_WINDOW_KEY, DEFAULT_PRESSURE_WINDOW)?;
+    let duplicate = setting_secs(conn, DUPLICATE_WINDOW_KEY, DEFAULT_DUPLICATE_WINDOW)?;
+    Ok(*now - Duration::seconds(retention.max(pressure).max(duplicate)))
+}
+
+fn prune_job(disp: &Dispatch, _ctx: &Context, conn: &GuildConn, _job: &Job) -> hook::Result<()> {
+    let cutoff = prune_cutoff(conn, &disp.scheduler().clock().now())?;
+    let n = prune_messages(conn.as_ref(), &cutoff)?;
+    debug!("Pruned {} messages in guild {}", n, conn.as_id());
+    Ok(())
+}
+
+fn schedule_hook(disp: &Dispatch, _ctx: &Context, event: &Event) -> hook::Result<()> {
+    let guild = match event {
+        Event::GuildCreate { guild, .. } => guild.id,
+        _ => return Ok(())
+    };
+
+    let conn = get_cached_connection(guild)?;
+    let rconn = conn.borrow();
+    if rconn.jobs(Some(PRUNE_JOB))?.is_empty() {
+        let interval = Duration::seconds(PRUNE_INTERVAL);
+        rconn.schedule_job(PRUNE_JOB, "", &(disp.scheduler().clock().now() + interval), Some(interval))?;
+    }
+
+    Ok(())
+}
+
+/// Creates the maintenance module.
+pub fn maintenance_mod() -> Module {
+    Module::with_name("maintenance")
+        .with_config_value(config::Value::new(
+            RETENTION_KEY,
+            "How long message records are kept, e.g. 86400, 12h or 7d, up to 365d. Records needed for spam detection are always kept.",
+            Kind::Duration,
+            Some(DEFAULT_RETENTION.to_string()),
+        ))
+        .with_job_handler(PRUNE_JOB, prune_job)
+        .with_event_hook(schedule_hook)
+        .with_dependency("config")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::util::test_guild_conn;
+    use crate::util::test_context;
+    use serenity::model::id::UserId;
+
+    #[test]
+    fn test_prune_cutoff() {
+        let (_dir, gconn) = test_guild_conn();
+        let now = Utc::now();
+
+        assert_eq!(prune_cutoff(&gconn, &now).unwrap(), now - Duration::seconds(DEFAULT_RETENTION));
+
+        gconn.set_value(RETENTION_KEY, "12h").unwrap();
+        assert_eq!(prune_cutoff(&gconn, &now).unwrap(), now - Duration::hours(12));
+
+        // The pressure window wins if it is longer.
+        gconn.set_value(RETENTION_KEY, "5").unwrap();
+        gconn.set_value(PRESSURE_WINDOW_KEY, "30").unwrap();
+        assert_eq!(prune_cutoff(&gconn, &now).unwrap(), now - Duration::seconds(30));
+    }
+
+    #[test]
+    fn test_retention_out_of_range() {
+        let (_dir, gconn) = test_guild_conn();
+        let disp = Dispatch::new(UserId(1)).with_module(maintenance_mod().clear_dependencies());
+        let ctx = test_context();
+        let now = Utc::now();
+
+        for v in &["99999999999999999", "999999999999d", "100000000d", "366d"] {
+            assert!(disp.set_config(&ctx, &gconn, RETENTION_KEY, v).is_err());
+        }
+        disp.set_config(&ctx, &gconn, RETENTION_KEY, "365d").unwrap();
+        assert_eq!(prune_cutoff(&gconn, &now).unwrap(), now - Duration::days(365));
+
+        // Values stored before the limit existed fall back to the default.
+        gconn.set_value(RETENTION_KEY, "99999999999999999").unwrap();
+        assert_eq!(prune_cutoff(&gconn, &now).unwrap(), now - Duration::seconds(DEFAULT_RETENTION));
+    }
+}
@@ -24,14 +24,51 @@ use std::borrow::Cow;
 use once_cell::unsync::Lazy;
 use clap::{App, AppSettings, SubCommand, Arg, ArgMatches};
 use crate::util::help_str;
-use crate::args::parse_app_matches;
-use crate::modules::roles::resolve_role;
+use crate::args::{parse_app_matches, subcommand_name, subcommand_names};
+use crate::modules::roles::{resolve_role, join_role};
 use crate::error::{AnyError};
 use crate::db::cache::get_cached_connection;
 use crate::modules::hook::Error::DeniedWithReason;
 use serenity::utils::MessageBuilder;
+use serenity::model::guild::Guild;
+use serenity::model::id::RoleId;
+use crate::db::GuildConn;
+use std::fmt::Write;
 use crate::modules::Module;
 
+/// Describes the joinable roles of a guild, grouped by role group. Roles which no longer exist are skipped.
+fn list_roles(guild: &Guild, conn: &GuildConn) -> crate::db::Result<String> {
+    let name = |r: &RoleId| guild.roles.get(r).map(|r| r.name.as_str());
+
+    let mut out = String::new();
+    for g in conn.role_groups()? {
+        let names: Vec<&str> = g.roles.iter().filter_map(name).collect();
+        if names.is_empty() {
+            continue;
+        }
+        let rule = match (g.exclusive, g.max) {
+            (true, _) => " (pick one)".to_string(),
+            (false, Some(n)) => format!(" (pick up to {})", n),
+            (false, None) => String::new(),
+        };
+        writeln!(out, "{}{}: {}", g.name, rule, names.join(", ")).unwrap();
+    }
+
+    let ungrouped: Vec<&str> = conn.joinable_roles()?
+        .iter()
+        .filter(|(_, g)| g.is_none())
+        .filter_map(|(r, _)| name(r))
+        .collect();
+    if !ungrouped.is_empty() {
+        writeln!(out, "other: {}", ungrouped.join(", ")).unwrap();
+    }
+
+    if out.is_empty() {
+        out.push_str("There are no joinable roles.");
+    }
+    Ok(out)
+}
+
 /// ZST struct for processing the `me` command
 pub struct Me;
 
@@ -57,6 +94,10 @@ thread_local! {
                         .about("Allows a user to leave a joinable role. Ask server admins for more info.")
                         .arg(role_id.clone())
                 )
+                .subcommand(
+                    SubCommand::with_name("list-roles")
+                        .about("Lists the roles you can join, grouped by role group.")
+                )
         }
     );
 }
@@ -66,6 +107,13 @@ impl Command for Me {
         let m: ArgMatches = PARSER.with(|p| parse_app_matches("me", args, p))?;
 
         let reply = match m.subcommand() {
+            ("list-roles", Some(_)) => {
+                let guild = msg.guild(ctx).unwrap();
+                let rg = guild.read();
+                let conn = get_cached_connection(rg.id)?;
+                let rconn = conn.borrow();
+                list_roles(&rg, &rconn)?
+            },
             (s, Some(m)) => {
                 let joining = s == "join-role";
                 let guild = msg.guild(ctx).unwrap();
@@ -81,8 +129,15 @@ impl Command for Me {
                 }
 
                 if joining {
-                    member.add_role(ctx, role.id)?;
-                    format!("Joined role {}", &role.name)
+                    let left = join_role(ctx, &rconn, msg.author.id, &member.roles, role.id)?;
+                    let left: Vec<&str> = left.iter()
+                        .filter_map(|r| rg.roles.get(r).map(|r| r.name.as_str()))
+                        .collect();
+                    if left.is_empty() {
+                        format!("Joined role {}", &role.name)
+                    } else {
+                        format!("Joined role {}, leaving {}", &role.name, left.join(", "))
+                    }
                 } else {
                     member.remove_role(ctx, role.id)?;
                     format!("Left role {}", &role.name)
@@ -99,6 +154,14 @@ impl Command for Me {
 
     }
 
+    fn subcommand(&self, args: &str) -> Option<String> {
+        PARSER.with(|p| subcommand_name("me", args, p))
+    }
+
+    fn subcommands(&self) -> Vec<String> {
+        PARSER.with(|p| subcommand_names(p))
+    }
+
     fn help(&self) -> Cow<'static, str> {
         PARSER.with(|p| help_str(&p).into())
     }
@@ -20,8 +20,9 @@
 
 use crate::modules::commands::Command;
 use std::sync::Arc;
-use crate::modules::hook::CommandHookFn;
+use crate::modules::hook::{CommandHookFn, MessageHookFn, EventHookFn, JobHandlerFn};
 use std::collections::HashSet;
+use crate::db::Tier;
 
 pub mod commands;
 pub mod hook;
@@ -31,16 +32,36 @@ pub mod no_bot;
 pub mod config;
 pub mod roles;
 pub mod me;
+pub mod spam;
+pub mod punishment;
+pub mod help;
+pub mod permissions;
+pub mod overrides;
+pub mod modlog;
+pub mod infractions;
+pub mod sanctions;
+pub mod maintenance;
+pub mod links;
+pub mod filter;
+pub mod raid;
+pub mod gate;
+pub mod reaction_roles;
+pub mod alias;
+pub mod export;
 
 /// An integrated unit of functionality for Glimbot. A module may have a command module associated with it,
 /// and one or more hooks.
 pub struct Module {
     name: String,
     command_handler: Option<Arc<dyn Command>>,
+    aliases: Vec<String>,
     command_hooks: Vec<CommandHookFn>,
+    message_hooks: Vec<MessageHookFn>,
+    event_hooks: Vec<EventHookFn>,
+    job_handlers: Vec<(String, JobHandlerFn)>,
     config_values: Vec<config::Value>,
     dependencies: HashSet<String>,
-    sensitive: bool
+    required_tier: Tier
 }
 
 impl Module {
@@ -49,10 +70,14 @@ impl Module {
         let mut o = Module {
             name: name.into(),
             command_hooks: Vec::new(),
+            message_hooks: Vec::new(),
+            event_hooks: Vec::new(),
+            job_handlers: Vec::new(),
             command_handler: None,
+            aliases: Vec::new(),
             config_values: Vec::new(),
             dependencies: HashSet::new(),
-            sensitive: true
+            required_tier: Tier::Admin
         };
 
         o.dependencies.insert("base_hooks".to_string());
@@ -65,9 +90,15 @@ impl Module {
         self
     }
 
-    /// Sets the sensitivity for the module.
-    pub fn with_sensitivity(mut self, is_sensitive: bool) -> Self {
-        self.sensitive = is_sensitive;
+    /// Sets the sensitivity for the module. Sensitive modules require the admin tier, while
+    /// other modules may be used by everyone.
+    pub fn with_sensitivity(self, is_sensitive: bool) -> Self {
+        self.with_required_tier(if is_sensitive { Tier::Admin } else { Tier::Everyone })
+    }
+
+    /// Sets the permission tier needed to run the module's command.
+    pub fn with_required_tier(mut self, tier: Tier) -> Self {
+        self.required_tier = tier;
         self
     }
 
@@ -77,6 +108,25 @@ impl Module {
         self
     }
 
+    /// Adds a message hook to the current module.
+    pub fn with_message_hook(mut self, f: MessageHookFn) -> Self {
+        self.message_hooks.push(f);
+        self
+    }
+
+    /// Adds an event hook to the current module.
+    pub fn with_event_hook(mut self, f: EventHookFn) -> Self {
+        self.event_hooks.push(f);
+        self
+    }
+
+    /// Adds a handler for scheduled jobs of the given kind to the current module.
+    /// Loading two modules which handle the same kind of job will panic.
+    pub fn with_job_handler(mut self, kind: impl Into<String>, f: JobHandlerFn) -> Self {
+        self.job_handlers.push((kind.into(), f));
+        self
+    }
+
     /// Sets the command handler for the current module.
     pub fn with_command<T: Command + 'static>(mut self, cmd: T) -> Self {
         let ptr: Arc<dyn Command> = Arc::new(cmd);
@@ -84,6 +134,13 @@ impl Module {
         self
     }
 
+    /// Adds another name the module's command can be invoked by.
+    /// Loading a module whose name or aliases are already taken will panic.
+    pub fn with_alias(mut self, alias: impl Into<String>) -> Self {
+        self.aliases.push(alias.into());
+        self
+    }
+
     /// Associates a [Value][config::Value] with this module.
     pub fn with_config_value(mut self, v: config::Value) -> Self {
         self.config_values.push(v);
@@ -118,11 +175,31 @@ impl Module {
         self.command_handler.as_ref()
     }
 
+    /// Accessor for the aliases of the module's command.
+    pub fn aliases(&self) -> &[String] {
+        &self.aliases
+    }
+
     /// Accessor for any command hooks held in the Module.
     pub fn command_hooks(&self) -> &[CommandHookFn] {
         &self.command_hooks
     }
 
+    /// Accessor for any message hooks held in the Module.
+    pub fn message_hooks(&self) -> &[MessageHookFn] {
+        &self.message_hooks
+    }
+
+    /// Accessor for any event hooks held in the Module.
+    pub fn event_hooks(&self) -> &[EventHookFn] {
+        &self.event_hooks
+    }
+
+    /// Accessor for the job handlers held in the Module, along with the kind of job each handles.
+    pub fn job_handlers(&self) -> &[(String, JobHandlerFn)] {
+        &self.job_handlers
+    }
+
     /// Accessor for the dependencies on other modules for this module.
     pub fn dependencies(&self) -> &HashSet<String> {
         &self.dependencies
@@ -130,6 +207,11 @@ impl Module {
 
     /// Accessor for whether or not this command should be restricted by default.
     pub fn is_sensitive(&self) -> bool {
-        self.sensitive
+        self.required_tier > Tier::Everyone
+    }
+
+    /// Accessor for the permission tier needed to run this module's command.
+    pub fn required_tier(&self) -> Tier {
+        self.required_tier
     }
 }
\ No newline at end of file
@@ -0,0 +1,132 @@
+//  Glimbot - A Discord anti-spam and administration bot.
+//  Copyright (C) 2020 Nick Samson
+
+//  This program is free software: you can redistribute it and/or modify
+//  it under the terms of the GNU General Public License as published by
+//  the Free Software Foundation, either version 3 of the License, or
+//  (at your option) any later version.
+
+//  This program is distributed in the hope that it will be useful,
+//  but WITHOUT ANY WARRANTY; without even the implied warranty of
+//  MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+//  GNU General Public License for more details.
+
+//  You should have received a copy of the GNU General Public License
+//  along with this program.  If not, see <https://www.gnu.org/licenses/>.
+
+//! The moderation log. Every privileged action Glimbot takes is recorded in the guild's audit log
+//! table, and posted as an embed to the modlog channel if one is configured.
+
+use crate::dispatch::Dispatch;
+use crate::modules::{Module, config};
+use crate::db::GuildConn;
+use crate::util::LogErrorExt;
+use serenity::prelude::Context;
+use serenity::model::id::{UserId, ChannelId};
+use chrono::{DateTime, Utc};
+use crate::modules::config::Kind;
+
+static MODLOG_KEY: &str = "modlog_channel";
+
+/// A single entry in the audit log.
+#[derive(Debug, Clone)]
+pub struct AuditEntry {
+    /// The user who performed the action. Automatic actions are attributed to Glimbot.
+    pub actor: UserId,
+    /// What was acted upon, e.g. a user or role mention or a config key.
+    pub target: String,
+    /// A short description of the action.
+    pub action: String,
+    /// Why the action was taken, if known.
+    pub reason: String,
+    /// When the action was taken.
+    pub time: DateTime<Utc>,
+}
+
+impl AuditEntry {
+    /// Creates a new entry for an action taken now, with no reason.
+    pub fn new(actor: UserId, action: impl Into<String>, target: impl Into<String>) -> Self {
+        AuditEntry {
+            actor,
+            target: target.into(),
+            action: action.into(),
+            reason: String::new(),
+            time: Utc::now(),
+        }
+    }
+
+    /// Creates a new entry for an action Glimbot took on its own.
+    pub fn automatic(ctx: &Context, action: impl Into<String>, target: impl Into<String>) -> Self {
+        let me = ctx.cache.read().user.id;
+        Self::new(me, action, target)
+    }
+
+    /// Sets the reason for the entry.
+    pub fn with_reason(mut self, reason: impl Into<String>) -> Self {
+        self.reason = reason.into();
+        self
+    }
+}
+
+/// Records an entry in the audit log, then posts it to the modlog channel if one is set.
+/// Failures are logged rather than returned, since the action itself has already happened.
+pub fn record(disp: &Dispatch, ctx: &Context, conn: &GuildConn, entry: &AuditEntry) {
+    conn.as_ref().execute(
+        "INSERT INTO audit_log (actor, target, action, reason, unix_time) VALUES (?, ?, ?, ?, ?);",
+        params![entry.actor.0 as i64, &entry.target, &entry.action, &entry.reason, entry.time.timestamp()]
+    ).log_error();
+
+    let channel = disp.get_optional_config::<ChannelId>(conn, MODLOG_KEY)
+        .ok()
+        .flatten();
+
+    if let Some(channel) = channel {
+        channel.send_message(ctx, |m| m.embed(|e| {
+            e.title(&entry.action)
+                .field("Actor", format!("<@{}>", entry.actor), true)
+                .field("Target", &entry.target, true)
+                .timestamp(&entry.time);
+            if !entry.reason.is_empty() {
+                e.field("Reason", &entry.reason, false);
+            }
+            e
+        })).log_error();
+    }
+}
+
+/// Creates the modlog module.
+pub fn modlog_mod() -> Module {
+    Module::with_name("modlog")
+        .with_config_value(config::Value::new(
+            MODLOG_KEY,
+            "The channel Glimbot posts moderation log entries to.",
+            Kind::Channel,
+            Option::<String>::None,
+        ))
+        .with_dependency("config")
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::util::test_guild_conn;
+    use crate::util::test_context;
+    use rusqlite::NO_PARAMS;
+
+    #[test]
+    fn test_record() {
+        let (_dir, gconn) = test_guild_conn();
+        let disp = Dispatch::new(UserId(1)).with_module(modlog_mod().clear_dependencies());
+
+        let entry = AuditEntry::new(UserId(2), "ban", "<@3>").with_reason("Spamming");
+        record(&disp, &test_context(), &gconn, &entry);
+
+        let row: (i64, String, String, String, i64) = gconn.as_ref().query_row(
+            "SELECT actor, target, action, reason, unix_time FROM audit_log;",
+            NO_PARAMS,
+            |r| Ok((r.get(0)?, r.get(1)?, r.get(2)?, r.get(3)?, r.get(4)?))
+        ).unwrap();
+        assert_eq!(row, (2, "<@3>".to_string(), "ban".to_string(), "Spamming".to_string(), entry.time.timestamp()));
+    }
+}
\ No newline at end of file
@@ -23,8 +23,7 @@ use std::borrow::{Cow};
 use crate::db::cache::get_cached_connection;
 use crate::modules::hook::Error::DeniedWithReason;
 use crate::modules::{Module, config};
-use crate::modules::config::{valid_bool, simple_validator};
-use std::sync::Arc;
+use crate::modules::config::Kind;
 
 static NO_BOT_KEY: &'static str = "ignore_bots";
 const DEFAULT_VALUE: bool = false;
@@ -33,7 +32,7 @@ const DEFAULT_VALUE: bool = false;
 fn no_bot_hook<'a, 'b, 'c, 'd>(disp: &'a Dispatch, _ctx: &'b Context, msg: &'c Message, name: Cow<'d, str>) -> super::hook::Result<Cow<'d, str>> {
     let conn = get_cached_connection(msg.guild_id.unwrap())?;
     let rl = conn.as_ref().borrow();
-    let bots_allowed = disp.get_or_set_config(&rl, NO_BOT_KEY)?.parse::<bool>().unwrap();
+    let bots_allowed = disp.get_config::<bool>(&rl, NO_BOT_KEY)?;
     if bots_allowed || (!bots_allowed && !msg.author.bot){
         Ok(name)
     } else {
@@ -47,7 +46,7 @@ pub fn deny_bot_mod() -> Module {
         .with_config_value(
             config::Value::new(NO_BOT_KEY,
                                "Whether or not bots are allowed to send Glimbot commands. Default is false.",
-                               Arc::new(simple_validator(valid_bool)),
+                               Kind::Bool,
                                Some(DEFAULT_VALUE.to_string())))
         .with_command_hook(no_bot_hook)
         .with_sensitivity(true)
@@ -0,0 +1,347 @@
+//  Glimbot - A Discord anti-spam and administration bot.
+//  Copyright (C) 2020 Nick Samson
+
+//  This program is free software: you can redistribute it and/or modify
+//  it under the terms of the GNU General Public License as published by
+//  the Free Software Foundation, either version 3 of the License, or
+//  (at your option) any later version.
+
+//  This program is distributed in the hope that it will be useful,
+//  but WITHOUT ANY WARRANTY; without even the implied warranty of
+//  MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+//  GNU General Public License for more details.
+
+//  You should have received a copy of the GNU General Public License
+//  along with this program.  If not, see <https://www.gnu.org/licenses/>.
+
+//! Module for managing per-command permission overrides.
+//!
+//! An override allows or denies a command (or one of its subcommands) to a role or user, optionally
+//! only in a single channel. When several overrides match, the most specific one wins:
+//! a channel override beats a guild-wide one, then a user override beats a role override, then
+//! a subcommand override beats a whole-command one. If overrides of equal specificity disagree,
+//! deny wins.
+
+use crate::modules::commands::Command;
+use crate::modules::Module;
+use crate::modules::hook::Error::DeniedWithReason;
+use crate::modules::permissions::check_command;
+use crate::modules::roles::resolve_role;
+use crate::modules::modlog::{record, AuditEntry};
+use crate::dispatch::Dispatch;
+use crate::args::{parse_app_matches, subcommand_name, subcommand_names};
+use crate::util::help_str;
+use crate::db::cache::get_cached_connection;
+use crate::db::{PermissionOverride, OverrideTarget};
+use serenity::prelude::Context;
+use serenity::model::channel::Message;
+use serenity::model::guild::Guild;
+use serenity::model::id::{GuildId, UserId, RoleId, ChannelId};
+use serenity::utils::MessageBuilder;
+use std::borrow::Cow;
+use std::fmt::Write;
+use std::str::FromStr;
+use once_cell::unsync::Lazy;
+use clap::{App, AppSettings, Arg, ArgMatches, SubCommand};
+
+/// Finds the most specific override which applies to the user, returning whether it allows the command.
+/// `roles` should be the user's roles; the guild's @everyone role is always considered.
+pub fn resolve_override(overrides: &[PermissionOverride], guild: GuildId, user: UserId, roles: &[RoleId], channel: Option<ChannelId>, subcommand: Option<&str>) -> Option<bool> {
+    let everyone = RoleId(guild.0);
+    let applicable = overrides.iter().filter(|o| {
+        let target = match o.target {
+            OverrideTarget::User(u) => u == user,
+            OverrideTarget::Role(r) => r == everyone || roles.contains(&r),
+        };
+        let channel = o.channel.is_none() || o.channel == channel;
+        let subcommand = o.subcommand.is_none() || o.subcommand.as_deref() == subcommand;
+        target && channel && subcommand
+    });
+
+    let specificity = |o: &PermissionOverride| (
+        o.channel.is_some(),
+        matches!(o.target, OverrideTarget::User(_)),
+        o.subcommand.is_some()
+    );
+
+    applicable.fold(None, |best: Option<(_, bool)>, o| {
+        let spec = specificity(o);
+        match best {
+            Some((b, allow)) if b == spec => Some((b, allow && o.allow)),
+            Some((b, _)) if b > spec => best,
+            _ => Some((spec, o.allow))
+        }
+    }).map(|(_, allow)| allow)
+}
+
+/// Resolves a string into the role or user an override should target. Roles take precedence.
+fn resolve_target(guild: &Guild, s: &str) -> super::hook::Result<OverrideTarget> {
+    if let Ok(role) = resolve_role(guild, s) {
+        return Ok(OverrideTarget::Role(role.id));
+    }
+
+    let user = if let Ok(id) = UserId::from_str(s) {
+        id
+    } else {
+        guild.member_named(s)
+            .ok_or_else(|| DeniedWithReason("No such role or user.".into()))?
+            .user.read().id
+    };
+
+    Ok(OverrideTarget::User(user))
+}
+
+/// Checks that the (canonical) command has the given subcommand, so overrides can't name one which
+/// would never match.
+fn check_subcommand<'a>(disp: &Dispatch, command: &str, sub: &'a str) -> super::hook::Result<&'a str> {
+    let known = disp.resolve_command(command)
+        .map(|c| c.subcommands())
+        .unwrap_or_default();
+    if known.iter().any(|s| s == sub) {
+        Ok(sub)
+    } else if known.is_empty() {
+        Err(DeniedWithReason(format!("{} has no subcommands.", command).into()))
+    } else {
+        Err(DeniedWithReason(format!("{} has no subcommand {}. Try one of: {}", command, sub, known.join(", ")).into()))
+    }
+}
+
+fn describe(guild: &Guild, ctx: &Context, o: &PermissionOverride) -> String {
+    let mut out = format!("#{} {} {}", o.id, if o.allow { "allow" } else { "deny" }, o.command);
+    if let Some(s) = &o.subcommand {
+        write!(out, " {}", s).unwrap();
+    }
+
+    match o.target {
+        OverrideTarget::Role(r) => {
+            let name = guild.roles.get(&r).map_or_else(|| r.to_string(), |r| r.name.clone());
+            write!(out, " for role {}", name).unwrap();
+        },
+        OverrideTarget::User(u) => {
+            let name = guild.members.get(&u).map_or_else(|| u.to_string(), |m| m.display_name().into_owned());
+            write!(out, " for user {}", name).unwrap();
+        },
+    }
+
+    if let Some(c) = o.channel {
+        write!(out, " in #{}", c.name(ctx).unwrap_or_else(|| c.to_string())).unwrap();
+    }
+
+    out
+}
+
+fn describe_target(command: &str, target: OverrideTarget) -> String {
+    match target {
+        OverrideTarget::Role(r) => format!("{} for <@&{}>", command, r),
+        OverrideTarget::User(u) => format!("{} for <@{}>", command, u),
+    }
+}
+
+/// ZST struct for processing the `overrides` command
+pub struct Overrides;
+
+thread_local! {
+    static PARSER: Lazy<App<'static, 'static>> = Lazy::new(
+        || {
+            let command = Arg::with_name("command")
+                .value_name("COMMAND")
+                .help("The command the override applies to.")
+                .takes_value(true)
+                .required(true);
+
+            let target = Arg::with_name("target")
+                .value_name("ROLE_OR_USER")
+                .help("The role or user the override applies to. Roles are checked first.")
+                .takes_value(true)
+                .required(true);
+
+            let subcommand = Arg::with_name("subcommand")
+                .long("sub")
+                .short("s")
+                .value_name("SUBCOMMAND")
+                .help("Limits the override to a single subcommand.")
+                .takes_value(true);
+
+            let channel = Arg::with_name("channel")
+                .long("channel")
+                .short("c")
+                .value_name("CHANNEL")
+                .help("Limits the override to a single channel.")
+                .takes_value(true);
+
+            App::new("overrides")
+                .about("Command for allowing or denying commands to specific roles and users.")
+                .setting(AppSettings::SubcommandRequiredElseHelp)
+                .subcommand(
+                    SubCommand::with_name("allow")
+                        .about("Allows a role or user to run a command.")
+                        .args(&[command.clone(), target.clone(), subcommand.clone(), channel.clone()])
+                )
+                .subcommand(
+                    SubCommand::with_name("deny")
+                        .about("Denies a role or user from running a command.")
+                        .args(&[command.clone(), target.clone(), subcommand.clone(), channel.clone()])
+                )
+                .subcommand(
+                    SubCommand::with_name("list")
+                        .about("Lists the overrides in this guild.")
+                        .arg(command.clone().required(false))
+                )
+                .subcommand(
+                    SubCommand::with_name("remove")
+                        .about("Removes an override by its number, as shown by `list`.")
+                        .arg(Arg::with_name("id")
+                            .value_name("ID")
+                            .help("The number of the override.")
+                            .takes_value(true)
+                            .required(true)
+                        )
+                )
+        }
+    );
+}
+
+impl Command for Overrides {
+    fn invoke(&self, disp: &Dispatch, ctx: &Context, msg: &Message, args: Cow<str>) -> super::commands::Result<()> {
+        let m: ArgMatches = PARSER.with(|p| parse_app_matches("overrides", args, p))?;
+
+        let guild = msg.guild(ctx).unwrap();
+        let rg = guild.read();
+        let conn = get_cached_connection(rg.id)?;
+        let rconn = conn.borrow();
+
+        let reply = match m.subcommand() {
+            ("list", Some(m)) => {
+                let command = m.value_of("command").map(|c| disp.canonical_command(c).unwrap_or(c));
+                let overrides = rconn.overrides(command)?;
+                if overrides.is_empty() {
+                    "No overrides are set.".to_string()
+                } else {
+                    overrides.iter()
+                        .map(|o| describe(&rg, ctx, o))
+                        .collect::<Vec<_>>()
+                        .join("\n")
+                }
+            },
+            ("remove", Some(m)) => {
+                let id = m.value_of("id").unwrap().trim_start_matches('#').parse::<i64>()
+                    .map_err(|_| DeniedWithReason("Override numbers must be integers.".into()))?;
+                if rconn.remove_override(id)? {
+                    record(disp, ctx, &rconn, &AuditEntry::new(msg.author.id, "Removed override", format!("#{}", id)));
+                    format!("Removed override #{}.", id)
+                } else {
+                    format!("There is no override #{}.", id)
+                }
+            },
+            (s, Some(m)) => {
+                let command = check_command(disp, m.value_of("command").unwrap())?;
+                let target = resolve_target(&rg, m.value_of("target").unwrap())?;
+                let channel = m.value_of("channel")
+                    .map(|c| ChannelId::from_str(c)
+                        .ok()
+                        .filter(|c| rg.channels.contains_key(c))
+                        .ok_or_else(|| DeniedWithReason("No such channel.".into())))
+                    .transpose()?;
+
+                let subcommand = m.value_of("subcommand")
+                    .map(|sub| check_subcommand(disp, command, sub))
+                    .transpose()?;
+
+                rconn.set_override(command, subcommand, target, channel, s == "allow")?;
+                let action = format!("Set {} override", s);
+                record(disp, ctx, &rconn, &AuditEntry::new(msg.author.id, action, describe_target(command, target)));
+                "Override set.".to_string()
+            },
+            _ => unreachable!()
+        };
+
+        msg.channel_id.say(ctx, MessageBuilder::new()
+            .push_codeblock_safe(reply, None)
+            .build())?;
+
+        Ok(())
+    }
+
+    fn subcommand(&self, args: &str) -> Option<String> {
+        PARSER.with(|p| subcommand_name("overrides", args, p))
+    }
+
+    fn subcommands(&self) -> Vec<String> {
+        PARSER.with(|p| subcommand_names(p))
+    }
+
+    fn help(&self) -> Cow<'static, str> {
+        PARSER.with(|p| help_str(p).into())
+    }
+}
+
+/// Creates the overrides module.
+pub fn overrides_mod() -> Module {
+    Module::with_name("overrides")
+        .with_command(Overrides)
+        .with_sensitivity(true)
+        .with_dependency("permissions")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::modules::roles::roles_module;
+    use crate::modules::help::help_mod;
+
+    fn o(subcommand: Option<&str>, target: OverrideTarget, channel: Option<u64>, allow: bool) -> PermissionOverride {
+        PermissionOverride {
+            id: 0,
+            command: "roles".to_string(),
+            subcommand: subcommand.map(String::from),
+            target,
+            channel: channel.map(ChannelId),
+            allow,
+        }
+    }
+
+    #[test]
+    fn test_resolve_override() {
+        let guild = GuildId(1);
+        let user = UserId(2);
+        let moderator = RoleId(3);
+        let muted = RoleId(4);
+        let roles = [moderator, muted];
+        let resolve = |os: &[PermissionOverride], channel, sub| resolve_override(os, guild, user, &roles, channel, sub);
+
+        assert_eq!(resolve(&[], None, None), None);
+
+        let mod_add = o(Some("add-user"), OverrideTarget::Role(moderator), None, true);
+        assert_eq!(resolve(std::slice::from_ref(&mod_add), None, Some("add-user")), Some(true));
+        assert_eq!(resolve(std::slice::from_ref(&mod_add), None, Some("rem-user")), None);
+
+        // Deny wins at equal specificity.
+        let muted_add = o(Some("add-user"), OverrideTarget::Role(muted), None, false);
+        assert_eq!(resolve(&[mod_add.clone(), muted_add.clone()], None, Some("add-user")), Some(false));
+
+        // User beats role.
+        let user_add = o(None, OverrideTarget::User(user), None, true);
+        assert_eq!(resolve(&[muted_add.clone(), user_add.clone()], None, Some("add-user")), Some(true));
+
+        // Channel beats everything else.
+        let everyone_chan = o(None, OverrideTarget::Role(RoleId(guild.0)), Some(5), false);
+        let all = [mod_add, muted_add, user_add, everyone_chan];
+        assert_eq!(resolve(&all, Some(ChannelId(5)), Some("add-user")), Some(false));
+        assert_eq!(resolve(&all, Some(ChannelId(6)), Some("add-user")), Some(true));
+
+        // Other users' overrides don't apply.
+        let other = o(None, OverrideTarget::User(UserId(7)), None, false);
+        assert_eq!(resolve(&[other], None, None), None);
+    }
+
+    #[test]
+    fn test_check_subcommand() {
+        let disp = Dispatch::new(UserId(1))
+            .with_module(roles_module().clear_dependencies())
+            .with_module(help_mod().clear_dependencies());
+
+        assert_eq!(check_subcommand(&disp, "roles", "add-user").unwrap(), "add-user");
+        assert!(check_subcommand(&disp, "roles", "add-users").is_err());
+        assert!(check_subcommand(&disp, "help", "add-user").is_err());
+    }
+}
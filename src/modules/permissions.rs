@@ -0,0 +1,156 @@
+//  Glimbot - A Discord anti-spam and administration bot.
+//  Copyright (C) 2020 Nick Samson
+
+//  This program is free software: you can redistribute it and/or modify
+//  it under the terms of the GNU General Public License as published by
+//  the Free Software Foundation, either version 3 of the License, or
+//  (at your option) any later version.
+
+//  This program is distributed in the hope that it will be useful,
+//  but WITHOUT ANY WARRANTY; without even the implied warranty of
+//  MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+//  GNU General Public License for more details.
+
+//  You should have received a copy of the GNU General Public License
+//  along with this program.  If not, see <https://www.gnu.org/licenses/>.
+
+//! Module for restricting otherwise non-sensitive commands to admins on a per-guild basis, and for
+//! viewing the staff roles which grant permission tiers.
+
+use crate::modules::commands::Command;
+use crate::modules::Module;
+use crate::modules::hook::Error::CommandNotFound;
+use crate::db::Tier;
+use crate::modules::modlog::{record, AuditEntry};
+use crate::dispatch::Dispatch;
+use crate::args::{parse_app_matches, subcommand_name, subcommand_names};
+use crate::util::help_str;
+use crate::db::cache::get_cached_connection;
+use serenity::prelude::Context;
+use serenity::model::channel::Message;
+use serenity::utils::MessageBuilder;
+use std::borrow::Cow;
+use once_cell::unsync::Lazy;
+use clap::{App, AppSettings, Arg, ArgMatches, SubCommand};
+
+/// ZST struct for processing the `permissions` command
+pub struct Permissions;
+
+thread_local! {
+    static PARSER: Lazy<App<'static, 'static>> = Lazy::new(
+        || {
+            let command = Arg::with_name("command")
+                .value_name("COMMAND")
+                .help("The name of a command.")
+                .takes_value(true)
+                .required(true);
+
+            App::new("permissions")
+                .about("Command for restricting commands to admins in this guild.")
+                .setting(AppSettings::SubcommandRequiredElseHelp)
+                .subcommand(
+                    SubCommand::with_name("restrict")
+                        .about("Restricts a command so only admins may run it.")
+                        .arg(command.clone())
+                )
+                .subcommand(
+                    SubCommand::with_name("unrestrict")
+                        .about("Lifts a restriction placed with `restrict`.")
+                        .arg(command.clone())
+                )
+                .subcommand(
+                    SubCommand::with_name("list-restricted")
+                        .about("Lists the commands restricted in this guild.")
+                )
+                .subcommand(
+                    SubCommand::with_name("list-staff")
+                        .about("Lists the staff roles in this guild and the tier each grants. Use `roles <ROLE> set-tier` to change them.")
+                )
+        }
+    );
+}
+
+/// Ensures the given name refers to a loaded module with a command, returning the command's canonical
+/// name so that settings made through a module alias apply to the command itself.
+pub fn check_command<'a>(disp: &'a Dispatch, name: &str) -> super::hook::Result<&'a str> {
+    disp.canonical_command(name)
+        .ok_or_else(|| CommandNotFound(name.to_string()))
+}
+
+impl Command for Permissions {
+    fn invoke(&self, disp: &Dispatch, ctx: &Context, msg: &Message, args: Cow<str>) -> super::commands::Result<()> {
+        let m: ArgMatches = PARSER.with(|p| parse_app_matches("permissions", args, p))?;
+
+        let conn = get_cached_connection(msg.guild_id.unwrap())?;
+        let rconn = conn.borrow();
+
+        let reply = match m.subcommand() {
+            ("list-restricted", Some(_)) => {
+                let restricted = rconn.restricted_commands()?;
+                if restricted.is_empty() {
+                    "No commands are restricted.".to_string()
+                } else {
+                    format!("Restricted commands: {}", restricted.join(", "))
+                }
+            },
+            ("list-staff", Some(_)) => {
+                let staff = rconn.staff_roles()?;
+                if staff.is_empty() {
+                    "No staff roles are set. Only the server owner may run sensitive commands.".to_string()
+                } else {
+                    let guild = msg.guild(ctx).unwrap();
+                    let rg = guild.read();
+                    staff.into_iter()
+                        .map(|(r, t)| {
+                            let name = rg.roles.get(&r).map_or_else(|| r.to_string(), |r| r.name.clone());
+                            format!("{}: {}", name, t)
+                        })
+                        .collect::<Vec<_>>()
+                        .join("\n")
+                }
+            },
+            (s, Some(m)) => {
+                let name = check_command(disp, m.value_of("command").unwrap())?;
+                let restricting = s == "restrict";
+                rconn.set_command_restricted(name, restricting)?;
+                let action = if restricting { "Restricted command" } else { "Unrestricted command" };
+                record(disp, ctx, &rconn, &AuditEntry::new(msg.author.id, action, name));
+
+                let tier = disp.modules().get(name).map_or(Tier::Everyone, |m| m.required_tier());
+                match (restricting, tier) {
+                    (true, _) => format!("Restricted {}.", name),
+                    (false, Tier::Everyone) => format!("Unrestricted {}.", name),
+                    (false, t) => format!("Unrestricted {}, but it still requires {}.", name, t),
+                }
+            },
+            _ => unreachable!()
+        };
+
+        msg.channel_id.say(ctx, MessageBuilder::new()
+            .push_codeblock_safe(reply, None)
+            .build())?;
+
+        Ok(())
+    }
+
+    fn subcommand(&self, args: &str) -> Option<String> {
+        PARSER.with(|p| subcommand_name("permissions", args, p))
+    }
+
+    fn subcommands(&self) -> Vec<String> {
+        PARSER.with(|p| subcommand_names(p))
+    }
+
+    fn help(&self) -> Cow<'static, str> {
+        PARSER.with(|p| help_str(p).into())
+    }
+}
+
+/// Creates the permissions module.
+pub fn permissions_mod() -> Module {
+    Module::with_name("permissions")
+        .with_command(Permissions)
+        .with_alias("perms")
+        .with_sensitivity(true)
+        .with_dependency("roles")
+}
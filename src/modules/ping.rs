@@ -43,6 +43,10 @@ impl Command for Ping {
         msg.channel_id.say(&ctx.http, message).map_err(AnyError::boxed)?;
         Ok(())
     }
+
+    fn help(&self) -> Cow<'static, str> {
+        Cow::Borrowed("ping [TEXT]\nReplies with Pong!, or echoes TEXT back if given.")
+    }
 }
 
 /// Creates a new ping module.
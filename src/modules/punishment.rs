@@ -0,0 +1,254 @@
+//  Glimbot - A Discord anti-spam and administration bot.
+//  Copyright (C) 2020 Nick Samson
+
+//  This program is free software: you can redistribute it and/or modify
+//  it under the terms of the GNU General Public License as published by
+//  the Free Software Foundation, either version 3 of the License, or
+//  (at your option) any later version.
+
+//  This program is distributed in the hope that it will be useful,
+//  but WITHOUT ANY WARRANTY; without even the implied warranty of
+//  MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+//  GNU General Public License for more details.
+
+//  You should have received a copy of the GNU General Public License
+//  along with this program.  If not, see <https://www.gnu.org/licenses/>.
+
+//! Contains the actions Glimbot can take against users who break the rules of a guild.
+//!
+//! Temporary punishments schedule an `unmute` or `unban` job, which the punishment module's job
+//! handlers run once the punishment expires.
+
+use serenity::prelude::Context;
+use serenity::model::prelude::{Message, GuildId, UserId, ChannelId, MessageId, RoleId};
+use serenity::utils::MessageBuilder;
+use chrono::{DateTime, Duration, Utc};
+use std::fmt;
+use once_cell::sync::Lazy;
+use regex::Regex;
+use crate::db::{GuildConn, Job};
+use crate::dispatch::Dispatch;
+use crate::error::{BotResult, SerenityError};
+use crate::modules::{Module, hook};
+use crate::modules::modlog::{record, AuditEntry};
+
+static UNMUTE_JOB: &str = "unmute";
+static UNBAN_JOB: &str = "unban";
+
+/// The user (and optionally the message) an [Action] is applied to.
+#[derive(Debug, Clone, Copy)]
+pub struct Target {
+    /// The guild the action takes place in.
+    pub guild: GuildId,
+    /// The user being acted upon.
+    pub user: UserId,
+    /// The channel any notices should be posted in.
+    pub channel: ChannelId,
+    /// The offending message, if any.
+    pub message: Option<MessageId>,
+}
+
+impl Target {
+    /// Creates a target for the author of a guild message.
+    /// # Panics
+    /// If the message was not sent in a guild.
+    pub fn from_message(msg: &Message) -> Self {
+        Target {
+            guild: msg.guild_id.unwrap(),
+            user: msg.author.id,
+            channel: msg.channel_id,
+            message: Some(msg.id),
+        }
+    }
+}
+
+/// An action which can be taken against a user.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Action {
+    /// Deletes the offending message.
+    Delete,
+    /// Posts a warning mentioning the user.
+    Warn,
+    /// Gives the user a role for the given duration.
+    Mute {
+        /// The role used to mute users.
+        role: RoleId,
+        /// How long the user should stay muted.
+        duration: Duration,
+    },
+    /// Kicks the user from the guild.
+    Kick,
+    /// Bans the user from the guild.
+    Ban,
+    /// Bans the user from the guild for the given duration.
+    TempBan {
+        /// How long the user should stay banned.
+        duration: Duration,
+    },
+}
+
+impl Action {
+    /// Applies the action to the target, giving the reason where Discord allows it.
+    /// Temporary actions schedule a job in the guild database to lift them.
+    pub fn apply(&self, ctx: &Context, conn: &GuildConn, target: &Target, reason: &str) -> BotResult<()> {
+        debug!("Applying {} to user {} in guild {}: {}", self, target.user, target.guild, reason);
+        match *self {
+            Action::Delete => {
+                if let Some(m) = target.message {
+                    target.channel.delete_message(ctx, m).map_err(SerenityError::from)?;
+                }
+            },
+            Action::Warn => {
+                target.channel.say(ctx, MessageBuilder::new()
+                    .mention(&target.user)
+                    .push(" you have been warned: ")
+                    .push_safe(reason)
+                    .build()
+                ).map_err(SerenityError::from)?;
+            },
+            Action::Mute { role, duration } => {
+                let until = expiry(duration)?;
+                ctx.http.add_member_role(target.guild.0, target.user.0, role.0).map_err(SerenityError::from)?;
+                // Muting an already muted user restarts their mute.
+                let payload = format!("{} {}", target.user, role);
+                conn.cancel_jobs(UNMUTE_JOB, &payload)?;
+                conn.schedule_job(UNMUTE_JOB, &payload, &until, None)?;
+            },
+            Action::Kick => {
+                target.guild.kick_with_reason(ctx, target.user, reason).map_err(SerenityError::from)?;
+            },
+            Action::Ban => {
+                target.guild.ban(ctx, target.user, &(0, reason)).map_err(SerenityError::from)?;
+            },
+            Action::TempBan { duration } => {
+                let until = expiry(duration)?;
+                target.guild.ban(ctx, target.user, &(0, reason)).map_err(SerenityError::from)?;
+                let payload = target.user.to_string();
+                conn.cancel_jobs(UNBAN_JOB, &payload)?;
+                conn.schedule_job(UNBAN_JOB, &payload, &until, None)?;
+            },
+        }
+
+        Ok(())
+    }
+}
+
+/// Works out when a temporary punishment starting now ends.
+fn expiry(duration: Duration) -> BotResult<DateTime<Utc>> {
+    Ok(Utc::now().checked_add_signed(duration)
+        .ok_or(hook::Error::DeniedWithReason("That punishment would last too long.".into()))?)
+}
+
+fn parse_ids(payload: &str) -> Option<Vec<u64>> {
+    payload.split_whitespace().map(|s| s.parse::<u64>().ok()).collect()
+}
+
+/// Records a lifted punishment. Requests Discord refuses outright (e.g. the user has since left)
+/// are dropped rather than retried.
+fn finish_lift(disp: &Dispatch, ctx: &Context, conn: &GuildConn, res: serenity::Result<()>, action: &str, user: UserId) -> hook::Result<()> {
+    match res.map_err(SerenityError::from) {
+        Ok(()) => {
+            let entry = AuditEntry::automatic(ctx, action, format!("<@{}>", user))
+                .with_reason("The punishment expired.");
+            record(disp, ctx, conn, &entry);
+            Ok(())
+        },
+        Err(e) if matches!(e.unsuccessful_request(), Some(r) if r.status_code.is_client_error()) => {
+            warn!("Could not lift punishment for user {} in guild {}: {}", user, conn.as_id(), e);
+            Ok(())
+        },
+        Err(e) => Err(e.into())
+    }
+}
+
+fn unmute_job(disp: &Dispatch, ctx: &Context, conn: &GuildConn, job: &Job) -> hook::Result<()> {
+    let (user, role) = match parse_ids(&job.payload).as_deref() {
+        Some([user, role]) => (UserId(*user), RoleId(*role)),
+        _ => {
+            warn!("Dropping unmute job {} with malformed payload {:?}", job.id, job.payload);
+            return Ok(());
+        }
+    };
+
+    let res = ctx.http.remove_member_role(conn.as_id().0, user.0, role.0);
+    finish_lift(disp, ctx, conn, res, "Unmuted", user)
+}
+
+fn unban_job(disp: &Dispatch, ctx: &Context, conn: &GuildConn, job: &Job) -> hook::Result<()> {
+    let user = match parse_ids(&job.payload).as_deref() {
+        Some([user]) => UserId(*user),
+        _ => {
+            warn!("Dropping unban job {} with malformed payload {:?}", job.id, job.payload);
+            return Ok(());
+        }
+    };
+
+    let res = conn.as_id().unban(ctx, user);
+    finish_lift(disp, ctx, conn, res, "Unbanned", user)
+}
+
+static DURATION_RE: Lazy<Regex> = Lazy::new(|| Regex::new(r"^(?:(\d+)d)?(?:(\d+)h)?(?:(\d+)m)?(?:(\d+)s?)?$").unwrap());
+
+/// The longest duration [parse_duration] accepts, in seconds.
+pub const MAX_DURATION_SECS: i64 = 365 * 86400;
+
+/// Parses a duration such as `90`, `45s`, `10m`, `2h30m` or `7d`. Bare numbers are seconds.
+/// Durations longer than [MAX_DURATION_SECS] are rejected.
+pub fn parse_duration(s: &str) -> Option<Duration> {
+    let caps = DURATION_RE.captures(s.trim())?;
+    let part = |i: usize| caps.get(i).map_or(Ok(0), |m| m.as_str().parse::<i64>());
+    let (days, hours, minutes, seconds) = (part(1).ok()?, part(2).ok()?, part(3).ok()?, part(4).ok()?);
+    let total = days.checked_mul(86400)?
+        .checked_add(hours.checked_mul(3600)?)?
+        .checked_add(minutes.checked_mul(60)?)?
+        .checked_add(seconds)?;
+
+    Some(total)
+        .filter(|t| *t > 0 && *t <= MAX_DURATION_SECS && *t <= Duration::max_value().num_seconds())
+        .map(Duration::seconds)
+}
+
+/// Creates the punishment module, which lifts temporary punishments once they expire.
+pub fn punishment_mod() -> Module {
+    Module::with_name("punishment")
+        .with_job_handler(UNMUTE_JOB, unmute_job)
+        .with_job_handler(UNBAN_JOB, unban_job)
+        .with_dependency("modlog")
+}
+
+impl fmt::Display for Action {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Action::Delete => write!(f, "delete"),
+            Action::Warn => write!(f, "warn"),
+            Action::Mute { duration, .. } => write!(f, "mute for {}s", duration.num_seconds()),
+            Action::Kick => write!(f, "kick"),
+            Action::Ban => write!(f, "ban"),
+            Action::TempBan { duration } => write!(f, "ban for {}s", duration.num_seconds()),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_duration() {
+        assert_eq!(parse_duration("90"), Some(Duration::seconds(90)));
+        assert_eq!(parse_duration("45s"), Some(Duration::seconds(45)));
+        assert_eq!(parse_duration("10m"), Some(Duration::minutes(10)));
+        assert_eq!(parse_duration("2h30m"), Some(Duration::minutes(150)));
+        assert_eq!(parse_duration("1d1s"), Some(Duration::seconds(86401)));
+        assert_eq!(parse_duration("0"), None);
+        assert_eq!(parse_duration(""), None);
+        assert_eq!(parse_duration("10x"), None);
+        assert_eq!(parse_duration("m"), None);
+        assert_eq!(parse_duration("365d"), Some(Duration::days(365)));
+        assert_eq!(parse_duration("365d1s"), None);
+        assert_eq!(parse_duration("100000000d"), None);
+        assert_eq!(parse_duration("999999999999d"), None);
+        assert_eq!(parse_duration("99999999999999999s"), None);
+        assert_eq!(parse_duration("99999999999999999999999"), None);
+    }
+}
@@ -0,0 +1,493 @@
+//  Glimbot - A Discord anti-spam and administration bot.
+//  Copyright (C) 2020 Nick Samson
+
+//  This program is free software: you can redistribute it and/or modify
+//  it under the terms of the GNU General Public License as published by
+//  the Free Software Foundation, either version 3 of the License, or
+//  (at your option) any later version.
+
+//  This program is distributed in the hope that it will be useful,
+//  but WITHOUT ANY WARRANTY; without even the implied warranty of
+//  MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+//  GNU General Public License for more details.
+
+//  You should have received a copy of the GNU General Public License
+//  along with this program.  If not, see <https://www.gnu.org/licenses/>.
+
+//! Raid detection. Member joins are counted over a rolling window kept in memory, along with how
+//! many of them came from newly created accounts. Once either count passes its threshold, the guild
+//! goes into lockdown, which is announced in the modlog and may raise the verification level, delete
+//! the guild's invites or kick everyone who joins until it ends.
+//!
+//! A lockdown is an `end_lockdown` job, so it ends on its own after `raid_lockdown_duration` even
+//! across restarts. The job's payload holds the verification level to restore, if it was raised.
+
+use crate::modules::commands::Command;
+use crate::modules::{Module, config, hook};
+use crate::modules::config::{FromConfig, Kind};
+use crate::modules::hook::Event;
+use crate::modules::modlog::{record, AuditEntry};
+use crate::dispatch::Dispatch;
+use crate::dispatch::KeyRetrievalError;
+use crate::args::{parse_app_matches, subcommand_name, subcommand_names};
+use crate::util::{help_str, LogErrorExt};
+use crate::db::cache::get_cached_connection;
+use crate::db::{GuildConn, Job};
+use serenity::prelude::Context;
+use serenity::model::channel::Message;
+use serenity::model::guild::VerificationLevel;
+use serenity::model::id::GuildId;
+use serenity::utils::MessageBuilder;
+use std::borrow::Cow;
+use std::collections::{HashMap, VecDeque};
+use std::str::FromStr;
+use once_cell::unsync::Lazy;
+use once_cell::sync::Lazy as SyncLazy;
+use parking_lot::Mutex;
+use chrono::{DateTime, Duration, Utc};
+use clap::{App, AppSettings, ArgMatches, SubCommand};
+
+static JOIN_COUNT_KEY: &str = "raid_join_count";
+static YOUNG_JOIN_COUNT_KEY: &str = "raid_young_join_count";
+static JOIN_WINDOW_KEY: &str = "raid_join_window";
+static YOUNG_ACCOUNT_AGE_KEY: &str = "raid_young_account_age";
+static LOCKDOWN_ACTION_KEY: &str = "raid_lockdown_action";
+static LOCKDOWN_DURATION_KEY: &str = "raid_lockdown_duration";
+
+const DEFAULT_JOIN_COUNT: usize = 0;
+const DEFAULT_YOUNG_JOIN_COUNT: usize = 0;
+const DEFAULT_JOIN_WINDOW: i64 = 60; // Seconds
+const DEFAULT_YOUNG_ACCOUNT_AGE: i64 = 7 * 86400; // Seconds
+const DEFAULT_LOCKDOWN_DURATION: i64 = 1800; // Seconds
+
+static END_LOCKDOWN_JOB: &str = "end_lockdown";
+
+// Recent joins by guild.
+static RECENT_JOINS: SyncLazy<Mutex<HashMap<GuildId, JoinWindow>>> = SyncLazy::new(
+    || Mutex::new(HashMap::new())
+);
+
+/// What a guild does while it is in lockdown, besides alerting the modlog.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum LockdownAction {
+    /// Only alerts the modlog.
+    Alert,
+    /// Raises the guild's verification level to require a verified phone, restoring it afterwards.
+    Verification,
+    /// Deletes every invite to the guild when the lockdown starts. Discord can't pause invites, and
+    /// deleted invites can't be restored, so they have to be recreated once the lockdown ends.
+    PauseInvites,
+    /// Kicks everyone who joins.
+    Kick,
+}
+
+impl LockdownAction {
+    /// The name of the action as used in the guild config.
+    pub fn as_str(self) -> &'static str {
+        match self {
+            LockdownAction::Alert => "alert",
+            LockdownAction::Verification => "verification",
+            LockdownAction::PauseInvites => "pause_invites",
+            LockdownAction::Kick => "kick",
+        }
+    }
+}
+
+impl FromStr for LockdownAction {
+    type Err = ();
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "alert" => Ok(LockdownAction::Alert),
+            "verification" => Ok(LockdownAction::Verification),
+            "pause_invites" => Ok(LockdownAction::PauseInvites),
+            "kick" => Ok(LockdownAction::Kick),
+            _ => Err(())
+        }
+    }
+}
+
+impl FromConfig for LockdownAction {
+    fn from_config(s: &str) -> Option<Self> {
+        s.parse().ok()
+    }
+}
+
+/// The joins a guild has seen recently.
+#[derive(Debug, Clone, Default)]
+pub struct JoinWindow {
+    joins: VecDeque<(DateTime<Utc>, bool)>,
+}
+
+impl JoinWindow {
+    /// Forgets joins older than the window, returning the number of joins left and how many of those
+    /// were from young accounts.
+    pub fn counts(&mut self, now: &DateTime<Utc>, window: Duration) -> (usize, usize) {
+        let since = *now - window;
+        while matches!(self.joins.front(), Some((t, _)) if *t <= since) {
+            self.joins.pop_front();
+        }
+
+        (self.joins.len(), self.joins.iter().filter(|(_, young)| *young).count())
+    }
+
+    /// Records a join and returns the counts as for [counts][JoinWindow::counts].
+    pub fn add(&mut self, now: &DateTime<Utc>, young: bool, window: Duration) -> (usize, usize) {
+        self.joins.push_back((*now, young));
+        self.counts(now, window)
+    }
+}
+
+/// A guild's raid detection settings.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct RaidSettings {
+    /// Joins within the window which start a lockdown. 0 disables the check.
+    pub join_count: usize,
+    /// Joins from young accounts within the window which start a lockdown. 0 disables the check.
+    pub young_join_count: usize,
+    /// The window over which joins are counted.
+    pub window: Duration,
+    /// Accounts created more recently than this are young.
+    pub young_account_age: Duration,
+    /// What to do during a lockdown.
+    pub action: LockdownAction,
+    /// How long lockdowns last unless ended by hand.
+    pub lockdown_duration: Duration,
+}
+
+impl RaidSettings {
+    /// Loads the raid settings for the guild.
+    pub fn load(disp: &Dispatch, conn: &GuildConn) -> Result<Self, KeyRetrievalError> {
+        Ok(RaidSettings {
+            join_count: disp.get_config(conn, JOIN_COUNT_KEY)?,
+            young_join_count: disp.get_config(conn, YOUNG_JOIN_COUNT_KEY)?,
+            window: disp.get_config(conn, JOIN_WINDOW_KEY)?,
+            young_account_age: disp.get_config(conn, YOUNG_ACCOUNT_AGE_KEY)?,
+            action: disp.get_config(conn, LOCKDOWN_ACTION_KEY)?,
+            lockdown_duration: disp.get_config(conn, LOCKDOWN_DURATION_KEY)?,
+        })
+    }
+
+    /// Whether the given join counts amount to a raid.
+    pub fn is_raid(&self, joins: usize, young_joins: usize) -> bool {
+        let reached = |n: usize, at: usize| at > 0 && n >= at;
+        reached(joins, self.join_count) || reached(young_joins, self.young_join_count)
+    }
+}
+
+fn verification_level(n: u64) -> Option<VerificationLevel> {
+    match n {
+        0 => Some(VerificationLevel::None),
+        1 => Some(VerificationLevel::Low),
+        2 => Some(VerificationLevel::Medium),
+        3 => Some(VerificationLevel::High),
+        4 => Some(VerificationLevel::Higher),
+        _ => None
+    }
+}
+
+/// Returns the job which will end the guild's lockdown, if it is in one.
+pub fn lockdown(conn: &GuildConn) -> crate::db::Result<Option<Job>> {
+    Ok(conn.jobs(Some(END_LOCKDOWN_JOB))?.into_iter().next())
+}
+
+fn start_lockdown(disp: &Dispatch, ctx: &Context, conn: &GuildConn, settings: &RaidSettings, now: &DateTime<Utc>, cause: &str) -> hook::Result<()> {
+    let mut guild = *conn.as_id();
+    warn!("Starting raid lockdown in guild {}: {}", guild, cause);
+
+    let mut previous = String::new();
+    if settings.action == LockdownAction::Verification {
+        let current = ctx.cache.read().guild(guild).map(|g| g.read().verification_level);
+        if let Some(level) = current.filter(|l| *l < VerificationLevel::Higher) {
+            guild.edit(ctx, |g| g.verification_level(VerificationLevel::Higher))?;
+            previous = level.num().to_string();
+        }
+    }
+
+    if settings.action == LockdownAction::PauseInvites {
+        let invites = guild.invites(ctx)?;
+        debug!("Deleting {} invites to guild {}", invites.len(), guild);
+        for invite in invites {
+            ctx.http.delete_invite(&invite.code).map(|_| ()).log_error();
+        }
+    }
+
+    conn.schedule_job(END_LOCKDOWN_JOB, &previous, &(*now + settings.lockdown_duration), None)?;
+    let entry = AuditEntry::automatic(ctx, "Started raid lockdown", settings.action.as_str())
+        .with_reason(cause);
+    record(disp, ctx, conn, &entry);
+    Ok(())
+}
+
+fn end_lockdown(disp: &Dispatch, ctx: &Context, conn: &GuildConn, job: &Job, entry: AuditEntry) -> hook::Result<()> {
+    let mut guild = *conn.as_id();
+    if let Some(level) = job.payload.parse::<u64>().ok().and_then(verification_level) {
+        guild.edit(ctx, |g| g.verification_level(level))?;
+    }
+
+    conn.remove_job(job.id)?;
+    RECENT_JOINS.lock().remove(&guild);
+    info!("Ended raid lockdown in guild {}", guild);
+    record(disp, ctx, conn, &entry);
+    Ok(())
+}
+
+fn end_lockdown_job(disp: &Dispatch, ctx: &Context, conn: &GuildConn, job: &Job) -> hook::Result<()> {
+    let entry = AuditEntry::automatic(ctx, "Ended raid lockdown", "guild")
+        .with_reason("The lockdown expired.");
+    end_lockdown(disp, ctx, conn, job, entry)
+}
+
+fn join_hook(disp: &Dispatch, ctx: &Context, event: &Event) -> hook::Result<()> {
+    let (guild, member) = match event {
+        Event::MemberAdd { guild, member } => (*guild, *member),
+        _ => return Ok(())
+    };
+
+    let (user, created, bot) = {
+        let u = member.user.read();
+        (u.id, u.created_at().with_timezone(&Utc), u.bot)
+    };
+    if bot {
+        return Ok(());
+    }
+
+    let conn = get_cached_connection(guild)?;
+    let rconn = conn.borrow();
+    let settings = RaidSettings::load(disp, &rconn)?;
+    let now = disp.scheduler().clock().now();
+
+    if lockdown(&rconn)?.is_some() {
+        if settings.action == LockdownAction::Kick {
+            let reason = "The guild is in raid lockdown.";
+            guild.kick_with_reason(ctx, user, reason)?;
+            record(disp, ctx, &rconn, &AuditEntry::automatic(ctx, "kick", format!("<@{}>", user)).with_reason(reason));
+        }
+        return Ok(());
+    }
+
+    if settings.join_count == 0 && settings.young_join_count == 0 {
+        return Ok(());
+    }
+
+    let young = now - created < settings.young_account_age;
+    let (joins, young_joins) = RECENT_JOINS.lock()
+        .entry(guild)
+        .or_default()
+        .add(&now, young, settings.window);
+    trace!("Guild {} has seen {} joins ({} young) recently", guild, joins, young_joins);
+
+    if settings.is_raid(joins, young_joins) {
+        let cause = format!("{} joins, {} from new accounts, in the last {} seconds.",
+                            joins, young_joins, settings.window.num_seconds());
+        start_lockdown(disp, ctx, &rconn, &settings, &now, &cause)?;
+    }
+
+    Ok(())
+}
+
+/// ZST struct for processing the `raid` command
+pub struct Raid;
+
+thread_local! {
+    static PARSER: Lazy<App<'static, 'static>> = Lazy::new(
+        || {
+            App::new("raid")
+                .about("Command for checking on or ending a raid lockdown.")
+                .setting(AppSettings::SubcommandRequiredElseHelp)
+                .subcommand(
+                    SubCommand::with_name("status")
+                        .about("Shows whether the guild is in lockdown, and how many users have joined recently.")
+                )
+                .subcommand(
+                    SubCommand::with_name("end")
+                        .about("Ends the lockdown.")
+                )
+        }
+    );
+}
+
+impl Command for Raid {
+    fn invoke(&self, disp: &Dispatch, ctx: &Context, msg: &Message, args: Cow<str>) -> super::commands::Result<()> {
+        let m: ArgMatches = PARSER.with(|p| parse_app_matches("raid", args, p))?;
+
+        let guild = msg.guild_id.unwrap();
+        let conn = get_cached_connection(guild)?;
+        let rconn = conn.borrow();
+        let current = lockdown(&rconn)?;
+
+        let reply = match (m.subcommand_name(), current) {
+            (Some("status"), current) => {
+                let settings = RaidSettings::load(disp, &rconn)?;
+                let now = disp.scheduler().clock().now();
+                let (joins, young) = RECENT_JOINS.lock()
+                    .get_mut(&guild)
+                    .map_or((0, 0), |w| w.counts(&now, settings.window));
+                let state = match current {
+                    Some(job) => format!("The guild is in lockdown until {}.", job.run_at.format("%Y-%m-%d %H:%M:%S UTC")),
+                    None => "The guild is not in lockdown.".to_string()
+                };
+                format!("{}\n{} users have joined in the last {} seconds, {} from new accounts.",
+                        state, joins, settings.window.num_seconds(), young)
+            },
+            (Some("end"), Some(job)) => {
+                end_lockdown(disp, ctx, &rconn, &job, AuditEntry::new(msg.author.id, "Ended raid lockdown", "guild"))?;
+                "Ended the lockdown.".to_string()
+            },
+            (Some("end"), None) => "The guild is not in lockdown.".to_string(),
+            _ => unreachable!()
+        };
+
+        msg.channel_id.say(ctx, MessageBuilder::new()
+            .push_codeblock_safe(reply, None)
+            .build())?;
+
+        Ok(())
+    }
+
+    fn subcommand(&self, args: &str) -> Option<String> {
+        PARSER.with(|p| subcommand_name("raid", args, p))
+    }
+
+    fn subcommands(&self) -> Vec<String> {
+        PARSER.with(|p| subcommand_names(p))
+    }
+
+    fn help(&self) -> Cow<'static, str> {
+        PARSER.with(|p| help_str(p).into())
+    }
+}
+
+/// Creates the raid module.
+pub fn raid_mod() -> Module {
+    Module::with_name("raid")
+        .with_command(Raid)
+        .with_config_value(config::Value::new(
+            JOIN_COUNT_KEY,
+            "The number of joins within the join window which starts a lockdown. 0 disables the check.",
+            Kind::UINT,
+            Some(DEFAULT_JOIN_COUNT.to_string()),
+        ).with_sensitivity(true))
+        .with_config_value(config::Value::new(
+            YOUNG_JOIN_COUNT_KEY,
+            "The number of joins from new accounts within the join window which starts a lockdown. 0 disables the check.",
+            Kind::UINT,
+            Some(DEFAULT_YOUNG_JOIN_COUNT.to_string()),
+        ).with_sensitivity(true))
+        .with_config_value(config::Value::new(
+            JOIN_WINDOW_KEY,
+            "How long joins are counted over, e.g. 60 or 1m.",
+            Kind::Duration,
+            Some(DEFAULT_JOIN_WINDOW.to_string()),
+        ).with_sensitivity(true))
+        .with_config_value(config::Value::new(
+            YOUNG_ACCOUNT_AGE_KEY,
+            "Accounts created less than this long before joining count as new, e.g. 86400 or 7d, up to 365d.",
+            Kind::Duration,
+            Some(DEFAULT_YOUNG_ACCOUNT_AGE.to_string()),
+        ).with_sensitivity(true))
+        .with_config_value(config::Value::new(
+            LOCKDOWN_ACTION_KEY,
+            "What to do during a lockdown, besides alerting the modlog. One of alert, verification (require a verified phone), pause_invites (delete every invite, which must be recreated by hand afterwards) or kick (kick everyone who joins).",
+            Kind::Enum(&["alert", "verification", "pause_invites", "kick"]),
+            Some("alert".to_string()),
+        ))
+        .with_config_value(config::Value::new(
+            LOCKDOWN_DURATION_KEY,
+            "How long a lockdown lasts unless ended with `raid end`, e.g. 1800, 30m or 2h, up to 365d.",
+            Kind::Duration,
+            Some(DEFAULT_LOCKDOWN_DURATION.to_string()),
+        ))
+        .with_event_hook(join_hook)
+        .with_job_handler(END_LOCKDOWN_JOB, end_lockdown_job)
+        .with_sensitivity(true)
+        .with_dependency("modlog")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::util::test_guild_conn;
+    use crate::modules::modlog::modlog_mod;
+    use crate::util::test_context;
+    use serenity::model::id::UserId;
+    use rusqlite::NO_PARAMS;
+
+    #[test]
+    fn test_join_window() {
+        let start = Utc::now();
+        let window = Duration::seconds(60);
+        let mut w = JoinWindow::default();
+        assert_eq!(w.add(&start, true, window), (1, 1));
+        assert_eq!(w.add(&(start + Duration::seconds(30)), false, window), (2, 1));
+        assert_eq!(w.add(&(start + Duration::seconds(60)), true, window), (2, 1));
+        assert_eq!(w.counts(&(start + Duration::seconds(200)), window), (0, 0));
+    }
+
+    #[test]
+    fn test_is_raid() {
+        let mut settings = RaidSettings {
+            join_count: 10,
+            young_join_count: 0,
+            window: Duration::seconds(60),
+            young_account_age: Duration::days(7),
+            action: LockdownAction::Alert,
+            lockdown_duration: Duration::minutes(30),
+        };
+
+        assert!(!settings.is_raid(9, 9));
+        assert!(settings.is_raid(10, 0));
+
+        settings.young_join_count = 3;
+        assert!(settings.is_raid(3, 3));
+
+        settings.join_count = 0;
+        settings.young_join_count = 0;
+        assert!(!settings.is_raid(1000, 1000));
+    }
+
+    #[test]
+    fn test_lockdown() {
+        let (_dir, gconn) = test_guild_conn();
+        let id = *gconn.as_id();
+        let disp = Dispatch::new(UserId(1))
+            .with_module(modlog_mod().clear_dependencies())
+            .with_module(raid_mod().clear_dependencies());
+        let ctx = test_context();
+        // Stored values too long to be durations fall back to the default rather than panicking.
+        gconn.set_value(LOCKDOWN_DURATION_KEY, "99999999999999999").unwrap();
+        gconn.set_value(JOIN_WINDOW_KEY, "2m").unwrap();
+        let settings = RaidSettings::load(&disp, &gconn).unwrap();
+        assert_eq!(settings.lockdown_duration, Duration::seconds(DEFAULT_LOCKDOWN_DURATION));
+        assert_eq!(settings.window, Duration::minutes(2));
+        let now = Utc::now();
+        assert!(lockdown(&gconn).unwrap().is_none());
+
+        RECENT_JOINS.lock().entry(id).or_default().add(&now, true, settings.window);
+        start_lockdown(&disp, &ctx, &gconn, &settings, &now, "Test raid.").unwrap();
+        let job = lockdown(&gconn).unwrap().unwrap();
+        assert_eq!(job.run_at.timestamp(), (now + settings.lockdown_duration).timestamp());
+        assert_eq!(job.payload, "");
+
+        end_lockdown_job(&disp, &ctx, &gconn, &job).unwrap();
+        assert!(lockdown(&gconn).unwrap().is_none());
+        assert!(!RECENT_JOINS.lock().contains_key(&id));
+
+        let mut stmt = gconn.as_ref().prepare("SELECT action, target, reason FROM audit_log ORDER BY id;").unwrap();
+        let entries = stmt.query_map(NO_PARAMS, |r| Ok((r.get(0)?, r.get(1)?, r.get(2)?)))
+            .unwrap()
+            .collect::<rusqlite::Result<Vec<(String, String, String)>>>()
+            .unwrap();
+        assert_eq!(entries, vec![
+            ("Started raid lockdown".to_string(), "alert".to_string(), "Test raid.".to_string()),
+            ("Ended raid lockdown".to_string(), "guild".to_string(), "The lockdown expired.".to_string()),
+        ]);
+    }
+
+    #[test]
+    fn test_lockdown_action() {
+        for a in &[LockdownAction::Alert, LockdownAction::Verification, LockdownAction::PauseInvites, LockdownAction::Kick] {
+            assert_eq!(a.as_str().parse::<LockdownAction>(), Ok(*a));
+        }
+    }
+}
\ No newline at end of file
@@ -0,0 +1,457 @@
+//  Glimbot - A Discord anti-spam and administration bot.
+//  Copyright (C) 2020 Nick Samson
+
+//  This program is free software: you can redistribute it and/or modify
+//  it under the terms of the GNU General Public License as published by
+//  the Free Software Foundation, either version 3 of the License, or
+//  (at your option) any later version.
+
+//  This program is distributed in the hope that it will be useful,
+//  but WITHOUT ANY WARRANTY; without even the implied warranty of
+//  MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+//  GNU General Public License for more details.
+
+//  You should have received a copy of the GNU General Public License
+//  along with this program.  If not, see <https://www.gnu.org/licenses/>.
+
+//! Reaction roles. Admins bind emoji on a message to joinable roles; reacting grants the role and
+//! removing the reaction takes it away. In exclusive mode, picking one emoji drops the user's other
+//! roles (and reactions) from the same message. Role group rules apply just as they do to `me join-role`.
+//!
+//! Reactions added while Glimbot was offline are picked up when the guild becomes available again.
+//! This resync only grants roles: a missing reaction may just mean the role was joined with `me join-role`.
+
+use crate::modules::commands::Command;
+use crate::modules::{Module, hook};
+use crate::modules::hook::Event;
+use crate::modules::hook::Error::DeniedWithReason;
+use crate::modules::modlog::{record, AuditEntry};
+use crate::modules::roles::{resolve_role, join_role};
+use crate::dispatch::Dispatch;
+use crate::args::{parse_app_matches, subcommand_name, subcommand_names};
+use crate::util::{help_str, LogErrorExt};
+use crate::error::SerenityError;
+use crate::db::cache::get_cached_connection;
+use crate::db::ReactionRole;
+use serenity::prelude::Context;
+use serenity::model::channel::{Message, Reaction, ReactionType};
+use serenity::model::guild::Guild;
+use serenity::model::id::{ChannelId, EmojiId, GuildId, MessageId, RoleId, UserId};
+use serenity::utils::MessageBuilder;
+use std::borrow::Cow;
+use std::collections::HashSet;
+use std::str::FromStr;
+use once_cell::unsync::Lazy;
+use once_cell::sync::Lazy as SyncLazy;
+use regex::Regex;
+use clap::{App, AppSettings, Arg, ArgMatches, SubCommand};
+
+/// The most users Discord will return per page of reactions.
+const PAGE_SIZE: u8 = 100;
+
+static CUSTOM_EMOJI_RE: SyncLazy<Regex> = SyncLazy::new(
+    || Regex::new(r"^<(a?):(\w+):(\d+)>$").unwrap()
+);
+
+/// Parses an emoji as written in a message: either a custom emoji like `<:name:id>` or a unicode emoji.
+fn parse_reaction(s: &str) -> Option<ReactionType> {
+    let s = s.trim();
+    if let Some(c) = CUSTOM_EMOJI_RE.captures(s) {
+        return Some(ReactionType::Custom {
+            animated: !c[1].is_empty(),
+            id: EmojiId(c[3].parse().ok()?),
+            name: Some(c[2].to_string()),
+        });
+    }
+
+    if s.is_empty() || s.is_ascii() || s.contains(char::is_whitespace) {
+        None
+    } else {
+        Some(ReactionType::Unicode(s.to_string()))
+    }
+}
+
+/// Writes an emoji the way [parse_reaction] reads it.
+fn reaction_text(r: &ReactionType) -> String {
+    match r {
+        ReactionType::Custom { animated, id, name } => format!(
+            "<{}:{}:{}>",
+            if *animated { "a" } else { "" },
+            name.as_deref().unwrap_or("_"),
+            id
+        ),
+        ReactionType::Unicode(s) => s.clone(),
+        _ => String::new(),
+    }
+}
+
+/// The key an emoji is stored under. Custom emoji are keyed by id, since their names can change.
+/// Variation selectors are dropped, as clients don't agree on whether to send them.
+fn reaction_key(r: &ReactionType) -> String {
+    match r {
+        ReactionType::Custom { id, .. } => id.to_string(),
+        ReactionType::Unicode(s) => s.replace('\u{fe0f}', ""),
+        _ => String::new(),
+    }
+}
+
+fn cached_roles(ctx: &Context, guild: GuildId, user: UserId) -> Vec<RoleId> {
+    guild.to_guild_cached(ctx)
+        .and_then(|g| g.read().members.get(&user).map(|m| m.roles.clone()))
+        .unwrap_or_default()
+}
+
+fn on_reaction(ctx: &Context, r: &Reaction, added: bool) -> hook::Result<()> {
+    let guild = match r.guild_id {
+        Some(g) => g,
+        None => return Ok(())
+    };
+    if r.user_id == ctx.cache.read().user.id {
+        return Ok(());
+    }
+
+    let conn = get_cached_connection(guild)?;
+    let rconn = conn.borrow();
+    let bindings = rconn.reaction_roles(Some(r.message_id))?;
+    let key = reaction_key(&r.emoji);
+    let binding = match bindings.iter().find(|b| b.emoji == key) {
+        Some(b) => b,
+        None => return Ok(())
+    };
+    if !rconn.role_is_joinable(binding.role)? {
+        debug!("Ignoring reaction for role {} in guild {} as it is no longer joinable", binding.role, guild);
+        return Ok(());
+    }
+
+    if !added {
+        ctx.http.remove_member_role(guild.0, r.user_id.0, binding.role.0)?;
+        return Ok(());
+    }
+
+    let held = cached_roles(ctx, guild, r.user_id);
+    let mut removed = match join_role(ctx, &rconn, r.user_id, &held, binding.role) {
+        Ok(removed) => removed,
+        Err(DeniedWithReason(reason)) => {
+            debug!("Not granting role {} to user {}: {}", binding.role, r.user_id, reason);
+            remove_reaction(ctx, r, &r.emoji);
+            return Ok(());
+        },
+        Err(e) => return Err(e)
+    };
+
+    if binding.exclusive {
+        let others: Vec<RoleId> = bindings.iter()
+            .map(|b| b.role)
+            .filter(|role| *role != binding.role && held.contains(role) && !removed.contains(role))
+            .collect();
+        for role in others {
+            ctx.http.remove_member_role(guild.0, r.user_id.0, role.0)?;
+            removed.push(role);
+        }
+    }
+    for other in bindings.iter().filter(|b| b.emoji != key && removed.contains(&b.role)) {
+        if let Some(emoji) = parse_reaction(&other.emoji_text) {
+            remove_reaction(ctx, r, &emoji);
+        }
+    }
+
+    Ok(())
+}
+
+/// Removes a user's reaction from the message they reacted to. Failures are only logged, since the
+/// reaction no longer has any effect.
+fn remove_reaction(ctx: &Context, r: &Reaction, emoji: &ReactionType) {
+    if let Err(e) = ctx.http.delete_reaction(r.channel_id.0, r.message_id.0, Some(r.user_id.0), emoji) {
+        debug!("Couldn't remove reaction {} from user {}: {}", reaction_text(emoji), r.user_id, e);
+    }
+}
+
+/// Lists every user who has reacted to a binding's message with its emoji.
+fn reaction_users(ctx: &Context, binding: &ReactionRole, emoji: &ReactionType) -> serenity::Result<Vec<UserId>> {
+    let mut users = Vec::new();
+    let mut after = None;
+    loop {
+        let page = ctx.http.get_reaction_users(binding.channel.0, binding.message.0, emoji, PAGE_SIZE, after)?;
+        let done = page.len() < PAGE_SIZE as usize;
+        after = page.last().map(|u| u.id.0);
+        users.extend(page.into_iter().filter(|u| !u.bot).map(|u| u.id));
+        if done {
+            return Ok(users);
+        }
+    }
+}
+
+/// Grants roles for reactions added while Glimbot was away. Failing to grant a role to one user
+/// doesn't stop the others from getting theirs.
+fn resync(ctx: &Context, guild: &Guild) -> hook::Result<()> {
+    let conn = get_cached_connection(guild.id)?;
+    let rconn = conn.borrow();
+    let bindings = rconn.reaction_roles(None)?;
+    let mut granted = HashSet::new();
+
+    for binding in &bindings {
+        let emoji = match parse_reaction(&binding.emoji_text) {
+            Some(e) if rconn.role_is_joinable(binding.role)? => e,
+            _ => continue
+        };
+        let users = match reaction_users(ctx, binding, &emoji) {
+            Ok(u) => u,
+            Err(e) => {
+                warn!("Couldn't fetch reactions on message {} in guild {}: {}", binding.message, guild.id, e);
+                continue;
+            }
+        };
+
+        let siblings: Vec<RoleId> = bindings.iter()
+            .filter(|b| b.message == binding.message)
+            .map(|b| b.role)
+            .collect();
+        for user in users {
+            // Large guilds don't send every member up front, so fall back to asking Discord.
+            let member = match guild.member(ctx, user).map_err(SerenityError::from) {
+                Ok(m) => m,
+                Err(e) if e.not_found() => continue,
+                Err(e) => {
+                    error!("Couldn't look up member {} in guild {}: {}", user, guild.id, e);
+                    continue;
+                }
+            };
+            let taken = binding.exclusive
+                && (granted.contains(&(binding.message, user)) || member.roles.iter().any(|r| siblings.contains(r)));
+            if member.roles.contains(&binding.role) || taken {
+                continue;
+            }
+
+            let res = ctx.http.add_member_role(guild.id.0, user.0, binding.role.0);
+            res.log_error();
+            if res.is_ok() {
+                granted.insert((binding.message, user));
+            }
+        }
+    }
+
+    Ok(())
+}
+
+fn reaction_roles_hook(_disp: &Dispatch, ctx: &Context, event: &Event) -> hook::Result<()> {
+    match event {
+        Event::ReactionAdd(r) => on_reaction(ctx, r, true),
+        Event::ReactionRemove(r) => on_reaction(ctx, r, false),
+        Event::GuildCreate { guild, .. } => resync(ctx, guild),
+        _ => Ok(())
+    }
+}
+
+/// ZST struct for processing the `reactroles` command
+pub struct ReactRoles;
+
+thread_local! {
+    static REACTROLES_PARSER: Lazy<App<'static, 'static>> = Lazy::new(
+        || {
+            App::new("reactroles")
+                .about("Command for binding emoji on a message to joinable roles.")
+                .setting(AppSettings::SubcommandRequiredElseHelp)
+                .subcommand(
+                    SubCommand::with_name("bind")
+                        .about("Binds an emoji on a message to a role, and reacts with it.")
+                        .arg(Arg::with_name("channel")
+                            .value_name("CHANNEL")
+                            .help("The channel the message is in.")
+                            .takes_value(true)
+                            .required(true)
+                        )
+                        .arg(Arg::with_name("message")
+                            .value_name("MESSAGE")
+                            .help("The id of the message.")
+                            .takes_value(true)
+                            .required(true)
+                        )
+                        .arg(Arg::with_name("emoji")
+                            .value_name("EMOJI")
+                            .help("The emoji users react with.")
+                            .takes_value(true)
+                            .required(true)
+                        )
+                        .arg(Arg::with_name("role")
+                            .value_name("ROLE")
+                            .help("The role to grant. It must be joinable.")
+                            .takes_value(true)
+                            .required(true)
+                        )
+                )
+                .subcommand(
+                    SubCommand::with_name("unbind")
+                        .about("Unbinds an emoji on a message.")
+                        .arg(Arg::with_name("message")
+                            .value_name("MESSAGE")
+                            .help("The id of the message.")
+                            .takes_value(true)
+                            .required(true)
+                        )
+                        .arg(Arg::with_name("emoji")
+                            .value_name("EMOJI")
+                            .help("The emoji to unbind.")
+                            .takes_value(true)
+                            .required(true)
+                        )
+                )
+                .subcommand(
+                    SubCommand::with_name("mode")
+                        .about("Sets whether users may hold one or several of a message's roles.")
+                        .arg(Arg::with_name("message")
+                            .value_name("MESSAGE")
+                            .help("The id of the message.")
+                            .takes_value(true)
+                            .required(true)
+                        )
+                        .arg(Arg::with_name("mode")
+                            .value_name("MODE")
+                            .help("exclusive allows one role at a time, multi allows any number.")
+                            .possible_values(&["exclusive", "multi"])
+                            .takes_value(true)
+                            .required(true)
+                        )
+                )
+                .subcommand(
+                    SubCommand::with_name("list")
+                        .about("Lists all reaction roles.")
+                )
+        }
+    );
+}
+
+fn parse_message_id(s: &str) -> Result<MessageId, hook::Error> {
+    s.parse::<u64>()
+        .map(MessageId)
+        .map_err(|_| DeniedWithReason("Invalid message id.".into()))
+}
+
+fn parse_emoji_arg(s: &str) -> Result<ReactionType, hook::Error> {
+    parse_reaction(s).ok_or_else(|| DeniedWithReason("That doesn't look like an emoji.".into()))
+}
+
+impl Command for ReactRoles {
+    fn invoke(&self, disp: &Dispatch, ctx: &Context, msg: &Message, args: Cow<str>) -> super::commands::Result<()> {
+        let m: ArgMatches = REACTROLES_PARSER.with(|p| parse_app_matches("reactroles", args, p))?;
+
+        let guild = msg.guild(ctx).unwrap();
+        let rg = guild.read();
+        let conn = get_cached_connection(rg.id)?;
+        let rconn = conn.borrow();
+
+        let reply = match m.subcommand() {
+            ("bind", Some(m)) => {
+                let channel = ChannelId::from_str(m.value_of("channel").unwrap())
+                    .ok()
+                    .filter(|c| rg.channels.contains_key(c))
+                    .ok_or_else(|| DeniedWithReason("No such channel.".into()))?;
+                let message = parse_message_id(m.value_of("message").unwrap())?;
+                let emoji = parse_emoji_arg(m.value_of("emoji").unwrap())?;
+                let role = resolve_role(&rg, m.value_of("role").unwrap())?;
+                if !rconn.role_is_joinable(role.id)? {
+                    return Err(DeniedWithReason("Only joinable roles can be bound to reactions.".into()).into());
+                }
+
+                if ctx.http.get_message(channel.0, message.0).is_err() {
+                    return Err(DeniedWithReason("Couldn't find that message.".into()).into());
+                }
+                if ctx.http.create_reaction(channel.0, message.0, &emoji).is_err() {
+                    return Err(DeniedWithReason("Couldn't react with that emoji.".into()).into());
+                }
+
+                let text = reaction_text(&emoji);
+                rconn.bind_reaction_role(channel, message, &reaction_key(&emoji), &text, role.id)?;
+                record(disp, ctx, &rconn, &AuditEntry::new(msg.author.id, "Bound reaction role", format!("<@&{}>", role.id))
+                    .with_reason(format!("{} on message {}", text, message)));
+                format!("Reacting with {} on message {} now grants {}.", text, message, role.name)
+            },
+            ("unbind", Some(m)) => {
+                let message = parse_message_id(m.value_of("message").unwrap())?;
+                let emoji = parse_emoji_arg(m.value_of("emoji").unwrap())?;
+                if rconn.unbind_reaction_role(message, &reaction_key(&emoji))? {
+                    record(disp, ctx, &rconn, &AuditEntry::new(msg.author.id, "Unbound reaction role", format!("message {}", message))
+                        .with_reason(reaction_text(&emoji)));
+                    "Unbound the emoji.".to_string()
+                } else {
+                    "That emoji isn't bound on that message.".to_string()
+                }
+            },
+            ("mode", Some(m)) => {
+                let message = parse_message_id(m.value_of("message").unwrap())?;
+                let mode = m.value_of("mode").unwrap();
+                if rconn.set_reaction_roles_exclusive(message, mode == "exclusive")? {
+                    format!("Set message {} to {} mode.", message, mode)
+                } else {
+                    "That message has no reaction roles.".to_string()
+                }
+            },
+            ("list", Some(_)) => {
+                let bindings = rconn.reaction_roles(None)?;
+                if bindings.is_empty() {
+                    "No reaction roles are bound.".to_string()
+                } else {
+                    bindings.iter()
+                        .map(|b| {
+                            let role = rg.roles.get(&b.role).map_or_else(|| b.role.to_string(), |r| r.name.clone());
+                            let mode = if b.exclusive { "exclusive" } else { "multi" };
+                            format!("{} in #{} ({}): {} -> {}", b.message, b.channel, mode, b.emoji_text, role)
+                        })
+                        .collect::<Vec<_>>()
+                        .join("\n")
+                }
+            },
+            _ => unreachable!()
+        };
+
+        msg.channel_id.say(ctx, MessageBuilder::new()
+            .push_codeblock_safe(reply, None)
+            .build())?;
+
+        Ok(())
+    }
+
+    fn subcommand(&self, args: &str) -> Option<String> {
+        REACTROLES_PARSER.with(|p| subcommand_name("reactroles", args, p))
+    }
+
+    fn subcommands(&self) -> Vec<String> {
+        REACTROLES_PARSER.with(|p| subcommand_names(p))
+    }
+
+    fn help(&self) -> Cow<'static, str> {
+        REACTROLES_PARSER.with(|p| help_str(p).into())
+    }
+}
+
+/// Creates the reaction roles module.
+pub fn reaction_roles_mod() -> Module {
+    Module::with_name("reactroles")
+        .with_command(ReactRoles)
+        .with_alias("rr")
+        .with_event_hook(reaction_roles_hook)
+        .with_sensitivity(true)
+        .with_dependency("roles")
+        .with_dependency("modlog")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_reaction() {
+        let custom = parse_reaction("<a:party:1234>").unwrap();
+        assert_eq!(reaction_key(&custom), "1234");
+        assert_eq!(reaction_text(&custom), "<a:party:1234>");
+        assert_eq!(reaction_key(&parse_reaction(" <:pear:55> ").unwrap()), "55");
+
+        let heart = parse_reaction("\u{2764}\u{fe0f}").unwrap();
+        assert_eq!(reaction_key(&heart), "\u{2764}");
+        assert_eq!(reaction_text(&heart), "\u{2764}\u{fe0f}");
+
+        assert!(parse_reaction("pear").is_none());
+        assert!(parse_reaction("<:pear:abc>").is_none());
+        assert!(parse_reaction("").is_none());
+        assert!(parse_reaction("\u{1f34e} \u{1f350}").is_none());
+    }
+}
\ No newline at end of file
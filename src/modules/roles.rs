@@ -15,30 +15,37 @@
 //  along with this program.  If not, see <https://www.gnu.org/licenses/>.
 
 //! Module for managing roles and ensuring users can't run restricted (admin-only) commands.
+//!
+//! Permissions are tiered: the guild owner outranks admins, who outrank moderators, who outrank everyone else.
+//! Admin and moderator tiers are granted by assigning a tier to one or more staff roles.
+//!
+//! Joinable roles may be sorted into named groups. A group can be exclusive, so joining one of its
+//! roles leaves the others, or capped at a maximum number of roles held at once.
 
 use crate::modules::Module;
-use crate::modules::config;
 use crate::dispatch::Dispatch;
+use crate::modules::overrides::resolve_override;
 use serenity::prelude::Context;
-use serenity::model::prelude::{Message, Role};
+use serenity::model::prelude::{Message, Role, User};
 use std::borrow::Cow;
-use serenity::model::id::{UserId, RoleId};
+use serenity::model::id::{UserId, RoleId, GuildId, ChannelId};
 use crate::db::cache::get_cached_connection;
 use crate::modules::hook::Error::{DeniedWithReason, NeedRole};
 use once_cell::unsync::Lazy;
 use clap::{App, Arg, AppSettings, SubCommand, ArgMatches};
-use crate::error::{AnyError, BotError};
+use crate::error::{AnyError, BotError, SerenityError};
 use std::str::{FromStr, ParseBoolError};
+use std::num::ParseIntError;
 use crate::modules::commands::Command;
-use crate::args::parse_app_matches;
+use crate::args::{parse_app_matches, subcommand_name, subcommand_names};
 use crate::modules::config::{fallible_validator};
 use serenity::utils::MessageBuilder;
-use std::sync::Arc;
-use crate::db::GuildConn;
+use crate::db::{GuildConn, Tier};
 use serenity::model::guild::Guild;
 use crate::util::help_str;
-
-static ADMIN_KEY: &'static str = "admin_role";
+use crate::modules::modlog::{record, AuditEntry};
+use crate::modules::gate::is_gate_role;
+use crate::modules::spam::is_mute_role;
 
 /// Errors related to role resolution.
 #[derive(thiserror::Error, Debug)]
@@ -46,6 +53,12 @@ pub enum Error {
     /// There was no such role in this context.
     #[error("No such role could be identified in this guild: {0}")]
     NoSuchRole(Cow<'static, str>),
+    /// There was no such user in this context.
+    #[error("No such user could be identified in this guild: {0}")]
+    NoSuchUser(String),
+    /// The string was not the name of a permission tier.
+    #[error("No such permission tier (expected everyone, moderator, admin or owner): {0}")]
+    NoSuchTier(String),
     /// Some other error occurred
     #[error("{0}")]
     Other(#[from] AnyError)
@@ -53,7 +66,7 @@ pub enum Error {
 
 impl BotError for Error {
     fn is_user_error(&self) -> bool {
-        matches!(self, Error::NoSuchRole(_))
+        matches!(self, Error::NoSuchRole(_) | Error::NoSuchUser(_) | Error::NoSuchTier(_))
     }
 }
 
@@ -63,6 +76,20 @@ impl From<Error> for super::commands::Error {
     }
 }
 
+impl FromStr for Tier {
+    type Err = Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_lowercase().as_str() {
+            "everyone" => Ok(Tier::Everyone),
+            "moderator" | "mod" => Ok(Tier::Moderator),
+            "admin" => Ok(Tier::Admin),
+            "owner" => Ok(Tier::Owner),
+            _ => Err(Error::NoSuchTier(s.to_string()))
+        }
+    }
+}
+
 /// Resolves a string into a role, given the guild to resolve it in.
 pub fn resolve_role(guild: &Guild, s: impl AsRef<str>) -> Result<&Role, Error> {
     let parsed = s.as_ref().parse::<RoleId>();
@@ -75,47 +102,198 @@ pub fn resolve_role(guild: &Guild, s: impl AsRef<str>) -> Result<&Role, Error> {
     Ok(real_role)
 }
 
-fn role_hook<'a, 'b, 'c, 'd>(disp: &'a Dispatch, ctx: &'b Context, msg: &'c Message, name: Cow<'d, str>) -> super::hook::Result<Cow<'d, str>> {
-    trace!("Applying role hook.");
-    let guild = msg.guild_id.unwrap();
+/// Resolves a string into a user, given the guild to resolve it in. Mentions and raw ids are
+/// accepted even if the user has left the guild; names must belong to a current member.
+pub fn resolve_user(guild: &Guild, s: impl AsRef<str>) -> Result<UserId, Error> {
+    if let Ok(id) = UserId::from_str(s.as_ref()) {
+        return Ok(id);
+    }
+
+    guild.member_named(s.as_ref())
+        .map(|m| m.user.read().id)
+        .ok_or_else(|| Error::NoSuchUser(s.as_ref().to_string()))
+}
+
+/// Longest allowed role group name.
+const MAX_GROUP_NAME_LEN: usize = 32;
+
+/// Checks that a role group name is usable, in the form clap validators expect.
+pub fn valid_group_name(s: String) -> Result<(), String> {
+    if s.is_empty() || s.chars().count() > MAX_GROUP_NAME_LEN {
+        Err(format!("Group names must be between 1 and {} characters long.", MAX_GROUP_NAME_LEN))
+    } else if !s.chars().all(|c| c.is_alphanumeric() || c == '-' || c == '_') {
+        Err("Group names may only contain letters, numbers, dashes and underscores.".to_string())
+    } else {
+        Ok(())
+    }
+}
+
+/// Gives a user a joinable role, following the rules of the role's group. In an exclusive group the
+/// user's other roles from the group are removed; in a capped group, joining past the cap is denied.
+/// `held` is the set of roles the user currently has. Returns the roles that were removed.
+pub fn join_role(ctx: &Context, conn: &GuildConn, user: UserId, held: &[RoleId], role: RoleId) -> super::hook::Result<Vec<RoleId>> {
+    let guild = *conn.as_id();
+    let group = conn.role_groups()?.into_iter().find(|g| g.roles.contains(&role));
+
+    let mut removed = Vec::new();
+    if let Some(g) = group {
+        let others: Vec<RoleId> = g.roles.iter()
+            .copied()
+            .filter(|r| *r != role && held.contains(r))
+            .collect();
+        if g.exclusive {
+            removed = others;
+        } else if let Some(max) = g.max {
+            if others.len() >= max as usize && !held.contains(&role) {
+                return Err(DeniedWithReason(format!("You may only hold {} role(s) from the {} group.", max, g.name).into()));
+            }
+        }
+    }
+
+    ctx.http.add_member_role(guild.0, user.0, role.0)?;
+    for r in &removed {
+        ctx.http.remove_member_role(guild.0, user.0, r.0)?;
+    }
+    Ok(removed)
+}
 
-    let owner: UserId = ctx.cache.read().guild(guild).unwrap().read().owner_id;
-    let author = msg.author.id;
-    if owner == author {
-        trace!("User is server owner.");
-        return Ok(name);
+/// Determines the highest permission tier the user holds in the guild. If the guild isn't cached,
+/// its owner can't be recognized and only staff roles count.
+pub fn user_tier(ctx: &Context, conn: &GuildConn, guild: GuildId, user: UserId, roles: &[RoleId]) -> crate::db::Result<Tier> {
+    let owner = ctx.cache.read().guild(guild).map(|g| g.read().owner_id);
+    if owner == Some(user) {
+        return Ok(Tier::Owner);
     }
 
+    staff_tier(conn, roles)
+}
+
+/// Determines the highest permission tier a member of the guild holds.
+fn member_tier(ctx: &Context, conn: &GuildConn, guild: &Guild, user: UserId) -> super::commands::Result<Tier> {
+    let roles = guild.member(ctx, user)?.roles;
+    Ok(user_tier(ctx, conn, guild.id, user, &roles)?)
+}
+
+/// Checks whether the role grants a staff tier. Staff roles must never be handed out automatically
+/// or joined by users themselves.
+pub fn is_staff_role(conn: &GuildConn, role: RoleId) -> crate::db::Result<bool> {
+    Ok(staff_tier(conn, &[role])? > Tier::Everyone)
+}
+
+/// Determines the highest tier granted by the given roles through the guild's staff roles.
+fn staff_tier(conn: &GuildConn, roles: &[RoleId]) -> crate::db::Result<Tier> {
+    let tier = conn.staff_roles()?
+        .into_iter()
+        .filter(|(r, _)| roles.contains(r))
+        .map(|(_, t)| t)
+        .max()
+        .unwrap_or(Tier::Everyone);
+    Ok(tier)
+}
+
+/// Checks that the actor may punish the target. Nobody may punish the guild owner or Glimbot itself,
+/// and everyone else may only be punished by someone of a higher tier. Users who aren't members of
+/// the guild have no tier.
+pub fn check_outranks(ctx: &Context, conn: &GuildConn, guild: &Guild, actor: UserId, target: UserId) -> super::hook::Result<()> {
+    let me = ctx.cache.read().user.id;
+    if target == guild.owner_id || target == me {
+        return Err(DeniedWithReason("That user cannot be punished.".into()));
+    }
+
+    let tier_of = |user: UserId| -> super::hook::Result<Tier> {
+        if user == guild.owner_id {
+            return Ok(Tier::Owner);
+        }
+
+        let roles = match guild.members.get(&user) {
+            Some(m) => m.roles.clone(),
+            None => match ctx.http.get_member(guild.id.0, user.0).map_err(SerenityError::from) {
+                Ok(m) => m.roles,
+                Err(e) if e.not_found() => Vec::new(),
+                Err(e) => return Err(e.into())
+            }
+        };
+        Ok(staff_tier(conn, &roles)?)
+    };
+
+    if tier_of(actor)? <= tier_of(target)? {
+        return Err(DeniedWithReason("You can only punish users below your own tier.".into()));
+    }
+
+    Ok(())
+}
+
+/// Checks whether the user may run the given command in the guild, following the same rules as the
+/// role hook: the guild owner and admins may run anything. For everyone else, the most specific
+/// [permission override][crate::modules::overrides] applies; without one, users may only run commands
+/// whose required tier they hold. Restricted commands require admin.
+pub fn check_permission(disp: &Dispatch, ctx: &Context, guild: GuildId, channel: Option<ChannelId>, user: &User, command: &str, subcommand: Option<&str>) -> super::hook::Result<()> {
     let conn = get_cached_connection(guild)?;
     let rconn = conn.as_ref().borrow();
 
-    let admin_role: RoleId = disp.get_config(&rconn, ADMIN_KEY)?.parse::<RoleId>().unwrap().into();
-    if msg.author.has_role(ctx, guild, admin_role)? {
-        trace!("User is admin.");
-        return Ok(name);
+    let member = guild.member(ctx, user.id)?;
+    let tier = user_tier(ctx, &rconn, guild, user.id, &member.roles)?;
+    if tier >= Tier::Admin {
+        trace!("User is {}.", tier);
+        return Ok(());
+    }
+
+    let overrides = rconn.overrides(Some(command))?;
+    match resolve_override(&overrides, guild, user.id, &member.roles, channel, subcommand) {
+        Some(true) => {
+            trace!("Command allowed by override.");
+            return Ok(());
+        },
+        Some(false) => {
+            trace!("Command denied by override.");
+            return Err(DeniedWithReason("You have been denied access to that command.".into()));
+        },
+        None => ()
+    }
+
+    // Now we need to see which tier the desired command requires.
+    let module = disp.modules().get(command).ok_or(DeniedWithReason("No such command.".into()))?;
+    let required = if rconn.command_is_restricted(command)? {
+        module.required_tier().max(Tier::Admin)
+    } else {
+        module.required_tier()
+    };
+
+    if tier >= required {
+        trace!("User is {}, command requires {}.", tier, required);
+        return Ok(());
+    }
+
+    trace!("Command requires {} and user is {}.", required, tier);
+    if required == Tier::Owner {
+        return Err(DeniedWithReason("Only the server owner may do that.".into()));
     }
 
-    // Now we need to see if the desired command is sensitive or not.
-    let module = disp.modules().get(name.as_ref()).ok_or(DeniedWithReason("No such command.".into()))?;
-    if module.sensitive || {
-        rconn.as_ref().query_row(
-            "SELECT ? IN restricted_commands;",
-            params![name.as_ref()],
-            |r| r.get(0),
-        ).map_err(crate::db::DatabaseError::SQLError)?
-    } {
-        trace!("Command is sensitive and user is not admin or owner.");
-        let full_guild = ctx.cache.read().guild(guild).unwrap();
-        let role_name = full_guild.read().roles.get(&admin_role).ok_or(DeniedWithReason("Not an admin or admin role outdated.".into()))?.name.clone();
-
-        let needed_role = vec![role_name];
-        Err(NeedRole(needed_role))
+    let full_guild = ctx.cache.read().guild(guild).unwrap();
+    let rg = full_guild.read();
+    let needed_roles: Vec<String> = rconn.staff_roles()?
+        .into_iter()
+        .filter(|(_, t)| *t >= required)
+        .filter_map(|(r, _)| rg.roles.get(&r).map(|r| r.name.clone()))
+        .collect();
+
+    if needed_roles.is_empty() {
+        Err(DeniedWithReason(format!("You must be {} to do that, and no staff roles are set up.", required).into()))
     } else {
-        trace!("Command not sensitive.");
-        Ok(name)
+        Err(NeedRole(needed_roles))
     }
 }
 
+fn role_hook<'d>(disp: &Dispatch, ctx: &Context, msg: &Message, name: Cow<'d, str>) -> super::hook::Result<Cow<'d, str>> {
+    trace!("Applying role hook.");
+    let guild = msg.guild_id.unwrap();
+    let args = disp.parse_command(ctx, msg).ok().flatten().map(|i| i.args);
+    let subcommand = disp.resolve_command(name.as_ref())
+        .and_then(|c| args.and_then(|a| c.subcommand(&a)));
+    check_permission(disp, ctx, guild, Some(msg.channel_id), &msg.author, name.as_ref(), subcommand.as_deref())?;
+    Ok(name)
+}
+
 thread_local! {
 static PARSER: Lazy<App<'static, 'static>> = Lazy::new(
     || {
@@ -142,6 +320,15 @@ static PARSER: Lazy<App<'static, 'static>> = Lazy::new(
                 .arg(user_id.clone())
                 .about("Removes a role from a user.")
             )
+            .subcommand(SubCommand::with_name("set-tier")
+                .arg(Arg::with_name("tier")
+                    .validator(fallible_validator::<Tier, Error>)
+                    .help("The permission tier to grant members of the role: everyone, moderator or admin. Everyone removes the role from staff.")
+                    .value_name("TIER")
+                    .required(true)
+                )
+                .about("Sets the permission tier granted by a staff role.")
+            )
             .subcommand(
             SubCommand::with_name("set-joinable")
                 .arg(Arg::with_name("joinable")
@@ -151,7 +338,31 @@ static PARSER: Lazy<App<'static, 'static>> = Lazy::new(
                     .default_value("true")
                     .required(false)
                     )
-                    .about("Sets a role as user joinable.")
+                .arg(Arg::with_name("group")
+                    .long("group")
+                    .short("g")
+                    .value_name("GROUP")
+                    .help("The role group to place a joinable role in, such as pronouns or region.")
+                    .validator(valid_group_name)
+                    .takes_value(true)
+                    )
+                .arg(Arg::with_name("exclusive")
+                    .long("exclusive")
+                    .value_name("EXCLUSIVE")
+                    .help("Whether joining a role in the group leaves the group's other roles.")
+                    .validator(fallible_validator::<bool, ParseBoolError>)
+                    .takes_value(true)
+                    .requires("group")
+                    )
+                .arg(Arg::with_name("max")
+                    .long("max")
+                    .value_name("MAX")
+                    .help("The most roles from the group a user may hold at once. 0 removes the cap.")
+                    .validator(fallible_validator::<u32, ParseIntError>)
+                    .takes_value(true)
+                    .requires("group")
+                    )
+                    .about("Sets a role as user joinable, optionally within a role group.")
             )
     }
 );
@@ -161,7 +372,7 @@ static PARSER: Lazy<App<'static, 'static>> = Lazy::new(
 pub struct Roles;
 
 impl Command for Roles {
-    fn invoke(&self, _disp: &Dispatch, ctx: &Context, msg: &Message, args: Cow<str>) -> super::commands::Result<()> {
+    fn invoke(&self, disp: &Dispatch, ctx: &Context, msg: &Message, args: Cow<str>) -> super::commands::Result<()> {
         let m: ArgMatches = PARSER.with(|p|
             parse_app_matches("roles", args, &p)
         )?;
@@ -175,42 +386,99 @@ impl Command for Roles {
             real_role.id
         };
 
+        let c = get_cached_connection(rg.id)?;
+        let cr = c.borrow();
+
         let reply = match m.subcommand() {
             ("set-joinable", Some(m)) => {
                 let joinable = m.value_of("joinable").unwrap().parse::<bool>().unwrap();
-                let c = get_cached_connection(guild.read().id)?;
-                let cr = c.borrow();
-                let sql = if joinable {
-                    "INSERT OR IGNORE INTO joinable_roles VALUES (?);"
+                let group = m.value_of("group").map(str::to_lowercase);
+                if !joinable && group.is_some() {
+                    return Err(DeniedWithReason("Only joinable roles can be placed in a group.".into()).into());
+                }
+
+                if joinable && is_gate_role(disp, &cr, role_id)? {
+                    return Err(DeniedWithReason("The gate's quarantine role can't be made joinable.".into()).into());
+                }
+
+                if joinable && is_staff_role(&cr, role_id)? {
+                    return Err(DeniedWithReason("Staff roles can't be made joinable.".into()).into());
+                }
+
+                let mut entry = if joinable {
+                    cr.set_role_joinable(role_id, group.as_deref())?;
+                    AuditEntry::new(msg.author.id, "Made role joinable", format!("<@&{}>", role_id))
                 } else {
-                    "DELETE FROM joinable_roles WHERE role = ?;"
+                    cr.remove_joinable_role(role_id)?;
+                    AuditEntry::new(msg.author.id, "Made role unjoinable", format!("<@&{}>", role_id))
                 };
+                if let Some(name) = &group {
+                    let current = cr.role_groups()?.into_iter().find(|g| &g.name == name);
+                    let exclusive = m.value_of("exclusive")
+                        .map(|e| e.parse::<bool>().unwrap())
+                        .or_else(|| current.as_ref().map(|g| g.exclusive))
+                        .unwrap_or(false);
+                    let max = match m.value_of("max").map(|n| n.parse::<u32>().unwrap()) {
+                        Some(0) => None,
+                        Some(n) => Some(n),
+                        None => current.and_then(|g| g.max)
+                    };
+                    cr.set_role_group_options(name, exclusive, max)?;
+
+                    let mode = match (exclusive, max) {
+                        (true, _) => "exclusive".to_string(),
+                        (false, Some(n)) => format!("up to {}", n),
+                        (false, None) => "unlimited".to_string(),
+                    };
+                    entry = entry.with_reason(format!("Group {} ({})", name, mode));
+                }
 
-                cr.as_ref().execute(
-                    sql,
-                    params![role_id.0 as i64]
-                ).map_err(crate::db::DatabaseError::SQLError)?;
-
+                record(disp, ctx, &cr, &entry);
                 "Role updated."
             },
+            ("set-tier", Some(m)) => {
+                let tier = m.value_of("tier").unwrap().parse::<Tier>()?;
+                if tier == Tier::Owner {
+                    return Err(DeniedWithReason("The owner tier cannot be granted to a role.".into()).into());
+                }
+
+                let actor = member_tier(ctx, &cr, &rg, msg.author.id)?;
+                if actor <= tier || actor <= staff_tier(&cr, &[role_id])? {
+                    return Err(DeniedWithReason("You can only manage roles below your own tier.".into()).into());
+                }
+
+                if tier > Tier::Everyone && (cr.role_is_joinable(role_id)? || is_gate_role(disp, &cr, role_id)? || is_mute_role(disp, &cr, role_id)?) {
+                    return Err(DeniedWithReason("Joinable, quarantine and mute roles can't be staff roles.".into()).into());
+                }
+
+                cr.set_role_tier(role_id, tier)?;
+
+                record(disp, ctx, &cr, &AuditEntry::new(msg.author.id,
+                                                        format!("Set role tier to {}", tier),
+                                                        format!("<@&{}>", role_id)));
+                "Role tier updated."
+            },
             (s, Some(m)) => {
-                let user = m.value_of("user-id").unwrap();
-                let parsed = UserId::from_str(user);
-                let real_user_id = if let Ok(id) = parsed {
-                    id
-                } else {
-                    rg.member_named(user).ok_or_else(|| DeniedWithReason("No such user.".into()))?.user.read().id
-                };
+                let real_user_id = resolve_user(&rg, m.value_of("user-id").unwrap())?;
+
+                // Staff roles can only be handed out by someone above them, and never to a peer or superior.
+                if member_tier(ctx, &cr, &rg, msg.author.id)? <= staff_tier(&cr, &[role_id])? {
+                    return Err(DeniedWithReason("You can only manage roles below your own tier.".into()).into());
+                }
+                check_outranks(ctx, &cr, &rg, msg.author.id, real_user_id)?;
 
                 let adding = s == "add-user";
                 let mut member = rg.member(ctx, real_user_id)?;
-                if adding {
+                let (action, reply) = if adding {
                     member.add_role(ctx, role_id)?;
-                    "Added role to user."
+                    (format!("Added role <@&{}>", role_id), "Added role to user.")
                 } else {
                     member.remove_role(ctx, role_id)?;
-                    "Removed role from user."
-                }
+                    (format!("Removed role <@&{}>", role_id), "Removed role from user.")
+                };
+
+                record(disp, ctx, &cr, &AuditEntry::new(msg.author.id, action, format!("<@{}>", real_user_id)));
+                reply
             },
             _ => unreachable!()
         };
@@ -224,25 +492,16 @@ impl Command for Roles {
         Ok(())
     }
 
-    fn help(&self) -> Cow<'static, str> {
-        PARSER.with(|p| help_str(&p).into())
+    fn subcommand(&self, args: &str) -> Option<String> {
+        PARSER.with(|p| subcommand_name("roles", args, p))
     }
-}
 
-/// Checks the validity of a numerical role id
-pub fn valid_role(_disp: &Dispatch, ctx: &Context, conn: &GuildConn, s: &str) -> bool {
-    let id = *conn.as_id();
-    let guild = ctx.cache.read().guild(id);
-    if let Some(g) = guild {
-        let rg = g.read();
-        let parsed = RoleId::from_str(s);
-        if let Ok(id) = parsed {
-            rg.roles.get(&id).is_some()
-        } else {
-            false
-        }
-    } else {
-        false
+    fn subcommands(&self) -> Vec<String> {
+        PARSER.with(|p| subcommand_names(p))
+    }
+
+    fn help(&self) -> Cow<'static, str> {
+        PARSER.with(|p| help_str(&p).into())
     }
 }
 
@@ -251,12 +510,6 @@ pub fn roles_module() -> Module {
     Module::with_name("roles")
         .with_sensitivity(true)
         .with_dependency("config")
-        .with_config_value(config::Value::new(
-            ADMIN_KEY,
-            "The role which should be allowed to run restricted commands.",
-            Arc::new(valid_role),
-            Option::<String>::None,
-        ))
         .with_command_hook(role_hook)
         .with_command(Roles)
 }
\ No newline at end of file
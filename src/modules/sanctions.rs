@@ -0,0 +1,153 @@
+//  Glimbot - A Discord anti-spam and administration bot.
+//  Copyright (C) 2020 Nick Samson
+
+//  This program is free software: you can redistribute it and/or modify
+//  it under the terms of the GNU General Public License as published by
+//  the Free Software Foundation, either version 3 of the License, or
+//  (at your option) any later version.
+
+//  This program is distributed in the hope that it will be useful,
+//  but WITHOUT ANY WARRANTY; without even the implied warranty of
+//  MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+//  GNU General Public License for more details.
+
+//  You should have received a copy of the GNU General Public License
+//  along with this program.  If not, see <https://www.gnu.org/licenses/>.
+
+//! Modules for muting and temporarily banning users by hand. Both punishments are lifted
+//! automatically once their duration has passed, even across restarts.
+
+use crate::modules::commands::Command;
+use crate::modules::Module;
+use crate::modules::hook::Error::DeniedWithReason;
+use crate::modules::modlog::{record, AuditEntry};
+use crate::modules::punishment::{Action, Target, parse_duration};
+use crate::modules::roles::{resolve_user, check_outranks};
+use crate::db::Tier;
+use crate::modules::spam::mute_role;
+use crate::dispatch::Dispatch;
+use crate::args::parse_app_matches;
+use crate::util::help_str;
+use crate::db::cache::get_cached_connection;
+use serenity::prelude::Context;
+use serenity::model::channel::Message;
+use serenity::utils::MessageBuilder;
+use std::borrow::Cow;
+use once_cell::unsync::Lazy;
+use clap::{App, Arg, ArgMatches};
+
+fn parser(name: &'static str, about: &'static str) -> App<'static, 'static> {
+    App::new(name)
+        .about(about)
+        .arg(Arg::with_name("user")
+            .value_name("USER")
+            .help("The user to punish.")
+            .takes_value(true)
+            .required(true)
+        )
+        .arg(Arg::with_name("duration")
+            .value_name("DURATION")
+            .help("How long the punishment lasts, e.g. 90, 45s, 10m, 2h30m or 7d, up to 365d. Bare numbers are seconds.")
+            .takes_value(true)
+            .required(true)
+        )
+        .arg(Arg::with_name("reason")
+            .value_name("REASON")
+            .help("Why the user is being punished.")
+            .takes_value(true)
+            .multiple(true)
+        )
+}
+
+fn punish(disp: &Dispatch, ctx: &Context, msg: &Message, m: &ArgMatches, temp_ban: bool) -> super::commands::Result<()> {
+    let duration = m.value_of("duration").and_then(parse_duration)
+        .ok_or_else(|| DeniedWithReason("Durations look like 90, 45s, 10m, 2h30m or 7d, and may be at most 365d.".into()))?;
+    let reason = m.values_of("reason").map_or_else(String::new, |v| v.collect::<Vec<_>>().join(" "));
+
+    let guild = msg.guild(ctx).unwrap();
+    let rg = guild.read();
+    let user = resolve_user(&rg, m.value_of("user").unwrap())?;
+    if user == msg.author.id {
+        return Err(DeniedWithReason("You cannot punish yourself.".into()).into());
+    }
+
+    let conn = get_cached_connection(rg.id)?;
+    let rconn = conn.borrow();
+    check_outranks(ctx, &rconn, &rg, msg.author.id, user)?;
+
+    let action = if temp_ban {
+        Action::TempBan { duration }
+    } else {
+        let role = mute_role(disp, &rconn)?
+            .ok_or_else(|| DeniedWithReason("No mute role is set. Set one with `config set mute_role <ROLE>`.".into()))?;
+        Action::Mute { role, duration }
+    };
+
+    let target = Target { guild: rg.id, user, channel: msg.channel_id, message: None };
+    action.apply(ctx, &rconn, &target, &reason)?;
+    record(disp, ctx, &rconn, &AuditEntry::new(msg.author.id, action.to_string(), format!("<@{}>", user))
+        .with_reason(&reason));
+
+    msg.channel_id.say(ctx, MessageBuilder::new()
+        .push_codeblock_safe(format!("Applied {}.", action), None)
+        .build())?;
+
+    Ok(())
+}
+
+/// ZST struct for processing the `mute` command
+pub struct Mute;
+
+thread_local! {
+    static MUTE_PARSER: Lazy<App<'static, 'static>> = Lazy::new(
+        || parser("mute", "Gives a user the mute role, removing it once the duration has passed.")
+    );
+}
+
+impl Command for Mute {
+    fn invoke(&self, disp: &Dispatch, ctx: &Context, msg: &Message, args: Cow<str>) -> super::commands::Result<()> {
+        let m: ArgMatches = MUTE_PARSER.with(|p| parse_app_matches("mute", args, p))?;
+        punish(disp, ctx, msg, &m, false)
+    }
+
+    fn help(&self) -> Cow<'static, str> {
+        MUTE_PARSER.with(|p| help_str(p).into())
+    }
+}
+
+/// ZST struct for processing the `tempban` command
+pub struct TempBan;
+
+thread_local! {
+    static TEMPBAN_PARSER: Lazy<App<'static, 'static>> = Lazy::new(
+        || parser("tempban", "Bans a user, unbanning them once the duration has passed.")
+    );
+}
+
+impl Command for TempBan {
+    fn invoke(&self, disp: &Dispatch, ctx: &Context, msg: &Message, args: Cow<str>) -> super::commands::Result<()> {
+        let m: ArgMatches = TEMPBAN_PARSER.with(|p| parse_app_matches("tempban", args, p))?;
+        punish(disp, ctx, msg, &m, true)
+    }
+
+    fn help(&self) -> Cow<'static, str> {
+        TEMPBAN_PARSER.with(|p| help_str(p).into())
+    }
+}
+
+/// Creates the mute module.
+pub fn mute_mod() -> Module {
+    Module::with_name("mute")
+        .with_command(Mute)
+        .with_required_tier(Tier::Moderator)
+        .with_dependency("spam")
+        .with_dependency("punishment")
+}
+
+/// Creates the tempban module.
+pub fn tempban_mod() -> Module {
+    Module::with_name("tempban")
+        .with_command(TempBan)
+        .with_required_tier(Tier::Admin)
+        .with_dependency("punishment")
+}
@@ -0,0 +1,704 @@
+//  Glimbot - A Discord anti-spam and administration bot.
+//  Copyright (C) 2020 Nick Samson
+
+//  This program is free software: you can redistribute it and/or modify
+//  it under the terms of the GNU General Public License as published by
+//  the Free Software Foundation, either version 3 of the License, or
+//  (at your option) any later version.
+
+//  This program is distributed in the hope that it will be useful,
+//  but WITHOUT ANY WARRANTY; without even the implied warranty of
+//  MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+//  GNU General Public License for more details.
+
+//  You should have received a copy of the GNU General Public License
+//  along with this program.  If not, see <https://www.gnu.org/licenses/>.
+
+//! The pressure-based anti-spam engine. Every guild message is assigned a pressure score, which is
+//! stored in the guild database. Users whose total pressure over a window exceeds the configured
+//! maximum commit an offence, and are punished according to an escalation ladder based on how many
+//! offences they have committed recently.
+//!
+//! Each message is also fingerprinted, so copy-paste raids, where many accounts post the same or
+//! nearly the same text, can count as offences too. Duplicate detection is off until a guild sets
+//! `spam_duplicate_accounts`.
+//!
+//! Mass mentions are checked separately, per message and over a rolling window kept in memory.
+//! Both limits are off until a guild sets `mention_max_per_message` or `mention_max_per_window`,
+//! since they apply even when the pressure engine is disabled. Going over either limit is an
+//! offence which always mutes. While the pressure engine is enabled,
+//! mass mentions also add [MASS_MENTION_PRESSURE]; otherwise they are punished on their own.
+
+use crate::dispatch::Dispatch;
+use serenity::prelude::Context;
+use serenity::model::prelude::{Message, GuildId, UserId, RoleId, ChannelId};
+use crate::modules::{Module, config};
+use crate::modules::config::Kind;
+use crate::modules::punishment::{Action, Target};
+use crate::modules::roles::{user_tier, is_staff_role};
+use crate::db::Tier;
+use crate::modules::modlog::{record, AuditEntry};
+use crate::db::cache::get_cached_connection;
+use crate::db::{user_pressure, GuildConn};
+use crate::dispatch::KeyRetrievalError;
+use once_cell::sync::Lazy;
+use regex::Regex;
+use parking_lot::Mutex;
+use lru_cache::LruCache;
+use std::collections::VecDeque;
+use std::collections::hash_map::DefaultHasher;
+use std::hash::{Hash, Hasher};
+use std::borrow::Cow;
+use std::sync::Arc;
+use chrono::{DateTime, Utc, Duration};
+
+static SPAM_ENABLED_KEY: &str = "spam_enabled";
+static MAX_PRESSURE_KEY: &str = "spam_max_pressure";
+/// The config key for the window over which message pressure is summed.
+pub static PRESSURE_WINDOW_KEY: &str = "spam_pressure_window";
+static OFFENCE_WINDOW_KEY: &str = "spam_offence_window";
+static WARN_AT_KEY: &str = "spam_warn_at";
+static MUTE_AT_KEY: &str = "spam_mute_at";
+static KICK_AT_KEY: &str = "spam_kick_at";
+static BAN_AT_KEY: &str = "spam_ban_at";
+static MUTE_DURATION_KEY: &str = "spam_mute_duration";
+static MUTE_ROLE_KEY: &str = "mute_role";
+static DUPLICATE_ACCOUNTS_KEY: &str = "spam_duplicate_accounts";
+/// The config key for the window over which duplicate messages are matched.
+pub static DUPLICATE_WINDOW_KEY: &str = "spam_duplicate_window";
+static DUPLICATE_SCOPE_KEY: &str = "spam_duplicate_scope";
+static MENTION_MAX_PER_MESSAGE_KEY: &str = "mention_max_per_message";
+static MENTION_MAX_PER_WINDOW_KEY: &str = "mention_max_per_window";
+static MENTION_WINDOW_KEY: &str = "mention_window";
+
+const DEFAULT_ENABLED: bool = false;
+const DEFAULT_MAX_PRESSURE: i64 = 60;
+/// The default pressure window, in seconds.
+pub const DEFAULT_PRESSURE_WINDOW: i64 = 10;
+const DEFAULT_OFFENCE_WINDOW: i64 = 3600; // Seconds
+const DEFAULT_WARN_AT: u32 = 2;
+const DEFAULT_MUTE_AT: u32 = 3;
+const DEFAULT_KICK_AT: u32 = 5;
+const DEFAULT_BAN_AT: u32 = 0;
+const DEFAULT_MUTE_DURATION: i64 = 600; // Seconds
+// Off by default, since ordinary members often repeat short phrases like greetings.
+const DEFAULT_DUPLICATE_ACCOUNTS: u32 = 0;
+/// The default duplicate window, in seconds.
+pub const DEFAULT_DUPLICATE_WINDOW: i64 = 30;
+const DEFAULT_DUPLICATE_SCOPE: &str = "guild";
+const DEFAULT_MENTION_MAX_PER_MESSAGE: usize = 0;
+const DEFAULT_MENTION_MAX_PER_WINDOW: usize = 0;
+const DEFAULT_MENTION_WINDOW: i64 = 30; // Seconds
+
+/// The fewest letters a message may have, ignoring everything else, and still be fingerprinted.
+/// Shorter messages are too likely to match by accident.
+pub const MIN_FINGERPRINT_LETTERS: usize = 10;
+
+/// Pressure every message generates, regardless of content.
+pub const BASE_PRESSURE: i64 = 10;
+/// Pressure generated for every [LENGTH_PRESSURE_CHARS] characters in a message.
+pub const LENGTH_PRESSURE: i64 = 1;
+/// The number of characters needed to generate [LENGTH_PRESSURE].
+pub const LENGTH_PRESSURE_CHARS: usize = 160;
+/// Pressure generated for each line past the first.
+pub const LINE_PRESSURE: i64 = 1;
+/// Pressure generated for each user mention, role mention or @everyone/@here.
+pub const MENTION_PRESSURE: i64 = 3;
+/// Pressure generated for each attachment.
+pub const ATTACHMENT_PRESSURE: i64 = 8;
+/// Pressure generated for each link.
+pub const LINK_PRESSURE: i64 = 5;
+/// Pressure generated if the message has the same content as the user's previous message.
+pub const REPEAT_PRESSURE: i64 = 10;
+/// Extra pressure generated by a message which goes over a mention limit.
+pub const MASS_MENTION_PRESSURE: i64 = 60;
+
+// Holds a hash of the last message each user sent in each guild, so we can detect repeats.
+static LAST_MESSAGES: Lazy<Mutex<LruCache<(GuildId, UserId), u64>>> = Lazy::new(
+    || Mutex::new(LruCache::new(4096))
+);
+
+// Holds the recent mentions of each user in each guild, for the rolling mention limit.
+static RECENT_MENTIONS: Lazy<Mutex<LruCache<(GuildId, UserId), MentionWindow>>> = Lazy::new(
+    || Mutex::new(LruCache::new(4096))
+);
+
+static LINK_RE: Lazy<Regex> = Lazy::new(|| Regex::new(r"https?://\S+").unwrap());
+static MENTION_RE: Lazy<Regex> = Lazy::new(|| Regex::new(r"<[^<>\s]*\d+>").unwrap());
+static EVERYONE_RE: Lazy<Regex> = Lazy::new(|| Regex::new(r"@(?:everyone|here)\b").unwrap());
+
+/// Computes the pressure of a message from its content and metadata.
+pub fn message_pressure(content: &str, mentions: usize, attachments: usize, repeated: bool) -> i64 {
+    let length = (content.chars().count() / LENGTH_PRESSURE_CHARS) as i64 * LENGTH_PRESSURE;
+    let lines = content.lines().count().saturating_sub(1) as i64 * LINE_PRESSURE;
+    let links = LINK_RE.find_iter(content).count() as i64 * LINK_PRESSURE;
+    let repeat = if repeated { REPEAT_PRESSURE } else { 0 };
+
+    BASE_PRESSURE
+        + length
+        + lines
+        + mentions as i64 * MENTION_PRESSURE
+        + attachments as i64 * ATTACHMENT_PRESSURE
+        + links
+        + repeat
+}
+
+/// Fingerprints message content for duplicate detection. Only the letters count, ignoring case,
+/// and mentions and custom emoji are dropped, so copies with small edits still match.
+/// Returns None if the message has fewer than [MIN_FINGERPRINT_LETTERS] letters.
+pub fn fingerprint(content: &str) -> Option<u64> {
+    const FNV_OFFSET: u64 = 0xcbf2_9ce4_8422_2325;
+    const FNV_PRIME: u64 = 0x0100_0000_01b3;
+
+    let stripped = MENTION_RE.replace_all(content, "");
+    let mut letters = 0;
+    let mut hash = FNV_OFFSET;
+    let mut buf = [0; 4];
+    for c in stripped.chars().filter(|c| c.is_alphabetic()).flat_map(char::to_lowercase) {
+        letters += 1;
+        for b in c.encode_utf8(&mut buf).bytes() {
+            hash = (hash ^ u64::from(b)).wrapping_mul(FNV_PRIME);
+        }
+    }
+
+    // Zero is stored for messages without a fingerprint.
+    Some(hash.max(1)).filter(|_| letters >= MIN_FINGERPRINT_LETTERS)
+}
+
+/// Counts the @everyone and @here pings in message content, whether or not the author was allowed
+/// to use them.
+pub fn count_everyone_attempts(content: &str) -> usize {
+    EVERYONE_RE.find_iter(content).count()
+}
+
+/// Counts every user and role mention in a message, along with every attempted @everyone/@here.
+pub fn count_mentions(msg: &Message) -> usize {
+    msg.mentions.len() + msg.mention_roles.len() + count_everyone_attempts(&msg.content)
+}
+
+/// The mentions a user has made recently, for enforcing the rolling mention limit.
+#[derive(Debug, Clone, Default)]
+pub struct MentionWindow {
+    entries: VecDeque<(DateTime<Utc>, usize)>,
+}
+
+impl MentionWindow {
+    /// Records a message's mentions, forgets anything older than the window, and returns the total
+    /// number of mentions left in the window.
+    pub fn add(&mut self, time: &DateTime<Utc>, mentions: usize, window: Duration) -> usize {
+        if mentions > 0 {
+            self.entries.push_back((*time, mentions));
+        }
+
+        let since = *time - window;
+        while matches!(self.entries.front(), Some((t, _)) if *t <= since) {
+            self.entries.pop_front();
+        }
+
+        self.entries.iter().map(|(_, n)| n).sum()
+    }
+}
+
+/// Checks the message against the guild's mention limits, returning the cause of the offence if it
+/// goes over one. Staff are exempt.
+fn check_mentions(disp: &Dispatch, ctx: &Context, conn: &GuildConn, guild: GuildId, msg: &Message, time: &DateTime<Utc>) -> super::hook::Result<Option<String>> {
+    let per_message = disp.get_config::<usize>(conn, MENTION_MAX_PER_MESSAGE_KEY)?;
+    let per_window = disp.get_config::<usize>(conn, MENTION_MAX_PER_WINDOW_KEY)?;
+    if per_message == 0 && per_window == 0 {
+        return Ok(None);
+    }
+
+    let roles = msg.member.as_ref().map_or(&[][..], |m| &m.roles[..]);
+    if user_tier(ctx, conn, guild, msg.author.id, roles)? >= Tier::Moderator {
+        return Ok(None);
+    }
+
+    let window = disp.get_config::<Duration>(conn, MENTION_WINDOW_KEY)?;
+
+    let mentions = count_mentions(msg);
+    let recent = {
+        let mut windows = RECENT_MENTIONS.lock();
+        let key = (guild, msg.author.id);
+        if windows.get_mut(&key).is_none() {
+            windows.insert(key, MentionWindow::default());
+        }
+        windows.get_mut(&key).unwrap().add(time, mentions, window)
+    };
+
+    let cause = if per_message > 0 && mentions > per_message {
+        Some(format!("Mentioning {} users or roles in one message", mentions))
+    } else if per_window > 0 && recent > per_window {
+        Some(format!("Mentioning {} users or roles in {} seconds", recent, window.num_seconds()))
+    } else {
+        None
+    };
+
+    if let Some(c) = &cause {
+        debug!("User {} went over the mention limits in guild {}: {}", msg.author.id, guild, c);
+    }
+    Ok(cause)
+}
+
+/// Records whether the message is the same as the previous message sent by the user in the guild.
+fn is_repeat(guild: GuildId, msg: &Message) -> bool {
+    let mut hasher = DefaultHasher::new();
+    msg.content.hash(&mut hasher);
+    let hash = hasher.finish();
+
+    let mut last = LAST_MESSAGES.lock();
+    let key = (guild, msg.author.id);
+    let repeated = !msg.content.is_empty() && matches!(last.get_mut(&key), Some(h) if *h == hash);
+    last.insert(key, hash);
+    repeated
+}
+
+/// Returns the number of accounts which have recently posted messages with the fingerprint, if there
+/// are enough of them to count as a raid.
+fn duplicate_senders(disp: &Dispatch, conn: &GuildConn, fingerprint: u64, channel: ChannelId, time: &DateTime<Utc>) -> Result<Option<u32>, KeyRetrievalError> {
+    let threshold = disp.get_config::<u32>(conn, DUPLICATE_ACCOUNTS_KEY)?;
+    if threshold == 0 {
+        return Ok(None);
+    }
+
+    let window = disp.get_config::<Duration>(conn, DUPLICATE_WINDOW_KEY)?;
+    let per_channel = disp.get_config::<String>(conn, DUPLICATE_SCOPE_KEY)? == "channel";
+
+    let senders = conn.count_duplicate_senders(
+        fingerprint,
+        &(*time - window),
+        Some(channel).filter(|_| per_channel)
+    )?;
+    Ok(Some(senders).filter(|n| *n >= threshold))
+}
+
+fn spam_enabled(disp: &Dispatch, conn: &GuildConn) -> Result<bool, KeyRetrievalError> {
+    disp.get_config(conn, SPAM_ENABLED_KEY)
+}
+
+/// Retrieves the guild's mute role, if one is set.
+pub fn mute_role(disp: &Dispatch, conn: &GuildConn) -> Result<Option<RoleId>, KeyRetrievalError> {
+    disp.get_optional_config(conn, MUTE_ROLE_KEY)
+}
+
+/// Checks whether the role is the mute role. Always false if the spam module isn't loaded.
+pub fn is_mute_role(disp: &Dispatch, conn: &GuildConn, role: RoleId) -> Result<bool, KeyRetrievalError> {
+    if disp.config_validator().check_key(MUTE_ROLE_KEY).is_err() {
+        return Ok(false);
+    }
+    Ok(mute_role(disp, conn)? == Some(role))
+}
+
+/// Checks that the mute role isn't a staff role.
+fn check_mute_role(_disp: &Dispatch, _ctx: &Context, conn: &GuildConn, s: &str) -> Result<(), Cow<'static, str>> {
+    match s.parse::<RoleId>().map(|r| is_staff_role(conn, r)) {
+        Ok(Ok(false)) => Ok(()),
+        Ok(Ok(true)) => Err("the mute role must not be a staff role".into()),
+        _ => Err("couldn't check whether the role is a staff role".into())
+    }
+}
+
+/// The escalation ladder for users who exceed their pressure threshold.
+/// Each step is tied to a number of offences within the offence window; a step set to 0 is disabled.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Escalation {
+    /// Offences needed before the user is warned.
+    pub warn_at: u32,
+    /// Offences needed before the user is muted.
+    pub mute_at: u32,
+    /// Offences needed before the user is kicked.
+    pub kick_at: u32,
+    /// Offences needed before the user is banned.
+    pub ban_at: u32,
+    /// The role used to mute users. Muting is skipped if there is none.
+    pub mute_role: Option<RoleId>,
+    /// How long users are muted for.
+    pub mute_duration: Duration,
+}
+
+impl Escalation {
+    /// Loads the escalation policy for the guild.
+    pub fn load(disp: &Dispatch, conn: &GuildConn) -> Result<Self, KeyRetrievalError> {
+        let step = |key: &str| disp.get_config::<u32>(conn, key);
+
+        let mute_role = mute_role(disp, conn)?;
+
+        Ok(Escalation {
+            warn_at: step(WARN_AT_KEY)?,
+            mute_at: step(MUTE_AT_KEY)?,
+            kick_at: step(KICK_AT_KEY)?,
+            ban_at: step(BAN_AT_KEY)?,
+            mute_role,
+            mute_duration: disp.get_config(conn, MUTE_DURATION_KEY)?,
+        })
+    }
+
+    /// Returns the actions to take against a user with the given number of recent offences,
+    /// including the current one. The offending message is always deleted.
+    pub fn actions_for(&self, offences: u32) -> Vec<Action> {
+        let reached = |at: u32| at > 0 && offences >= at;
+        let mut actions = vec![Action::Delete];
+
+        if reached(self.ban_at) {
+            actions.push(Action::Ban);
+        } else if reached(self.kick_at) {
+            actions.push(Action::Kick);
+        } else if let (true, Some(role)) = (reached(self.mute_at), self.mute_role) {
+            actions.push(Action::Mute { role, duration: self.mute_duration });
+        } else if reached(self.warn_at) || reached(self.mute_at) {
+            actions.push(Action::Warn);
+        }
+
+        actions
+    }
+
+    /// Like [actions_for][Escalation::actions_for], but mutes the user if the ladder would only
+    /// delete or warn. Used for offences which are never accidental, like mass mentions.
+    pub fn actions_with_mute(&self, offences: u32) -> Vec<Action> {
+        let mut actions = self.actions_for(offences);
+        let punished = actions.iter().any(|a| matches!(a, Action::Mute { .. } | Action::Kick | Action::Ban));
+        if let (false, Some(role)) = (punished, self.mute_role) {
+            actions.retain(|a| *a != Action::Warn);
+            actions.push(Action::Mute { role, duration: self.mute_duration });
+        }
+
+        actions
+    }
+}
+
+/// Records an offence by the user, unless they already committed one within the pressure window,
+/// so that a single burst of messages counts as one incident. Returns the number of offences within
+/// the offence window if a new one was recorded.
+fn record_offence(conn: &GuildConn, user: UserId, time: &DateTime<Utc>, pressure_window: Duration, offence_window: Duration) -> crate::db::Result<Option<u32>> {
+    if conn.count_offences(user, &(*time - pressure_window))? > 0 {
+        return Ok(None);
+    }
+
+    conn.add_offence(user, time)?;
+    conn.count_offences(user, &(*time - offence_window)).map(Some)
+}
+
+/// Records an offence by the author of the message and punishes them according to the escalation
+/// ladder, muting them at least if `always_mute` is set. Messages which are part of an incident
+/// that was already punished are only deleted.
+fn punish_offence(disp: &Dispatch, ctx: &Context, conn: &GuildConn, msg: &Message, time: &DateTime<Utc>, cause: &str, always_mute: bool) -> super::hook::Result<()> {
+    let target = Target::from_message(msg);
+    let pressure_window = disp.get_config::<Duration>(conn, PRESSURE_WINDOW_KEY)?;
+    let offence_window = disp.get_config::<Duration>(conn, OFFENCE_WINDOW_KEY)?;
+    let offences = match record_offence(conn, msg.author.id, time, pressure_window, offence_window)? {
+        Some(n) => n,
+        None => {
+            trace!("Message {} from {} is part of an incident which was already punished", msg.id, msg.author.id);
+            return Ok(Action::Delete.apply(ctx, conn, &target, cause)?);
+        }
+    };
+
+    let reason = format!("{} ({} offences in the last {} seconds).", cause, offences, offence_window);
+    let escalation = Escalation::load(disp, conn)?;
+    let actions = if always_mute {
+        escalation.actions_with_mute(offences)
+    } else {
+        escalation.actions_for(offences)
+    };
+
+    for action in actions {
+        action.apply(ctx, conn, &target, &reason)?;
+        if action != Action::Delete {
+            let entry = AuditEntry::automatic(ctx, action.to_string(), format!("<@{}>", msg.author.id))
+                .with_reason(&reason);
+            record(disp, ctx, conn, &entry);
+        }
+    }
+
+    Ok(())
+}
+
+fn pressure_hook(disp: &Dispatch, ctx: &Context, msg: &Message) -> super::hook::Result<()> {
+    let guild = match msg.guild_id {
+        Some(g) => g,
+        None => return Ok(())
+    };
+
+    let conn = get_cached_connection(guild)?;
+    let rconn = conn.as_ref().borrow();
+
+    if !spam_enabled(disp, &rconn)? {
+        return Ok(());
+    }
+
+    let time = msg.timestamp.with_timezone(&Utc);
+    let mass_mention = check_mentions(disp, ctx, &rconn, guild, msg, &time)?;
+    let pressure = message_pressure(
+        &msg.content,
+        count_mentions(msg),
+        msg.attachments.len(),
+        is_repeat(guild, msg),
+    ) + if mass_mention.is_some() { MASS_MENTION_PRESSURE } else { 0 };
+    let fingerprint = fingerprint(&msg.content);
+    trace!("Message {} from {} has pressure {} and fingerprint {:?}", msg.id, msg.author.id, pressure, fingerprint);
+    rconn.add_message(msg.author.id, msg.channel_id, msg.id, pressure, fingerprint, &time)?;
+
+    let max_pressure = disp.get_config::<i64>(&rconn, MAX_PRESSURE_KEY)?;
+    let window = disp.get_config::<Duration>(&rconn, PRESSURE_WINDOW_KEY)?;
+
+    let since = time - window;
+    let total = user_pressure(&since, msg.author.id, rconn.as_ref())?;
+    let over_pressure = total > max_pressure;
+    let duplicates = match fingerprint {
+        Some(f) => duplicate_senders(disp, &rconn, f, msg.channel_id, &time)?,
+        None => None
+    };
+
+    if over_pressure || duplicates.is_some() || mass_mention.is_some() {
+        if over_pressure {
+            debug!("User {} exceeded max pressure in guild {} ({} > {})", msg.author.id, guild, total, max_pressure);
+        }
+        if let Some(n) = duplicates {
+            debug!("Message {} from {} in guild {} matches messages from {} accounts", msg.id, msg.author.id, guild, n);
+        }
+
+        let cause = match &mass_mention {
+            Some(c) => c.as_str(),
+            None if over_pressure => "Spamming",
+            None => "Posting copied messages"
+        };
+        punish_offence(disp, ctx, &rconn, msg, &time, cause, mass_mention.is_some())?;
+    }
+
+    Ok(())
+}
+
+/// Enforces the mention limits on their own while the pressure engine is disabled.
+fn mention_hook(disp: &Dispatch, ctx: &Context, msg: &Message) -> super::hook::Result<()> {
+    let guild = match msg.guild_id {
+        Some(g) => g,
+        None => return Ok(())
+    };
+
+    let conn = get_cached_connection(guild)?;
+    let rconn = conn.as_ref().borrow();
+
+    // The pressure hook checks mentions itself.
+    if spam_enabled(disp, &rconn)? {
+        return Ok(());
+    }
+
+    let time = msg.timestamp.with_timezone(&Utc);
+    if let Some(cause) = check_mentions(disp, ctx, &rconn, guild, msg, &time)? {
+        punish_offence(disp, ctx, &rconn, msg, &time, &cause, true)?;
+    }
+
+    Ok(())
+}
+
+/// Creates the anti-spam module.
+pub fn spam_mod() -> Module {
+    Module::with_name("spam")
+        .with_config_value(config::Value::new(
+            SPAM_ENABLED_KEY,
+            "Whether or not Glimbot should track message pressure and act on spammers. Default is false.",
+            Kind::Bool,
+            Some(DEFAULT_ENABLED.to_string()),
+        ))
+        .with_config_value(config::Value::new(
+            MAX_PRESSURE_KEY,
+            "The amount of pressure a user may generate within the pressure window before being acted on.",
+            Kind::UINT,
+            Some(DEFAULT_MAX_PRESSURE.to_string()),
+        ).with_sensitivity(true))
+        .with_config_value(config::Value::new(
+            PRESSURE_WINDOW_KEY,
+            "How long message pressure is summed over, e.g. 10 or 30s.",
+            Kind::Duration,
+            Some(DEFAULT_PRESSURE_WINDOW.to_string()),
+        ).with_sensitivity(true))
+        .with_config_value(config::Value::new(
+            OFFENCE_WINDOW_KEY,
+            "How long offences are counted over for escalation, e.g. 3600 or 1h, up to 365d.",
+            Kind::Duration,
+            Some(DEFAULT_OFFENCE_WINDOW.to_string()),
+        ))
+        .with_config_value(config::Value::new(
+            WARN_AT_KEY,
+            "The number of offences after which a spammer is warned. 0 disables warnings.",
+            Kind::UINT,
+            Some(DEFAULT_WARN_AT.to_string()),
+        ))
+        .with_config_value(config::Value::new(
+            MUTE_AT_KEY,
+            "The number of offences after which a spammer is muted. 0 disables muting.",
+            Kind::UINT,
+            Some(DEFAULT_MUTE_AT.to_string()),
+        ))
+        .with_config_value(config::Value::new(
+            KICK_AT_KEY,
+            "The number of offences after which a spammer is kicked. 0 disables kicking.",
+            Kind::UINT,
+            Some(DEFAULT_KICK_AT.to_string()),
+        ))
+        .with_config_value(config::Value::new(
+            BAN_AT_KEY,
+            "The number of offences after which a spammer is banned. 0 disables banning.",
+            Kind::UINT,
+            Some(DEFAULT_BAN_AT.to_string()),
+        ))
+        .with_config_value(config::Value::new(
+            MUTE_DURATION_KEY,
+            "How long a spammer stays muted, e.g. 600, 10m or 1h, up to 365d.",
+            Kind::Duration,
+            Some(DEFAULT_MUTE_DURATION.to_string()),
+        ))
+        .with_config_value(config::Value::new(
+            MUTE_ROLE_KEY,
+            "The role given to muted users. Muting is skipped if this is not set.",
+            Kind::Role,
+            Option::<String>::None,
+        ).with_check(Arc::new(check_mute_role)))
+        .with_config_value(config::Value::new(
+            DUPLICATE_ACCOUNTS_KEY,
+            "The number of accounts which must post matching messages within the duplicate window before it counts as spam. 0, the default, disables duplicate detection. Ordinary members often repeat phrases like greetings, so use a high threshold, e.g. 8, ideally with the channel scope.",
+            Kind::UINT,
+            Some(DEFAULT_DUPLICATE_ACCOUNTS.to_string()),
+        ).with_sensitivity(true))
+        .with_config_value(config::Value::new(
+            DUPLICATE_WINDOW_KEY,
+            "How long matching messages are counted over, e.g. 30 or 1m.",
+            Kind::Duration,
+            Some(DEFAULT_DUPLICATE_WINDOW.to_string()),
+        ).with_sensitivity(true))
+        .with_config_value(config::Value::new(
+            DUPLICATE_SCOPE_KEY,
+            "Whether matching messages are counted across the whole guild or only within one channel. Either guild or channel.",
+            Kind::Enum(&["guild", "channel"]),
+            Some(DEFAULT_DUPLICATE_SCOPE.to_string()),
+        ))
+        .with_config_value(config::Value::new(
+            MENTION_MAX_PER_MESSAGE_KEY,
+            "The most users, roles and @everyone/@here a message may mention. 0, the default, disables the limit.",
+            Kind::UINT,
+            Some(DEFAULT_MENTION_MAX_PER_MESSAGE.to_string()),
+        ))
+        .with_config_value(config::Value::new(
+            MENTION_MAX_PER_WINDOW_KEY,
+            "The most users, roles and @everyone/@here a user may mention within the mention window. 0, the default, disables the limit.",
+            Kind::UINT,
+            Some(DEFAULT_MENTION_MAX_PER_WINDOW.to_string()),
+        ).with_sensitivity(true))
+        .with_config_value(config::Value::new(
+            MENTION_WINDOW_KEY,
+            "How long mentions are counted over, e.g. 30 or 1m.",
+            Kind::Duration,
+            Some(DEFAULT_MENTION_WINDOW.to_string()),
+        ).with_sensitivity(true))
+        .with_message_hook(pressure_hook)
+        .with_message_hook(mention_hook)
+        .with_dependency("roles")
+        .with_dependency("punishment")
+        .with_sensitivity(true)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::util::test_guild_conn;
+
+    #[test]
+    fn test_message_pressure() {
+        assert_eq!(message_pressure("hello", 0, 0, false), BASE_PRESSURE);
+        assert_eq!(message_pressure("a\nb\nc", 0, 0, false), BASE_PRESSURE + 2 * LINE_PRESSURE);
+        assert_eq!(message_pressure("hello", 2, 1, true),
+                   BASE_PRESSURE + 2 * MENTION_PRESSURE + ATTACHMENT_PRESSURE + REPEAT_PRESSURE);
+        assert_eq!(message_pressure("see https://example.com and http://a.b", 0, 0, false),
+                   BASE_PRESSURE + 2 * LINK_PRESSURE);
+
+        let long = "a".repeat(LENGTH_PRESSURE_CHARS * 3);
+        assert_eq!(message_pressure(&long, 0, 0, false), BASE_PRESSURE + 3 * LENGTH_PRESSURE);
+    }
+
+    #[test]
+    fn test_fingerprint() {
+        let raid = fingerprint("Join my server for FREE nitro!!! https://example.com/abc");
+        assert!(raid.is_some());
+        assert_eq!(fingerprint("join my server for free nitro https://example.com/abc <@1234>"), raid);
+        assert_eq!(fingerprint("<@!55> JOIN MY SERVER   for free nitro 123 https://example.com/abc"), raid);
+        assert_ne!(fingerprint("Join my server for FREE nitro!!! https://example.com/xyz"), raid);
+
+        assert_eq!(fingerprint("hi"), None);
+        assert_eq!(fingerprint("<:emoji:12345> 1234567890!!"), None);
+    }
+
+    #[test]
+    fn test_mention_window() {
+        assert_eq!(count_everyone_attempts("@everyone look @here"), 2);
+        assert_eq!(count_everyone_attempts("email me@heretic.org"), 0);
+
+        let start = Utc::now();
+        let window = Duration::seconds(30);
+        let mut w = MentionWindow::default();
+        assert_eq!(w.add(&start, 5, window), 5);
+        assert_eq!(w.add(&(start + Duration::seconds(10)), 0, window), 5);
+        assert_eq!(w.add(&(start + Duration::seconds(20)), 4, window), 9);
+        assert_eq!(w.add(&(start + Duration::seconds(30)), 1, window), 5);
+        assert_eq!(w.add(&(start + Duration::seconds(100)), 0, window), 0);
+    }
+
+    #[test]
+    fn test_escalation() {
+        let role = RoleId::from(1);
+        let duration = Duration::seconds(60);
+        let mut e = Escalation {
+            warn_at: 2,
+            mute_at: 3,
+            kick_at: 5,
+            ban_at: 0,
+            mute_role: Some(role),
+            mute_duration: duration,
+        };
+
+        assert_eq!(e.actions_for(1), vec![Action::Delete]);
+        assert_eq!(e.actions_for(2), vec![Action::Delete, Action::Warn]);
+        assert_eq!(e.actions_for(4), vec![Action::Delete, Action::Mute { role, duration }]);
+        assert_eq!(e.actions_for(100), vec![Action::Delete, Action::Kick]);
+
+        e.ban_at = 7;
+        assert_eq!(e.actions_for(7), vec![Action::Delete, Action::Ban]);
+
+        assert_eq!(e.actions_with_mute(1), vec![Action::Delete, Action::Mute { role, duration }]);
+        assert_eq!(e.actions_with_mute(2), vec![Action::Delete, Action::Mute { role, duration }]);
+        assert_eq!(e.actions_with_mute(7), vec![Action::Delete, Action::Ban]);
+
+        e.mute_role = None;
+        assert_eq!(e.actions_for(3), vec![Action::Delete, Action::Warn]);
+        assert_eq!(e.actions_with_mute(2), vec![Action::Delete, Action::Warn]);
+    }
+
+    #[test]
+    fn test_record_offence() {
+        let (_dir, gconn) = test_guild_conn();
+        let user = UserId::from(1);
+        let pressure_window = Duration::seconds(DEFAULT_PRESSURE_WINDOW);
+        let offence_window = Duration::seconds(DEFAULT_OFFENCE_WINDOW);
+        let escalation = Escalation {
+            warn_at: DEFAULT_WARN_AT,
+            mute_at: DEFAULT_MUTE_AT,
+            kick_at: DEFAULT_KICK_AT,
+            ban_at: DEFAULT_BAN_AT,
+            mute_role: Some(RoleId::from(1)),
+            mute_duration: Duration::seconds(DEFAULT_MUTE_DURATION),
+        };
+
+        // A burst of over-threshold messages is a single offence.
+        let start = Utc::now();
+        assert_eq!(record_offence(&gconn, user, &start, pressure_window, offence_window).unwrap(), Some(1));
+        for i in 1..10 {
+            let time = start + Duration::milliseconds(500 * i);
+            assert_eq!(record_offence(&gconn, user, &time, pressure_window, offence_window).unwrap(), None);
+        }
+        assert_eq!(gconn.count_offences(user, &(start - offence_window)).unwrap(), 1);
+        assert!(!escalation.actions_for(1).contains(&Action::Kick));
+
+        // Separate incidents still escalate.
+        let later = start + pressure_window + Duration::seconds(1);
+        assert_eq!(record_offence(&gconn, user, &later, pressure_window, offence_window).unwrap(), Some(2));
+        assert_eq!(record_offence(&gconn, UserId::from(2), &later, pressure_window, offence_window).unwrap(), Some(1));
+    }
+}
@@ -20,6 +20,15 @@ use std::borrow::Cow;
 use std::fmt::Display;
 use clap::App;
 use std::io::Cursor;
+use serenity::prelude::Context;
+use serenity::model::id::ChannelId;
+use serenity::utils::MessageBuilder;
+
+/// The most characters Discord allows in a message.
+pub const MAX_MESSAGE_LENGTH: usize = 2000;
+
+// Room left for the code block fences.
+const CODEBLOCK_OVERHEAD: usize = 8;
 
 /// Converts a string into a [Cow], unwrapping the result.
 /// # Panics
@@ -47,4 +56,97 @@ pub fn help_str(app: &App) -> String {
     let mut curs = Cursor::new(Vec::new());
     app.write_help(&mut curs).unwrap();
     String::from_utf8(curs.into_inner()).unwrap()
+}
+
+/// Splits text into pieces of at most `max` characters, breaking between lines where possible.
+pub fn split_message(text: &str, max: usize) -> Vec<String> {
+    let mut out = Vec::new();
+    let mut cur = String::new();
+    let mut cur_len = 0;
+    for line in text.lines() {
+        let mut chars: Vec<char> = line.chars().collect();
+        // Lines which don't fit in a message of their own are broken up.
+        while chars.len() > max {
+            let rest = chars.split_off(max);
+            if !cur.is_empty() {
+                out.push(std::mem::take(&mut cur));
+            }
+            out.push(chars.into_iter().collect());
+            chars = rest;
+            cur_len = 0;
+        }
+
+        let needed = if cur.is_empty() { chars.len() } else { cur_len + 1 + chars.len() };
+        if needed > max {
+            out.push(std::mem::take(&mut cur));
+            cur_len = 0;
+        }
+        if !cur.is_empty() {
+            cur.push('\n');
+            cur_len += 1;
+        }
+        cur_len += chars.len();
+        cur.extend(chars);
+    }
+
+    if !cur.is_empty() {
+        out.push(cur);
+    }
+    out
+}
+
+/// Sends text to a channel in code blocks, using as many messages as it takes.
+pub fn say_codeblocks(ctx: &Context, channel: ChannelId, text: &str) -> serenity::Result<()> {
+    for part in split_message(text, MAX_MESSAGE_LENGTH - CODEBLOCK_OVERHEAD) {
+        channel.say(ctx, MessageBuilder::new()
+            .push_codeblock_safe(part, None)
+            .build())?;
+    }
+    Ok(())
+}
+
+/// Creates a context which isn't connected to Discord, for tests of code which only needs one to
+/// be passed along. Any HTTP request made through it fails.
+#[cfg(test)]
+pub fn test_context() -> Context {
+    use std::sync::Arc;
+    use serenity::prelude::{RwLock, ShareMap};
+    use serenity::client::bridge::gateway::ShardMessenger;
+    use serenity::http::Http;
+
+    let (tx, _) = std::sync::mpsc::channel();
+    Context {
+        data: Arc::new(RwLock::new(ShareMap::custom())),
+        shard: ShardMessenger::new(tx),
+        shard_id: 0,
+        http: Arc::new(Http::default()),
+        cache: Default::default(),
+    }
+}
+
+/// Creates a fully migrated guild database in a temporary directory, for tests which need one. The
+/// directory is deleted when dropped, so it must outlive the connection.
+#[cfg(test)]
+pub fn test_guild_conn() -> (tempdir::TempDir, crate::db::GuildConn) {
+    use crate::db::{ensure_guild_db, init_guild_db, GuildConn};
+    use serenity::model::id::GuildId;
+
+    let dummy_dir = tempdir::TempDir::new("migrations").unwrap();
+    let id = GuildId::from(u64::MAX);
+    let mut dummy_conn = ensure_guild_db(dummy_dir.as_ref(), id).unwrap();
+    init_guild_db(&mut dummy_conn).unwrap();
+    (dummy_dir, GuildConn::new(id, dummy_conn))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_split_message() {
+        assert_eq!(split_message("ab\ncd\nef", 5), vec!["ab\ncd", "ef"]);
+        assert_eq!(split_message("abcdefg\nh", 3), vec!["abc", "def", "g\nh"]);
+        assert_eq!(split_message("ab\ncdefg", 3), vec!["ab", "cde", "fg"]);
+        assert!(split_message("", 3).is_empty());
+    }
 }
\ No newline at end of file